@@ -0,0 +1,597 @@
+//! Real-time audio engine
+//!
+//! The engine runs on its own thread, so that a slow frame of the editor never makes
+//! the audio stutter. It receives commands from the editor through a channel, plays
+//! the scheduled events against its own position, and renders small blocks ahead of
+//! the wall clock into an AudioSink. The SoundFont is loaded on that thread too, as a
+//! large one takes a while to read.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::audio::render::{load_sound_font, Synths};
+use crate::audio::schedule::{schedule, RenderTrack, SynthEvent, SynthEventKind, SynthPath};
+use crate::audio::sink::AudioSink;
+use crate::config::{ENGINE_BLOCK_FRAMES, ENGINE_LATENCY_SECONDS};
+use crate::smf::tempo_map::TempoMap;
+
+#[derive(Debug)]
+enum EngineCommand {
+    // the position is in seconds
+    Play(f64),
+    Stop,
+    Seek(f64),
+    SetTracks { tracks: Vec<RenderTrack>, tempo_map: TempoMap },
+    // (start, end) in seconds, or None to play straight through
+    SetLoop(Option<(f64, f64)>),
+    // plays the events of a count-in, which lasts the given seconds, then plays
+    // from the position
+    CountIn { second: f64, events: Vec<SynthEvent>, seconds: f64 },
+    // plays keys right away for a while, whether the song is playing or not
+    Audition { channel: u8, program: u8, keys: Vec<u8>, velocity: u8, seconds: f64 },
+    Shutdown,
+}
+
+pub struct AudioEngine {
+    commands: Sender<EngineCommand>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AudioEngine {
+    // when the SoundFont cannot be loaded, the engine plays nothing but still takes
+    // commands, so that the editor works the same without sound
+    pub fn start(sound_font_path: PathBuf, sink: Box<dyn AudioSink>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let synths = load_sound_font(&sound_font_path)
+                .and_then(|sound_font| Synths::new(&sound_font, sink.sample_rate()));
+
+            match synths {
+                Ok(synths) => EngineThread::new(synths, sink).run(receiver),
+                Err(e) => {
+                    println!("Playing without sound, {}: {}", sound_font_path.display(), e);
+                    while let Ok(command) = receiver.recv() {
+                        if let EngineCommand::Shutdown = command {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { commands, thread: Some(thread) }
+    }
+
+    pub fn play(&self, second: f64) {
+        self.send(EngineCommand::Play(second));
+    }
+
+    pub fn play_after_count_in(&self, second: f64, events: Vec<SynthEvent>, seconds: f64) {
+        self.send(EngineCommand::CountIn { second, events, seconds });
+    }
+
+    pub fn stop(&self) {
+        self.send(EngineCommand::Stop);
+    }
+
+    pub fn seek(&self, second: f64) {
+        self.send(EngineCommand::Seek(second));
+    }
+
+    // notes can be edited while playing, the engine picks up the changes on its next block
+    pub fn set_tracks(&self, tracks: Vec<RenderTrack>, tempo_map: TempoMap) {
+        self.send(EngineCommand::SetTracks { tracks, tempo_map });
+    }
+
+    pub fn set_loop(&self, loop_seconds: Option<(f64, f64)>) {
+        self.send(EngineCommand::SetLoop(loop_seconds));
+    }
+
+    // the keys that were auditioned on the channel and still sound are cut
+    pub fn audition(&self, channel: u8, program: u8, keys: Vec<u8>, velocity: u8, seconds: f64) {
+        self.send(EngineCommand::Audition { channel, program, keys, velocity, seconds });
+    }
+
+    fn send(&self, command: EngineCommand) {
+        if self.commands.send(command).is_err() {
+            println!("The audio engine has stopped");
+        }
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        let _ = self.commands.send(EngineCommand::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// The events of the song are set aside while the count-in plays
+struct CountIn {
+    song_events: Vec<SynthEvent>,
+    // where the song starts playing after the count-in
+    second: f64,
+    // the end of the count-in, in samples
+    end: usize,
+}
+
+struct EngineThread {
+    synths: Synths,
+    sink: Box<dyn AudioSink>,
+    sample_rate: f64,
+    events: Vec<SynthEvent>,
+    // index of the next event to play
+    next_event: usize,
+    // position in the song, in samples
+    position: usize,
+    playing: bool,
+    // (start, end) in samples
+    loop_samples: Option<(usize, usize)>,
+    count_in: Option<CountIn>,
+    // (path, channel, key) of the notes that received a note-on but no note-off yet
+    sounding: HashSet<(SynthPath, u8, u8)>,
+    // (channel, key, frames left) of the auditioned keys, which are not part of the song
+    // and play through the synthesizer of the tracks
+    auditions: Vec<(u8, u8, usize)>,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl EngineThread {
+    fn new(synths: Synths, sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            synths,
+            sample_rate: sink.sample_rate() as f64,
+            sink,
+            events: Vec::new(),
+            next_event: 0,
+            position: 0,
+            playing: false,
+            loop_samples: None,
+            count_in: None,
+            sounding: HashSet::new(),
+            auditions: Vec::new(),
+            left: vec![0.0; ENGINE_BLOCK_FRAMES],
+            right: vec![0.0; ENGINE_BLOCK_FRAMES],
+        }
+    }
+
+    fn run(mut self, receiver: Receiver<EngineCommand>) {
+        let block_duration = Duration::from_secs_f64(ENGINE_BLOCK_FRAMES as f64 / self.sample_rate);
+        let latency_frames = (ENGINE_LATENCY_SECONDS * self.sample_rate) as u64;
+
+        let started = Instant::now();
+        let mut frames_written: u64 = 0;
+
+        loop {
+            // the synthesizers always run, so that the release of the notes is heard
+            // after stopping, and blocks are rendered only slightly ahead of real time
+            let target_frames =
+                (started.elapsed().as_secs_f64() * self.sample_rate) as u64 + latency_frames;
+
+            if frames_written >= target_frames {
+                match receiver.recv_timeout(block_duration / 2) {
+                    Ok(command) => {
+                        if !self.handle_command(command) {
+                            return;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+                continue;
+            }
+
+            while let Ok(command) = receiver.try_recv() {
+                if !self.handle_command(command) {
+                    return;
+                }
+            }
+
+            self.render_block();
+            frames_written += ENGINE_BLOCK_FRAMES as u64;
+        }
+    }
+
+    // returns false when the engine must shut down
+    fn handle_command(&mut self, command: EngineCommand) -> bool {
+        match command {
+            EngineCommand::Play(second) => {
+                self.cancel_count_in();
+                self.locate(second);
+                self.playing = true;
+            }
+            EngineCommand::Stop => {
+                self.cancel_count_in();
+                self.playing = false;
+                self.release_all();
+            }
+            EngineCommand::Seek(second) => {
+                self.cancel_count_in();
+                self.release_all();
+                self.locate(second);
+            }
+            EngineCommand::SetTracks { tracks, tempo_map } => match self.count_in.as_mut() {
+                Some(count_in) => count_in.song_events = schedule(&tracks, &tempo_map),
+                None => {
+                    self.events = schedule(&tracks, &tempo_map);
+                    self.next_event = self.first_event_at(self.position);
+                    self.release_edited_notes();
+                    self.send_program_changes();
+                }
+            },
+            EngineCommand::CountIn { second, events, seconds } => {
+                self.cancel_count_in();
+                self.release_all();
+                let song_events = std::mem::replace(&mut self.events, events);
+                self.count_in = Some(CountIn { song_events, second, end: self.to_sample(seconds) });
+                self.locate(0.0);
+                self.playing = true;
+            }
+            EngineCommand::SetLoop(loop_seconds) => {
+                self.loop_samples = loop_seconds
+                    .map(|(start, end)| (self.to_sample(start), self.to_sample(end)))
+                    .filter(|(start, end)| end > start);
+            }
+            EngineCommand::Audition { channel, program, keys, velocity, seconds } => {
+                self.release_auditions(channel);
+                self.synths
+                    .send(SynthPath::Tracks, SynthEventKind::ProgramChange { channel, program });
+                let frames = self.to_sample(seconds);
+                for key in keys {
+                    self.synths
+                        .send(SynthPath::Tracks, SynthEventKind::NoteOn { channel, key, velocity });
+                    self.auditions.push((channel, key, frames));
+                }
+            }
+            EngineCommand::Shutdown => return false,
+        }
+        true
+    }
+
+    fn to_sample(&self, second: f64) -> usize {
+        (second.max(0.0) * self.sample_rate).round() as usize
+    }
+
+    fn first_event_at(&self, position: usize) -> usize {
+        let sample_rate = self.sample_rate;
+        self.events
+            .partition_point(|event| ((event.second * sample_rate).round() as usize) < position)
+    }
+
+    fn locate(&mut self, second: f64) {
+        self.position = self.to_sample(second);
+        self.next_event = self.first_event_at(self.position);
+        self.send_program_changes();
+    }
+
+    // the program changes are at the start of the song, so they would be skipped
+    // when playing from anywhere else
+    fn send_program_changes(&mut self) {
+        for event in self.events.iter() {
+            if let SynthEventKind::ProgramChange { .. } = event.kind {
+                self.synths.send(event.path, event.kind);
+            }
+        }
+    }
+
+    fn cancel_count_in(&mut self) {
+        if let Some(count_in) = self.count_in.take() {
+            self.events = count_in.song_events;
+        }
+    }
+
+    fn finish_count_in(&mut self) {
+        if let Some(count_in) = self.count_in.take() {
+            self.release_all();
+            self.events = count_in.song_events;
+            self.locate(count_in.second);
+        }
+    }
+
+    fn release_all(&mut self) {
+        for (path, channel, key) in self.sounding.drain() {
+            self.synths.note_off(path, channel, key);
+        }
+    }
+
+    fn release_auditions(&mut self, channel: u8) {
+        let synths = &mut self.synths;
+        self.auditions.retain(|(audition_channel, key, _)| {
+            if *audition_channel == channel {
+                synths.note_off(SynthPath::Tracks, channel, *key);
+            }
+            *audition_channel != channel
+        });
+    }
+
+    fn end_auditions(&mut self, frames: usize) {
+        let synths = &mut self.synths;
+        self.auditions.retain_mut(|(channel, key, frames_left)| {
+            *frames_left = frames_left.saturating_sub(frames);
+            if *frames_left == 0 {
+                synths.note_off(SynthPath::Tracks, *channel, *key);
+            }
+            *frames_left > 0
+        });
+    }
+
+    // after an edit, the notes that are sounding but no longer cover the current
+    // position are released, while the others keep ringing until their new note-off
+    fn release_edited_notes(&mut self) {
+        let mut still_on: HashSet<(SynthPath, u8, u8)> = HashSet::new();
+        for event in self.events[..self.next_event].iter() {
+            match event.kind {
+                SynthEventKind::NoteOn { channel, key, .. } => {
+                    still_on.insert((event.path, channel, key));
+                }
+                SynthEventKind::NoteOff { channel, key } => {
+                    still_on.remove(&(event.path, channel, key));
+                }
+                _ => {}
+            }
+        }
+
+        let released: Vec<(SynthPath, u8, u8)> =
+            self.sounding.iter().filter(|note| !still_on.contains(note)).cloned().collect();
+        for (path, channel, key) in released {
+            self.synths.note_off(path, channel, key);
+            self.sounding.remove(&(path, channel, key));
+        }
+    }
+
+    // the song starts at the end of the count-in, and playback wraps at the end of the
+    // loop unless it started after the loop, both in the middle of a block if needed
+    fn render_block(&mut self) {
+        let mut rendered = 0;
+
+        while rendered < ENGINE_BLOCK_FRAMES {
+            let count_in_end = self.count_in.as_ref().map(|count_in| count_in.end);
+            let loop_samples =
+                self.loop_samples.filter(|(_, end)| count_in_end.is_none() && self.position < *end);
+            let boundary =
+                count_in_end.or(loop_samples.map(|(_, end)| end)).filter(|_| self.playing);
+
+            let remaining = ENGINE_BLOCK_FRAMES - rendered;
+            let frames = match boundary {
+                Some(boundary) => remaining.min(boundary.saturating_sub(self.position)),
+                None => remaining,
+            };
+            self.render_frames(rendered, rendered + frames);
+            rendered += frames;
+
+            match (boundary, count_in_end, loop_samples) {
+                (Some(boundary), Some(_), _) if self.position >= boundary => {
+                    self.finish_count_in();
+                }
+                (Some(boundary), None, Some((start, _))) if self.position >= boundary => {
+                    self.release_all();
+                    self.position = start;
+                    self.next_event = self.first_event_at(start);
+                }
+                _ => {}
+            }
+        }
+
+        self.end_auditions(ENGINE_BLOCK_FRAMES);
+        self.sink.write(&self.left, &self.right);
+    }
+
+    // renders the frames from..to of the block, and plays the events that fall on them
+    fn render_frames(&mut self, from: usize, to: usize) {
+        let frames_end = self.position + to - from;
+        let mut rendered = from;
+
+        while self.playing && self.next_event < self.events.len() {
+            let event = self.events[self.next_event];
+            let sample = self.to_sample(event.second);
+            if sample >= frames_end {
+                break;
+            }
+
+            let offset = from + sample.saturating_sub(self.position);
+            if offset > rendered {
+                self.synths
+                    .render(&mut self.left[rendered..offset], &mut self.right[rendered..offset]);
+                rendered = offset;
+            }
+
+            match event.kind {
+                SynthEventKind::NoteOn { channel, key, .. } => {
+                    self.sounding.insert((event.path, channel, key));
+                }
+                SynthEventKind::NoteOff { channel, key } => {
+                    self.sounding.remove(&(event.path, channel, key));
+                }
+                _ => {}
+            }
+            self.synths.send(event.path, event.kind);
+            self.next_event += 1;
+        }
+
+        self.synths.render(&mut self.left[rendered..to], &mut self.right[rendered..to]);
+
+        if self.playing {
+            self.position = frames_end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::render::tests::sine_sound_font;
+    use crate::audio::sink::MemorySink;
+    use crate::note::midi_notes::{MidiNote, Pitch};
+    use crate::tick::{Tick, FIRST_TICK, PPQ};
+
+    const SAMPLE_RATE: u32 = 22050;
+    const A4: (SynthPath, u8, u8) = (SynthPath::Tracks, 0, 69);
+
+    // an engine that plays into memory the notes of a track, from beat to beat. The
+    // tests render its blocks themselves instead of following the wall clock
+    fn engine_thread(notes: &[(Tick, Tick)]) -> (EngineThread, MemorySink) {
+        let sink = MemorySink::new(SAMPLE_RATE);
+        let synths = Synths::new(&sine_sound_font(), SAMPLE_RATE).unwrap();
+        let mut thread = EngineThread::new(synths, Box::new(sink.clone()));
+
+        let notes = notes
+            .iter()
+            .map(|(start, end)| {
+                MidiNote::new(FIRST_TICK + start * PPQ, FIRST_TICK + end * PPQ, Pitch::new(69))
+            })
+            .collect();
+        let track = RenderTrack { notes, channel: 0, program: 0, path: SynthPath::Tracks };
+        thread.handle_command(EngineCommand::SetTracks {
+            tracks: vec![track],
+            tempo_map: TempoMap::default(),
+        });
+
+        (thread, sink)
+    }
+
+    // renders whole blocks for at least the given seconds, and returns their frames
+    fn render(thread: &mut EngineThread, seconds: f64) -> usize {
+        let blocks = (seconds * SAMPLE_RATE as f64 / ENGINE_BLOCK_FRAMES as f64).ceil() as usize;
+        for _ in 0..blocks {
+            thread.render_block();
+        }
+        blocks * ENGINE_BLOCK_FRAMES
+    }
+
+    fn second(beats: f64) -> f64 {
+        TempoMap::default().beats_to_seconds(beats)
+    }
+
+    fn sample(second: f64) -> usize {
+        (second * SAMPLE_RATE as f64).round() as usize
+    }
+
+    // the samples written so far
+    fn written(sink: &MemorySink) -> Vec<f32> {
+        sink.audio().lock().unwrap().left.clone()
+    }
+
+    fn loudest(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |max, sample| max.max(sample.abs()))
+    }
+
+    #[test]
+    fn plays_from_the_position_until_stopped() {
+        let (mut thread, sink) = engine_thread(&[(0, 8)]);
+
+        render(&mut thread, 0.1);
+        let before_play = written(&sink).len();
+        assert_eq!(thread.position, 0);
+
+        thread.handle_command(EngineCommand::Play(0.0));
+        let played = render(&mut thread, 0.3);
+        assert_eq!(thread.position, played);
+        assert!(thread.sounding.contains(&A4));
+
+        thread.handle_command(EngineCommand::Stop);
+        render(&mut thread, 0.3);
+        assert_eq!(thread.position, played);
+        assert!(thread.sounding.is_empty());
+
+        let samples = written(&sink);
+        assert_eq!(loudest(&samples[..before_play]), 0.0);
+        assert!(loudest(&samples[before_play..before_play + played]) > 0.01);
+        // the note is released when stopping, even though it had not ended
+        assert!(loudest(&samples[samples.len() - 2048..]) < 0.001);
+    }
+
+    #[test]
+    fn seeks_to_the_notes() {
+        let (mut thread, sink) = engine_thread(&[(8, 10)]);
+        let note_start = second(8.0);
+
+        thread.handle_command(EngineCommand::Play(0.0));
+        render(&mut thread, 0.2);
+        let before_seek = written(&sink).len();
+        assert!(thread.sounding.is_empty());
+
+        thread.handle_command(EngineCommand::Seek(note_start - 0.05));
+        assert_eq!(thread.position, sample(note_start - 0.05));
+        render(&mut thread, 0.2);
+        assert!(thread.sounding.contains(&A4));
+
+        let samples = written(&sink);
+        assert_eq!(loudest(&samples[..before_seek]), 0.0);
+        assert!(loudest(&samples[before_seek..]) > 0.01);
+    }
+
+    #[test]
+    fn wraps_around_the_loop_in_the_middle_of_a_block() {
+        // the note starts on the first beat of the loop
+        let (mut thread, sink) = engine_thread(&[(2, 3)]);
+        let (loop_start, loop_end) = (second(2.0), second(4.0));
+        thread.handle_command(EngineCommand::SetLoop(Some((loop_start, loop_end))));
+
+        let from = second(3.5);
+        thread.handle_command(EngineCommand::Play(from));
+        let before_end = render(&mut thread, 0.2);
+        assert_eq!(thread.position, sample(from) + before_end);
+        assert!(thread.sounding.is_empty());
+
+        let after_end = render(&mut thread, 0.2);
+        let past_end = sample(from) + before_end + after_end - sample(loop_end);
+        assert_eq!(thread.position, sample(loop_start) + past_end);
+        assert!(thread.sounding.contains(&A4));
+
+        let samples = written(&sink);
+        assert_eq!(loudest(&samples[..before_end]), 0.0);
+        assert!(loudest(&samples[samples.len() - past_end..]) > 0.01);
+    }
+
+    #[test]
+    fn hands_the_count_in_over_to_the_song() {
+        let (mut thread, sink) = engine_thread(&[(2, 3)]);
+        let click = (SynthPath::Click, 15, 76);
+        let events = vec![SynthEvent {
+            second: 0.0,
+            path: click.0,
+            kind: SynthEventKind::NoteOn { channel: click.1, key: click.2, velocity: 100 },
+        }];
+
+        // the song starts on its note once the count-in is over
+        let (song_start, count_in_seconds) = (second(2.0), 0.3);
+        thread.handle_command(EngineCommand::CountIn {
+            second: song_start,
+            events,
+            seconds: count_in_seconds,
+        });
+        let during_count_in = render(&mut thread, 0.2);
+        assert!(thread.count_in.is_some());
+        assert_eq!(thread.position, during_count_in);
+        assert_eq!(thread.sounding, HashSet::from([click]));
+
+        let after_count_in = render(&mut thread, 0.2);
+        let past_end = during_count_in + after_count_in - sample(count_in_seconds);
+        assert!(thread.count_in.is_none());
+        assert_eq!(thread.position, sample(song_start) + past_end);
+        assert_eq!(thread.sounding, HashSet::from([A4]));
+
+        let samples = written(&sink);
+        assert!(loudest(&samples[samples.len() - past_end..]) > 0.01);
+    }
+
+    #[test]
+    fn plays_nothing_without_a_sound_font() {
+        let path = std::env::temp_dir().join("midi_composer_engine_missing.sf2");
+        let sink = MemorySink::new(SAMPLE_RATE);
+        let engine = AudioEngine::start(path, Box::new(sink.clone()));
+
+        engine.play(0.0);
+        std::thread::sleep(Duration::from_millis(100));
+        engine.stop();
+        drop(engine);
+
+        assert!(written(&sink).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Metronome
+//!
+//! The click follows the meter track: the first beat of every bar is accented, and
+//! every beat can be subdivided. It is a track of its own, played by the audio engine
+//! through a synthesizer of its own so that it takes no channel from the tracks, and it
+//! is left out of the exported and rendered files unless asked for. The count-in is a
+//! few bars of click heard before playback starts, in the meter and the tempo of where
+//! it starts.
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::schedule::{RenderTrack, SynthEvent, SynthEventKind, SynthPath};
+use crate::config::{
+    METRONOME_CHANNEL, METRONOME_CLICK_SECONDS, METRONOME_KEYS, METRONOME_PROGRAM,
+    METRONOME_VELOCITIES,
+};
+use crate::note::midi_notes::{MidiNote, Pitch};
+use crate::smf::tempo_map::TempoMap;
+use crate::tick::{self, Tick};
+use crate::timeline::{MeterEvent, TimingInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetronomeSettings {
+    pub is_on: bool,
+    // from 0.0 to 1.0
+    pub volume: f32,
+    // clicks per beat
+    pub subdivision: u8,
+    pub count_in_bars: u8,
+    // whether the click goes into the exported midi file and the rendered audio
+    pub is_exported: bool,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self { is_on: false, volume: 0.8, subdivision: 1, count_in_bars: 0, is_exported: false }
+    }
+}
+
+impl MetronomeSettings {
+    // the key and the velocity of the click on a subdivision of a beat of a bar:
+    // 0 for the downbeat, 1 for the other beats and 2 for the subdivisions
+    fn click(&self, beat: Tick, subdivision: Tick) -> (u8, u8) {
+        let kind = match (beat, subdivision) {
+            (0, 0) => 0,
+            (_, 0) => 1,
+            _ => 2,
+        };
+        let velocity = (METRONOME_VELOCITIES[kind] as f32 * self.volume.clamp(0.0, 1.0)).round();
+        (METRONOME_KEYS[kind], velocity.max(1.0) as u8)
+    }
+
+    // the clicks of a bar, as (offset from the start of the bar, key, velocity)
+    fn bar_clicks(&self, meter: &MeterEvent) -> Vec<(Tick, u8, u8)> {
+        let subdivision = self.subdivision.max(1) as Tick;
+        let mut clicks = Vec::new();
+        for beat in 0..meter.numerator.max(1) as Tick {
+            for step in 0..subdivision {
+                let offset = beat * meter.beat_length() + step * meter.beat_length() / subdivision;
+                let (key, velocity) = self.click(beat, step);
+                clicks.push((offset, key, velocity));
+            }
+        }
+        clicks
+    }
+
+    // the clicks between two ticks, as notes on the metronome channel
+    pub fn clicks(&self, timing_info: &TimingInfo, from: Tick, to: Tick) -> Vec<MidiNote> {
+        let bar_length = timing_info.meter_at(from).bar_length();
+        let mut notes = Vec::new();
+
+        for (_, bar_tick) in timing_info.bar_lines(from - bar_length, to) {
+            let meter = timing_info.meter_at(bar_tick);
+            for (offset, key, velocity) in self.bar_clicks(&meter) {
+                let start = bar_tick + offset;
+                if start >= from && start < to {
+                    let end = start + meter.beat_length() / (2 * self.subdivision.max(1) as Tick);
+                    notes.push(MidiNote::new(start, end, Pitch::new(key)).with_velocity(velocity));
+                }
+            }
+        }
+
+        notes
+    }
+
+    pub fn render_track(&self, timing_info: &TimingInfo, from: Tick, to: Tick) -> RenderTrack {
+        RenderTrack {
+            notes: self.clicks(timing_info, from, to),
+            channel: METRONOME_CHANNEL,
+            program: METRONOME_PROGRAM,
+            path: SynthPath::Click,
+        }
+    }
+
+    // the events of the count-in before playing from position, in seconds from the
+    // start of the count-in, along with its length in seconds. None without count-in
+    pub fn count_in(
+        &self,
+        timing_info: &TimingInfo,
+        tempo_map: &TempoMap,
+        position: Tick,
+    ) -> Option<(Vec<SynthEvent>, f64)> {
+        if self.count_in_bars == 0 {
+            return None;
+        }
+
+        let meter = timing_info.meter_at(position);
+        let seconds_per_tick =
+            60.0 / tempo_map.tempo_at_beat(tick::to_song_beats(position)).bpm() / tick::PPQ as f64;
+        let bar_seconds = meter.bar_length() as f64 * seconds_per_tick;
+
+        let channel = METRONOME_CHANNEL;
+        let path = SynthPath::Click;
+        let mut events = vec![SynthEvent {
+            second: 0.0,
+            path,
+            kind: SynthEventKind::ProgramChange { channel, program: METRONOME_PROGRAM },
+        }];
+
+        for bar in 0..self.count_in_bars {
+            for (offset, key, velocity) in self.bar_clicks(&meter) {
+                let second = bar as f64 * bar_seconds + offset as f64 * seconds_per_tick;
+                events.push(SynthEvent {
+                    second,
+                    path,
+                    kind: SynthEventKind::NoteOn { channel, key, velocity },
+                });
+                events.push(SynthEvent {
+                    second: second + METRONOME_CLICK_SECONDS,
+                    path,
+                    kind: SynthEventKind::NoteOff { channel, key },
+                });
+            }
+        }
+
+        events.sort_by(|a, b| {
+            a.second.partial_cmp(&b.second).unwrap().then(a.kind.order().cmp(&b.kind.order()))
+        });
+
+        Some((events, self.count_in_bars as f64 * bar_seconds))
+    }
+}
@@ -0,0 +1,10 @@
+//! Audio
+//!
+//! Turns the notes of the tracks into sound through a SoundFont synthesizer, either
+//! offline into a file or in real time while editing.
+pub mod engine;
+pub mod metronome;
+pub mod render;
+pub mod schedule;
+pub mod sink;
+pub mod wav;
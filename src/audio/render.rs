@@ -0,0 +1,349 @@
+//! Offline rendering
+//!
+//! Bounces the tracks through a SoundFont into a buffer or a WAV file, without
+//! needing a sound card.
+
+use rustysynth::{SoundFont, SoundFontError, Synthesizer, SynthesizerError, SynthesizerSettings};
+
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::audio::schedule::{schedule, RenderTrack, SynthEventKind, SynthPath};
+use crate::audio::wav::write_wav_file;
+use crate::config::{RENDER_SAMPLE_RATE, RENDER_TAIL_SECONDS};
+use crate::smf::tempo_map::TempoMap;
+
+const PROGRAM_CHANGE: i32 = 0xC0;
+
+#[derive(Debug)]
+pub enum RenderError {
+    Io(std::io::Error),
+    SoundFont(SoundFontError),
+    Synthesizer(SynthesizerError),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Io(e) => write!(f, "could not access file: {}", e),
+            RenderError::SoundFont(e) => write!(f, "invalid SoundFont: {}", e),
+            RenderError::Synthesizer(e) => write!(f, "could not create synthesizer: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<std::io::Error> for RenderError {
+    fn from(e: std::io::Error) -> Self {
+        RenderError::Io(e)
+    }
+}
+
+impl From<SoundFontError> for RenderError {
+    fn from(e: SoundFontError) -> Self {
+        RenderError::SoundFont(e)
+    }
+}
+
+impl From<SynthesizerError> for RenderError {
+    fn from(e: SynthesizerError) -> Self {
+        RenderError::Synthesizer(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    // rendered after the last note-off so that the release of the notes is heard
+    pub tail_seconds: f32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self { sample_rate: RENDER_SAMPLE_RATE, tail_seconds: RENDER_TAIL_SECONDS }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedAudio {
+    pub sample_rate: u32,
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+pub fn load_sound_font(path: impl AsRef<Path>) -> Result<Arc<SoundFont>, RenderError> {
+    let mut file = File::open(path)?;
+    Ok(Arc::new(SoundFont::new(&mut file)?))
+}
+
+// The synthesizer of the tracks and the one of the click, mixed into the same buffers
+pub struct Synths {
+    tracks: Synthesizer,
+    click: Synthesizer,
+    click_left: Vec<f32>,
+    click_right: Vec<f32>,
+}
+
+impl Synths {
+    pub fn new(sound_font: &Arc<SoundFont>, sample_rate: u32) -> Result<Self, RenderError> {
+        let settings = SynthesizerSettings::new(sample_rate as i32);
+        Ok(Self {
+            tracks: Synthesizer::new(sound_font, &settings)?,
+            click: Synthesizer::new(sound_font, &settings)?,
+            click_left: Vec::new(),
+            click_right: Vec::new(),
+        })
+    }
+
+    pub fn send(&mut self, path: SynthPath, kind: SynthEventKind) {
+        let synthesizer = match path {
+            SynthPath::Tracks => &mut self.tracks,
+            SynthPath::Click => &mut self.click,
+        };
+        send_to_synthesizer(synthesizer, kind);
+    }
+
+    pub fn note_off(&mut self, path: SynthPath, channel: u8, key: u8) {
+        self.send(path, SynthEventKind::NoteOff { channel, key });
+    }
+
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        self.tracks.render(left, right);
+
+        let frames = left.len();
+        if self.click_left.len() < frames {
+            self.click_left.resize(frames, 0.0);
+            self.click_right.resize(frames, 0.0);
+        }
+        let (click_left, click_right) =
+            (&mut self.click_left[..frames], &mut self.click_right[..frames]);
+        self.click.render(click_left, click_right);
+
+        for (sample, click) in left.iter_mut().zip(click_left.iter()) {
+            *sample += click;
+        }
+        for (sample, click) in right.iter_mut().zip(click_right.iter()) {
+            *sample += click;
+        }
+    }
+}
+
+fn send_to_synthesizer(synthesizer: &mut Synthesizer, kind: SynthEventKind) {
+    match kind {
+        SynthEventKind::ProgramChange { channel, program } => {
+            synthesizer.process_midi_message(channel as i32, PROGRAM_CHANGE, program as i32, 0);
+        }
+        SynthEventKind::NoteOff { channel, key } => {
+            synthesizer.note_off(channel as i32, key as i32);
+        }
+        SynthEventKind::NoteOn { channel, key, velocity } => {
+            synthesizer.note_on(channel as i32, key as i32, velocity as i32);
+        }
+    }
+}
+
+pub fn render(
+    sound_font: &Arc<SoundFont>,
+    tracks: &[RenderTrack],
+    tempo_map: &TempoMap,
+    settings: RenderSettings,
+) -> Result<RenderedAudio, RenderError> {
+    let mut synths = Synths::new(sound_font, settings.sample_rate)?;
+
+    let events = schedule(tracks, tempo_map);
+
+    let to_sample = |second: f64| (second * settings.sample_rate as f64).round() as usize;
+    let last_second = events.last().map_or(0.0, |event| event.second);
+    let length = to_sample(last_second + settings.tail_seconds as f64);
+
+    let mut left = vec![0.0; length];
+    let mut right = vec![0.0; length];
+
+    // the synthesizers render up to each event, so that every event is sample accurate
+    let mut rendered = 0;
+    for event in events {
+        let sample = to_sample(event.second).min(length);
+        if sample > rendered {
+            synths.render(&mut left[rendered..sample], &mut right[rendered..sample]);
+            rendered = sample;
+        }
+        synths.send(event.path, event.kind);
+    }
+    synths.render(&mut left[rendered..], &mut right[rendered..]);
+
+    Ok(RenderedAudio { sample_rate: settings.sample_rate, left, right })
+}
+
+pub fn render_to_wav_file(
+    sound_font_path: impl AsRef<Path>,
+    wav_path: impl AsRef<Path>,
+    tracks: &[RenderTrack],
+    tempo_map: &TempoMap,
+    settings: RenderSettings,
+) -> Result<(), RenderError> {
+    let sound_font = load_sound_font(sound_font_path)?;
+    let audio = render(&sound_font, tracks, tempo_map, settings)?;
+    write_wav_file(wav_path, audio.sample_rate, &audio.left, &audio.right)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::note::midi_notes::{MidiNote, Pitch};
+    use crate::tick::{Tick, FIRST_TICK, PPQ};
+
+    // a sine of 441 Hz at 44100 Hz, looped, so that a held A4 sounds until its note-off
+    const PERIOD: usize = 100;
+    const PERIODS: usize = 44;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[kind.to_vec(), chunks.concat()].concat())
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes
+    }
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    fn dwords(dwords: &[u32]) -> Vec<u8> {
+        dwords.iter().flat_map(|dword| dword.to_le_bytes()).collect()
+    }
+
+    // the smallest SoundFont that plays: one preset, one instrument and one sample
+    pub(crate) fn sine_sound_font_data() -> Vec<u8> {
+        let length = PERIOD * PERIODS;
+        let mut samples: Vec<i16> = (0..length)
+            .map(|i| {
+                let phase = (i % PERIOD) as f64 / PERIOD as f64;
+                ((phase * std::f64::consts::TAU).sin() * 16000.0) as i16
+            })
+            .collect();
+        // the sample data ends with 46 zeros
+        samples.extend([0; 46]);
+        let samples: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+
+        let preset =
+            |preset_name, bag| [name(preset_name), words(&[0, 0, bag]), dwords(&[0, 0, 0])];
+        let instrument = |instrument_name, bag| [name(instrument_name), words(&[bag])].concat();
+        let sample_header = |sample_name, header: [u32; 5], pitch: u8, kind: u16| {
+            [name(sample_name), dwords(&header), vec![pitch, 0], words(&[0, kind])].concat()
+        };
+        // sample modes then sample id, and instrument, each zone closed by the next one
+        const SAMPLE_MODES: u16 = 54;
+        const SAMPLE_ID: u16 = 53;
+        const INSTRUMENT: u16 = 41;
+        let length = length as u32;
+
+        let info = list(b"INFO", &[chunk(b"ifil", &words(&[2, 1])), chunk(b"INAM", b"Sine\0\0")]);
+        let sdta = list(b"sdta", &[chunk(b"smpl", &samples)]);
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &[preset("Sine", 0).concat(), preset("EOP", 1).concat()].concat()),
+                chunk(b"pbag", &words(&[0, 0, 1, 0])),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &words(&[INSTRUMENT, 0, 0, 0])),
+                chunk(b"inst", &[instrument("Sine", 0), instrument("EOI", 1)].concat()),
+                chunk(b"ibag", &words(&[0, 0, 2, 0])),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &words(&[SAMPLE_MODES, 1, SAMPLE_ID, 0, 0, 0])),
+                chunk(
+                    b"shdr",
+                    &[
+                        sample_header("Sine", [0, length, PERIOD as u32, length, 44100], 69, 1),
+                        sample_header("EOS", [0; 5], 0, 0),
+                    ]
+                    .concat(),
+                ),
+            ],
+        );
+
+        chunk(b"RIFF", &[b"sfbk".to_vec(), info, sdta, pdta].concat())
+    }
+
+    pub(crate) fn sine_sound_font() -> Arc<SoundFont> {
+        Arc::new(SoundFont::new(&mut sine_sound_font_data().as_slice()).unwrap())
+    }
+
+    #[test]
+    fn renders_the_notes_and_the_tail() {
+        let sound_font = sine_sound_font();
+        let tempo_map = TempoMap::default();
+        let track = RenderTrack {
+            notes: vec![MidiNote::new(FIRST_TICK, FIRST_TICK + 2 * PPQ, Pitch::new(69))],
+            channel: 0,
+            program: 0,
+            path: SynthPath::Tracks,
+        };
+        let settings = RenderSettings { sample_rate: 22050, tail_seconds: 0.5 };
+
+        let audio = render(&sound_font, &[track], &tempo_map, settings).unwrap();
+
+        let seconds = tempo_map.beats_to_seconds(2.0) + 0.5;
+        let length = (seconds * 22050.0).round() as usize;
+        assert_eq!(audio.sample_rate, 22050);
+        assert_eq!(audio.left.len(), length);
+        assert_eq!(audio.right.len(), length);
+
+        // the note sounds from its start to its end
+        let note_end = (tempo_map.beats_to_seconds(2.0) * 22050.0) as usize;
+        let loudest = |samples: &[f32]| samples.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!(loudest(&audio.left[..note_end / 4]) > 0.01);
+        assert!(loudest(&audio.right[note_end * 3 / 4..note_end]) > 0.01);
+    }
+
+    #[test]
+    fn the_click_does_not_cut_a_track_on_its_channel() {
+        let tempo_map = TempoMap::default();
+        let note = |start: Tick, end: Tick| {
+            vec![MidiNote::new(FIRST_TICK + start, FIRST_TICK + end, Pitch::new(69))]
+        };
+        let tracks = [
+            RenderTrack {
+                notes: note(0, 4 * PPQ),
+                channel: 15,
+                program: 0,
+                path: SynthPath::Tracks,
+            },
+            // the same key on the same channel, which would end the note of the track
+            RenderTrack {
+                notes: note(PPQ, PPQ + PPQ / 4),
+                channel: 15,
+                program: 115,
+                path: SynthPath::Click,
+            },
+        ];
+        let settings = RenderSettings { sample_rate: 22050, tail_seconds: 0.5 };
+
+        let audio = render(&sine_sound_font(), &tracks, &tempo_map, settings).unwrap();
+
+        let sample = |beats: f64| (tempo_map.beats_to_seconds(beats) * 22050.0) as usize;
+        let loudest = |samples: &[f32]| samples.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!(loudest(&audio.left[sample(2.0)..sample(3.5)]) > 0.01);
+    }
+
+    #[test]
+    fn renders_the_tail_of_an_empty_song() {
+        let settings = RenderSettings { sample_rate: 22050, tail_seconds: 0.5 };
+        let audio = render(&sine_sound_font(), &[], &TempoMap::default(), settings).unwrap();
+
+        assert_eq!(audio.left.len(), 11025);
+        assert!(audio.left.iter().chain(audio.right.iter()).all(|sample| *sample == 0.0));
+    }
+}
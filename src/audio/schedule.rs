@@ -0,0 +1,112 @@
+//! Synthesizer events
+//!
+//! The notes of every track are flattened into a single list of note-on and note-off
+//! events, sorted by time in seconds, which is what a synthesizer consumes. The click of
+//! the metronome goes to a synthesizer of its own, so that the tracks keep all sixteen
+//! channels.
+
+use crate::note::midi_notes::MidiNote;
+use crate::smf::tempo_map::TempoMap;
+use crate::track::Track;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynthEventKind {
+    ProgramChange { channel: u8, program: u8 },
+    NoteOff { channel: u8, key: u8 },
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+}
+
+// The synthesizer that plays an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SynthPath {
+    Tracks,
+    Click,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthEvent {
+    pub second: f64,
+    pub path: SynthPath,
+    pub kind: SynthEventKind,
+}
+
+// What the synthesizer needs to know about a track
+#[derive(Debug, Clone)]
+pub struct RenderTrack {
+    pub notes: Vec<MidiNote>,
+    pub channel: u8,
+    pub program: u8,
+    pub path: SynthPath,
+}
+
+impl RenderTrack {
+    pub fn from_track(track: &Track) -> Self {
+        // the notes are split between the selected and the non-selected notes
+        let notes = track
+            .midi_notes
+            .notes
+            .iter()
+            .flatten()
+            .chain(track.selected.notes.notes.iter().flatten())
+            .cloned()
+            .collect();
+
+        Self {
+            notes,
+            channel: track.channel.min(15),
+            program: track.program.min(127),
+            path: SynthPath::Tracks,
+        }
+    }
+}
+
+impl SynthEventKind {
+    // on the same instant, a note-off must come before a note-on, otherwise two
+    // consecutive notes of the same pitch would cut each other
+    pub fn order(&self) -> u8 {
+        match self {
+            SynthEventKind::ProgramChange { .. } => 0,
+            SynthEventKind::NoteOff { .. } => 1,
+            SynthEventKind::NoteOn { .. } => 2,
+        }
+    }
+}
+
+pub fn schedule(tracks: &[RenderTrack], tempo_map: &TempoMap) -> Vec<SynthEvent> {
+    let mut events = Vec::new();
+
+    for track in tracks {
+        let channel = track.channel;
+        let path = track.path;
+
+        events.push(SynthEvent {
+            second: 0.0,
+            path,
+            kind: SynthEventKind::ProgramChange { channel, program: track.program },
+        });
+
+        for note in track.notes.iter() {
+            let key = note.pitch.get().min(127);
+            let (start, end) = note.to_seconds(tempo_map);
+            let start = start.max(0.0) as f64;
+            let end = (end as f64).max(start);
+
+            events.push(SynthEvent {
+                second: start,
+                path,
+                kind: SynthEventKind::NoteOn { channel, key, velocity: note.velocity.min(127) },
+            });
+            events.push(SynthEvent {
+                second: end,
+                path,
+                kind: SynthEventKind::NoteOff { channel, key },
+            });
+        }
+    }
+
+    events.sort_by(|a, b| {
+        a.second.partial_cmp(&b.second).unwrap().then(a.kind.order().cmp(&b.kind.order()))
+    });
+
+    events
+}
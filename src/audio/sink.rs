@@ -0,0 +1,190 @@
+//! Audio sinks
+//!
+//! Where the engine sends the audio it renders. The device sink plays it on the
+//! default output of the computer, while the null and memory sinks let the engine
+//! run on machines without a sound card.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Sample;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+use crate::audio::render::RenderedAudio;
+
+pub trait AudioSink: Send {
+    fn sample_rate(&self) -> u32;
+
+    // the two channels always have the same length
+    fn write(&mut self, left: &[f32], right: &[f32]);
+}
+
+// Discards everything
+#[derive(Debug, Clone, Copy)]
+pub struct NullSink {
+    pub sample_rate: u32,
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, _left: &[f32], _right: &[f32]) {}
+}
+
+// Keeps everything, ex: to inspect what the engine played
+#[derive(Debug, Clone)]
+pub struct MemorySink {
+    audio: Arc<Mutex<RenderedAudio>>,
+}
+
+impl MemorySink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            audio: Arc::new(Mutex::new(RenderedAudio {
+                sample_rate,
+                left: Vec::new(),
+                right: Vec::new(),
+            })),
+        }
+    }
+
+    // still readable once the sink has been moved into the engine
+    pub fn audio(&self) -> Arc<Mutex<RenderedAudio>> {
+        self.audio.clone()
+    }
+}
+
+impl AudioSink for MemorySink {
+    fn sample_rate(&self) -> u32 {
+        self.audio.lock().unwrap().sample_rate
+    }
+
+    fn write(&mut self, left: &[f32], right: &[f32]) {
+        let mut audio = self.audio.lock().unwrap();
+        audio.left.extend_from_slice(left);
+        audio.right.extend_from_slice(right);
+    }
+}
+
+#[derive(Debug)]
+pub enum SinkError {
+    NoDevice,
+    Device(String),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::NoDevice => write!(f, "no audio output device"),
+            SinkError::Device(e) => write!(f, "audio device error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+// Plays on the default output device. The cpal stream cannot always be moved between
+// threads, so it lives on a thread of its own until the sink is dropped, and is fed
+// through a queue of interleaved stereo samples, converted to the sample format of the
+// device as it plays them.
+pub struct DeviceSink {
+    sample_rate: u32,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    _shutdown: mpsc::Sender<()>,
+}
+
+impl DeviceSink {
+    pub fn open() -> Result<Self, SinkError> {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let (opened_sender, opened_receiver) = mpsc::channel();
+        let (shutdown_sender, shutdown_receiver) = mpsc::channel::<()>();
+
+        let stream_queue = queue.clone();
+        std::thread::spawn(move || {
+            let stream = match open_stream(stream_queue) {
+                Ok((stream, sample_rate)) => {
+                    let _ = opened_sender.send(Ok(sample_rate));
+                    stream
+                }
+                Err(e) => {
+                    let _ = opened_sender.send(Err(e));
+                    return;
+                }
+            };
+
+            // returns when the sink is dropped
+            let _ = shutdown_receiver.recv();
+            drop(stream);
+        });
+
+        let sample_rate = opened_receiver
+            .recv()
+            .map_err(|_| SinkError::Device("the audio thread stopped".to_string()))??;
+
+        Ok(Self { sample_rate, queue, _shutdown: shutdown_sender })
+    }
+}
+
+fn open_stream(queue: Arc<Mutex<VecDeque<f32>>>) -> Result<(cpal::Stream, u32), SinkError> {
+    let host = cpal::default_host();
+    let device = host.default_output_device().ok_or(SinkError::NoDevice)?;
+    let supported_config =
+        device.default_output_config().map_err(|e| SinkError::Device(e.to_string()))?;
+
+    let sample_format = supported_config.sample_format();
+    let config: cpal::StreamConfig = supported_config.into();
+
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, queue),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, queue),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, queue),
+    }?;
+
+    stream.play().map_err(|e| SinkError::Device(e.to_string()))?;
+
+    Ok((stream, config.sample_rate.0))
+}
+
+fn build_stream<T: Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream, SinkError> {
+    let channels = config.channels as usize;
+
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    // silence when the engine is late
+                    let left = queue.pop_front().unwrap_or(0.0);
+                    let right = queue.pop_front().unwrap_or(0.0);
+                    for (channel, sample) in frame.iter_mut().enumerate() {
+                        *sample = T::from(if channel % 2 == 0 { &left } else { &right });
+                    }
+                }
+            },
+            |e| println!("audio stream error: {}", e),
+        )
+        .map_err(|e| SinkError::Device(e.to_string()))
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, left: &[f32], right: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        for (l, r) in left.iter().zip(right.iter()) {
+            queue.push_back(*l);
+            queue.push_back(*r);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! WAV files
+//!
+//! Writes 16-bit stereo PCM, the most widely supported flavour of WAV.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const BITS_PER_SAMPLE: u16 = 16;
+const CHANNELS: u16 = 2;
+
+pub fn write_wav_file(
+    path: impl AsRef<Path>,
+    sample_rate: u32,
+    left: &[f32],
+    right: &[f32],
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_wav(&mut writer, sample_rate, left, right)?;
+    writer.flush()
+}
+
+// the channels are interleaved, and the samples are clamped to [-1, 1]
+pub fn write_wav(
+    writer: &mut impl Write,
+    sample_rate: u32,
+    left: &[f32],
+    right: &[f32],
+) -> std::io::Result<()> {
+    let frames = left.len().min(right.len()) as u32;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let data_size = frames * block_align as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+
+    for (l, r) in left.iter().zip(right.iter()) {
+        writer.write_all(&to_i16(*l).to_le_bytes())?;
+        writer.write_all(&to_i16(*r).to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
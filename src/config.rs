@@ -16,6 +16,194 @@ pub const INIT_GRID_SIZE: Size = Size::new(500.0, 300.0);
 pub const INIT_PITCH_POS: f32 = 27.0;
 pub const NOTE_MIN_SIZE: f32 = 0.015625; // 1/64th note
 
+// the grid starts at beat 1.0, which is where the first tick of a midi file lands
+pub const FIRST_BEAT: f32 = 1.0;
+pub const DEFAULT_VELOCITY: u8 = 100;
+pub const VELOCITY_LANE_HEIGHT: u16 = 60;
+pub const CHORD_LANE_HEIGHT: u16 = 24;
+pub const TRACK_HEADER_WIDTH: u16 = 180;
+pub const PIANO_WIDTH: u16 = 40;
+pub const RULER_HEIGHT: u16 = 40;
+
+// the channel that general midi reserves for drums (channel 10, counting from 1)
+pub const DRUM_CHANNEL: u8 = 9;
+
+pub static GENERAL_MIDI_PROGRAMS: [&'static str; 128] = [
+    "Acoustic Grand Piano",
+    "Bright Acoustic Piano",
+    "Electric Grand Piano",
+    "Honky-tonk Piano",
+    "Electric Piano 1",
+    "Electric Piano 2",
+    "Harpsichord",
+    "Clavinet",
+    "Celesta",
+    "Glockenspiel",
+    "Music Box",
+    "Vibraphone",
+    "Marimba",
+    "Xylophone",
+    "Tubular Bells",
+    "Dulcimer",
+    "Drawbar Organ",
+    "Percussive Organ",
+    "Rock Organ",
+    "Church Organ",
+    "Reed Organ",
+    "Accordion",
+    "Harmonica",
+    "Tango Accordion",
+    "Acoustic Guitar (nylon)",
+    "Acoustic Guitar (steel)",
+    "Electric Guitar (jazz)",
+    "Electric Guitar (clean)",
+    "Electric Guitar (muted)",
+    "Overdriven Guitar",
+    "Distortion Guitar",
+    "Guitar Harmonics",
+    "Acoustic Bass",
+    "Electric Bass (finger)",
+    "Electric Bass (pick)",
+    "Fretless Bass",
+    "Slap Bass 1",
+    "Slap Bass 2",
+    "Synth Bass 1",
+    "Synth Bass 2",
+    "Violin",
+    "Viola",
+    "Cello",
+    "Contrabass",
+    "Tremolo Strings",
+    "Pizzicato Strings",
+    "Orchestral Harp",
+    "Timpani",
+    "String Ensemble 1",
+    "String Ensemble 2",
+    "Synth Strings 1",
+    "Synth Strings 2",
+    "Choir Aahs",
+    "Voice Oohs",
+    "Synth Voice",
+    "Orchestra Hit",
+    "Trumpet",
+    "Trombone",
+    "Tuba",
+    "Muted Trumpet",
+    "French Horn",
+    "Brass Section",
+    "Synth Brass 1",
+    "Synth Brass 2",
+    "Soprano Sax",
+    "Alto Sax",
+    "Tenor Sax",
+    "Baritone Sax",
+    "Oboe",
+    "English Horn",
+    "Bassoon",
+    "Clarinet",
+    "Piccolo",
+    "Flute",
+    "Recorder",
+    "Pan Flute",
+    "Blown Bottle",
+    "Shakuhachi",
+    "Whistle",
+    "Ocarina",
+    "Lead 1 (square)",
+    "Lead 2 (sawtooth)",
+    "Lead 3 (calliope)",
+    "Lead 4 (chiff)",
+    "Lead 5 (charang)",
+    "Lead 6 (voice)",
+    "Lead 7 (fifths)",
+    "Lead 8 (bass + lead)",
+    "Pad 1 (new age)",
+    "Pad 2 (warm)",
+    "Pad 3 (polysynth)",
+    "Pad 4 (choir)",
+    "Pad 5 (bowed)",
+    "Pad 6 (metallic)",
+    "Pad 7 (halo)",
+    "Pad 8 (sweep)",
+    "FX 1 (rain)",
+    "FX 2 (soundtrack)",
+    "FX 3 (crystal)",
+    "FX 4 (atmosphere)",
+    "FX 5 (brightness)",
+    "FX 6 (goblins)",
+    "FX 7 (echoes)",
+    "FX 8 (sci-fi)",
+    "Sitar",
+    "Banjo",
+    "Shamisen",
+    "Koto",
+    "Kalimba",
+    "Bagpipe",
+    "Fiddle",
+    "Shanai",
+    "Tinkle Bell",
+    "Agogo",
+    "Steel Drums",
+    "Woodblock",
+    "Taiko Drum",
+    "Melodic Tom",
+    "Synth Drum",
+    "Reverse Cymbal",
+    "Guitar Fret Noise",
+    "Breath Noise",
+    "Seashore",
+    "Bird Tweet",
+    "Telephone Ring",
+    "Helicopter",
+    "Applause",
+    "Gunshot",
+];
+
+// pulses (ticks) per quarter note used when exporting midi files, until a midi file is
+// opened. It is the resolution of the editor, so that no note moves
+pub const EXPORT_PPQ: u16 = 960;
+// the resolutions that the export can be stepped through
+pub const EXPORT_PPQS: [u16; 5] = [96, 192, 240, 480, 960];
+// the midi file that is exported with Ctrl+E, until a project or a midi file is opened
+pub const EXPORT_FILE_NAME: &'static str = "export.mid";
+
+// the project file that is saved with Ctrl+S and opened with Ctrl+O, until a project or
+// a midi file is opened
+pub const PROJECT_FILE_NAME: &'static str = "project.json";
+
+// the scales saved by the user, shared by every project
+pub const CUSTOM_SCALES_FILE_NAME: &'static str = "scales.json";
+// the chord voicings saved by the user, shared by every project
+pub const VOICINGS_FILE_NAME: &'static str = "voicings.json";
+
+// time between two playhead updates during playback (~60 fps)
+pub const TRANSPORT_FRAME_MILLIS: u64 = 16;
+
+// offline rendering, with Ctrl+R, through the SoundFont given with --soundfont
+pub const RENDER_SAMPLE_RATE: u32 = 44100;
+pub const RENDER_TAIL_SECONDS: f32 = 2.0;
+pub const RENDER_FILE_NAME: &'static str = "render.wav";
+pub const DEFAULT_SOUNDFONT_FILE_NAME: &'static str = "soundfont.sf2";
+
+// the real-time engine renders blocks of this many frames, slightly ahead of the clock
+pub const ENGINE_BLOCK_FRAMES: usize = 256;
+pub const ENGINE_LATENCY_SECONDS: f64 = 0.05;
+// used when no output device is available
+pub const NULL_SINK_SAMPLE_RATE: u32 = 44100;
+// how long a note is heard when it is drawn, dragged to a new pitch or clicked on the piano
+pub const AUDITION_SECONDS: f64 = 0.4;
+
+// the metronome plays on channel 16 of a synthesizer of its own, or of the exported file
+// when no track uses it, and clicks with a woodblock: high on the first beat of a bar,
+// lower on the other beats, and lowest on the subdivisions
+pub const METRONOME_CHANNEL: u8 = 15;
+pub const METRONOME_PROGRAM: u8 = 115;
+pub const METRONOME_KEYS: [u8; 3] = [84, 79, 72];
+pub const METRONOME_VELOCITIES: [u8; 3] = [127, 100, 70];
+pub const METRONOME_CLICK_SECONDS: f64 = 0.05;
+// while playing, the clicks go on this many bars after the last note
+pub const METRONOME_EXTRA_BARS: u32 = 64;
+
 // when resizing many selected notes, unselect those that have a
 // length either much smaller or much larger than the length of
 // the clicked note
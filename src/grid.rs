@@ -8,6 +8,8 @@ use crate::config::{
 };
 use crate::note::scale::Scale;
 use crate::piano_theme::TrackTheme;
+use crate::tick::{self, Tick};
+use crate::timeline::{LoopRegion, TimingInfo};
 
 pub const IS_WHITE_KEY: [bool; 12] =
     [true, false, true, false, true, true, false, true, false, true, false, true];
@@ -50,6 +52,11 @@ impl Grid {
         }
     }
 
+    // the beat fraction is a power of two, which is always a whole number of ticks
+    pub fn beat_fraction_ticks(&self) -> Tick {
+        tick::from_beats(self.beat_fraction)
+    }
+
     pub fn to_track_axes(&self, point: Point, frame_size: &Size) -> Point {
         let region = self.visible_region(*frame_size);
 
@@ -101,6 +108,7 @@ impl Grid {
         &self,
         bounds: Rectangle,
         grid_cache: &Cache,
+        timing_info: &TimingInfo,
         theme: &TrackTheme,
     ) -> Geometry {
         let grid = grid_cache.draw(bounds.size(), |frame| {
@@ -114,6 +122,7 @@ impl Grid {
             let columns = region.columns();
             let (total_rows, total_columns) = (rows.clone().count(), columns.clone().count());
             let beat_linewidth = 2.0 / BEAT_SIZE;
+            let bar_linewidth = 2.0 * beat_linewidth;
             let note_linewidth = 2.0 / NOTE_SIZE;
             let color = Color::from_rgb8(255, 0, 0);
             // let color =
@@ -157,13 +166,8 @@ impl Grid {
                         ..Text::default()
                     };
 
-                    let note_name = NOTE_LABELS[note_index as usize % 12];
                     frame.fill_text(Text {
-                        content: format!(
-                            "{}{}",
-                            note_name,
-                            -2.0 + (note_index as f32 / 12 as f32).floor()
-                        ),
+                        content: self.scale.int_to_label(note_index),
                         ..note_label
                     });
                 }
@@ -185,13 +189,27 @@ impl Grid {
                         theme.grid_dark_column, // Color::from_rgba8(100, 74, 83, alpha),
                     );
                 }
+            }
+
+            // the bar lines follow the meter, and are labeled with the number of the bar
+            let first_tick = tick::from_beats(*columns.start() as f32);
+            let last_tick = tick::from_beats(*columns.end() as f32);
+            for (bar, bar_tick) in timing_info.bar_lines(first_tick, last_tick) {
+                let x = tick::to_beats(bar_tick);
+                let pos = Point::new(x - bar_linewidth / 2.0, *rows.start() as f32);
+
+                frame.fill_rectangle(
+                    pos,
+                    Size::new(bar_linewidth, total_rows as f32),
+                    theme.grid_bar_line,
+                );
 
                 let text_pos = Point::new(
-                    column as f32 + 0.07 / self.scaling.x,
+                    x + 0.07 / self.scaling.x,
                     region.y / NOTE_SIZE as f32 + text_size * 0.0 / NOTE_SIZE / self.scaling.x,
                 );
 
-                let beat_label = Text {
+                let bar_label = Text {
                     color: theme.text,
                     size: text_size,
                     position: text_pos,
@@ -200,7 +218,7 @@ impl Grid {
                     ..Text::default()
                 };
 
-                frame.fill_text(Text { content: format!("{:?}", column), ..beat_label });
+                frame.fill_text(Text { content: format!("{}", bar), ..bar_label });
             }
 
             // add off beat lines when the grid has a non-unit beat fraction
@@ -223,6 +241,33 @@ impl Grid {
         grid
     }
 
+    // a band over the whole height of the grid, fainter when looping is off
+    pub fn draw_loop_region(
+        &self,
+        bounds: Rectangle,
+        loop_region: Option<LoopRegion>,
+        is_looping: bool,
+        theme: &TrackTheme,
+    ) -> Geometry {
+        let mut frame = Frame::new(bounds.size());
+
+        if let Some(loop_region) = loop_region {
+            self.adjust_frame(&mut frame, &bounds.size());
+            let rows = self.visible_region(frame.size()).rows();
+
+            let color =
+                if is_looping { theme.loop_region } else { TrackTheme::xa(theme.loop_region, 0.4) };
+
+            frame.fill_rectangle(
+                Point::new(tick::to_beats(loop_region.start), *rows.start() as f32),
+                Size::new(tick::to_beats(loop_region.length()), rows.count() as f32),
+                color,
+            );
+        }
+
+        frame.into_geometry()
+    }
+
     // off beat
     pub fn sub_columns(&self, region: &Region) -> Vec<f32> {
         let period = self.beat_fraction * BEAT_SIZE;
@@ -289,6 +334,29 @@ impl Grid {
         }
     }
 
+    // horizontal position in pixels of a beat, for the widgets that share the time axis of the grid
+    pub fn beat_to_x(&self, beat: f32, width: f32) -> f32 {
+        (beat * BEAT_SIZE + self.translation.x) * self.scaling.x + width / 2.0
+    }
+
+    pub fn x_to_beat(&self, x: f32, width: f32) -> f32 {
+        ((x - width / 2.0) / self.scaling.x - self.translation.x) / BEAT_SIZE
+    }
+
+    pub fn tick_to_x(&self, tick: Tick, width: f32) -> f32 {
+        self.beat_to_x(tick::to_beats(tick), width)
+    }
+
+    // vertical position in pixels of the bottom of a row, for the widgets that share the
+    // pitch axis of the grid. The y axis is inverted, so higher rows are further up
+    pub fn row_to_y(&self, row: f32, height: f32) -> f32 {
+        (-row * NOTE_SIZE - self.translation.y) * self.scaling.y + height / 2.0
+    }
+
+    pub fn y_to_row(&self, y: f32, height: f32) -> f32 {
+        -((y - height / 2.0) / self.scaling.y + self.translation.y) / NOTE_SIZE
+    }
+
     pub fn project(&self, position: Point, size: Size) -> Point {
         let region = self.visible_region(size);
 
@@ -6,16 +6,21 @@ use iced::widget::{
     button,
     // column,
     container,
+    scrollable,
     text,
+    text_input,
     // checkbox,
     // horizontal_space, pick_list, row,
     // slider, text,
     Column,
+    Row,
+    Space,
 };
 use iced::window;
 
 use iced::alignment;
-use iced::{Application, Command, Length, Settings, Subscription, Vector};
+use iced::time::{self, Duration, Instant};
+use iced::{Alignment, Application, Command, Length, Settings, Subscription};
 use iced_native::Event;
 
 pub use iced_native;
@@ -26,18 +31,53 @@ pub use piano_theme::TrackTheme;
 pub mod track;
 use track::{TimingInfo, Track, TrackMessage};
 
+pub mod audio;
 pub mod grid;
+pub mod midi_input;
 pub use grid::Grid;
 
 pub mod note;
+pub mod project;
+pub mod ruler;
+pub mod smf;
+pub mod tick;
+pub mod timeline;
+pub mod transport;
 pub mod util;
+pub mod widgets;
 
 mod config;
 
-use crate::config::INIT_GRID_SIZE;
+use crate::audio::engine::AudioEngine;
+use crate::audio::metronome::MetronomeSettings;
+use crate::audio::render::{render_to_wav_file, RenderError, RenderSettings};
+use crate::audio::schedule::{RenderTrack, SynthEvent};
+use crate::audio::sink::{AudioSink, DeviceSink, NullSink};
+use crate::config::{
+    AUDITION_SECONDS, CUSTOM_SCALES_FILE_NAME, DEFAULT_SOUNDFONT_FILE_NAME, DEFAULT_VELOCITY,
+    DRUM_CHANNEL, EXPORT_FILE_NAME, EXPORT_PPQ, EXPORT_PPQS, INIT_GRID_SIZE, METRONOME_CHANNEL,
+    METRONOME_EXTRA_BARS, METRONOME_PROGRAM, NULL_SINK_SAMPLE_RATE, PIANO_WIDTH, PROJECT_FILE_NAME,
+    RENDER_FILE_NAME, RULER_HEIGHT, TRACK_HEADER_WIDTH, TRANSPORT_FRAME_MILLIS, VOICINGS_FILE_NAME,
+};
+use crate::midi_input::{
+    DeviceInput, MidiInputSource, RecordMode, RecordSettings, Recorder, ReplayInput,
+};
+use crate::note::chord_stamp::{
+    load_voicings, save_voicings, ChordStamp, SavedVoicing, Spread, StampChord,
+};
 use crate::note::midi_notes::{MidiNote, MidiNotes};
+use crate::note::quantize::QuantizeSettings;
+use crate::note::scale::{load_custom_scales, save_custom_scales, CustomScale, ScaleType};
+use crate::note::transpose::Interval;
+use crate::project::{ProjectFile, TrackFile, PROJECT_VERSION};
+use crate::ruler::Ruler;
+use crate::smf::tempo_map::{TempoMap, TickTiming};
+use crate::tick::{Tick, FIRST_TICK, PPQ};
+use crate::transport::{Transport, TransportMessage};
 use crate::util::{Action, ClipBoard, History, TrackId};
+use crate::widgets::{self, step_button};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 // TODO: OMGOMGOMGOMGOM
 //  I NEED TO SEND THE EVENTS TO THE TRACK'S UPDATE FUNCTION AND CHANGE THE TRACK'S STATE
@@ -54,7 +94,30 @@ use std::collections::HashMap;
 pub fn main() -> iced::Result {
     // env_logger::builder().format_timestamp(None).init();
 
+    // an optional midi or project file to open, an optional SoundFont to render with, and
+    // an optional midi input port to record from (by default the first port) or midi file
+    // to record as if it were played,
+    // ex: cargo run -- petit_biscuit_chords.mid --soundfont piano.sf2 --midi-input Keystation
+    // with --render, the file is rendered to a WAV file without opening the editor,
+    // ex: cargo run -- petit_biscuit_chords.mid --soundfont piano.sf2 --render out.wav
+    let flags = EditorFlags::from_args(std::env::args().skip(1));
+
+    if let Some(wav_path) = flags.render.clone() {
+        let (editor, _) = MidiEditor::new(flags);
+        match editor.render_audio_to(&wav_path) {
+            Ok(()) => {
+                println!("Rendered {}", wav_path.display());
+                return Ok(());
+            }
+            Err(e) => {
+                println!("Could not render {}: {}", wav_path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     MidiEditor::run(Settings {
+        flags,
         antialiasing: true,
         window: window::Settings {
             position: window::Position::Centered,
@@ -64,6 +127,35 @@ pub fn main() -> iced::Result {
     })
 }
 
+#[derive(Debug, Clone, Default)]
+struct EditorFlags {
+    file: Option<PathBuf>,
+    soundfont: Option<PathBuf>,
+    midi_input: Option<String>,
+    midi_replay: Option<PathBuf>,
+    render: Option<PathBuf>,
+}
+
+impl EditorFlags {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut flags = Self::default();
+        while let Some(arg) = args.next() {
+            if arg == "--soundfont" {
+                flags.soundfont = args.next().map(PathBuf::from);
+            } else if arg == "--midi-input" {
+                flags.midi_input = args.next();
+            } else if arg == "--midi-replay" {
+                flags.midi_replay = args.next().map(PathBuf::from);
+            } else if arg == "--render" {
+                flags.render = args.next().map(PathBuf::from);
+            } else {
+                flags.file = Some(PathBuf::from(arg));
+            }
+        }
+        flags
+    }
+}
+
 enum ActiveElement {
     Track(TrackId),
     None,
@@ -73,11 +165,49 @@ struct MidiEditor {
     history: History,
     tracks: HashMap<TrackId, Track>,
     track_order: Vec<TrackId>,
+    // tracks that were removed, or whose addition was undone, and that can come back with
+    // undo or redo along with their own history
+    removed_tracks: HashMap<TrackId, Track>,
+    // the name being typed for a track, applied when pressing enter
+    renaming: Option<(TrackId, String)>,
     debug_text: String,
     active_element: ActiveElement,
-    main_player_head: f32,
+    main_player_head: Tick,
+    transport: Transport,
     clipboard: ClipBoard,
-    _timein_info: TimingInfo,
+    timing_info: TimingInfo,
+    tempo_map: TempoMap,
+    soundfont: PathBuf,
+    // where Ctrl+S saves and Ctrl+O opens, the project that was opened or next to the
+    // midi file that was opened
+    project_path: PathBuf,
+    // where Ctrl+E exports, the midi file that was opened or next to the project that
+    // was opened
+    export_path: PathBuf,
+    // the resolution of the exported midi files, the one of the midi file that was opened
+    // or else EXPORT_PPQ
+    export_ppq: u16,
+    quantize_settings: QuantizeSettings,
+    metronome: MetronomeSettings,
+    // started the first time playback starts
+    audio_engine: Option<AudioEngine>,
+    // opened the first time recording starts, from the port or the file given as flags
+    midi_input: Option<Box<dyn MidiInputSource>>,
+    midi_input_port: Option<String>,
+    midi_replay: Option<PathBuf>,
+    // the take being recorded
+    recorder: Option<Recorder>,
+    record_settings: RecordSettings,
+    // whether the notes are heard while editing them
+    is_auditioning: bool,
+    // the scales saved by the user, offered by the scale picker after the presets
+    custom_scales: Vec<CustomScale>,
+    // whether the notes follow the scale picker degree by degree, or stay where they are
+    remaps_notes: bool,
+    // the chord that clicks write in the tracks, while chord stamping is on
+    chord_stamp: ChordStamp,
+    voicings: Vec<SavedVoicing>,
+    stamps_chords: bool,
     _selection: Selected,
 }
 
@@ -97,12 +227,34 @@ impl Default for MidiEditor {
         Self {
             history: History::default(),
             tracks, // vec![Track::new(0), Track::new(1)],
-            main_player_head: 3.0,
+            main_player_head: 3 * PPQ,
+            transport: Transport::new(3 * PPQ),
             track_order: vec![0], //vec![0, 1],
+            removed_tracks: HashMap::new(),
+            renaming: None,
             debug_text: "debug".to_string(),
             active_element: ActiveElement::Track(0),
             clipboard: ClipBoard::None,
-            _timein_info: TimingInfo::default(),
+            timing_info: TimingInfo::default(),
+            tempo_map: TempoMap::default(),
+            soundfont: PathBuf::from(DEFAULT_SOUNDFONT_FILE_NAME),
+            project_path: PathBuf::from(PROJECT_FILE_NAME),
+            export_path: PathBuf::from(EXPORT_FILE_NAME),
+            export_ppq: EXPORT_PPQ,
+            quantize_settings: QuantizeSettings::default(),
+            metronome: MetronomeSettings::default(),
+            audio_engine: None,
+            midi_input: None,
+            midi_input_port: None,
+            midi_replay: None,
+            recorder: None,
+            record_settings: RecordSettings::default(),
+            is_auditioning: true,
+            custom_scales: Vec::new(),
+            remaps_notes: false,
+            chord_stamp: ChordStamp::default(),
+            voicings: Vec::new(),
+            stamps_chords: false,
             _selection: Selected { _track_number: 0, _note_number: 0 },
         }
     }
@@ -121,10 +273,1243 @@ struct Selected {
 enum EditorMessage {
     Track(TrackId, TrackMessage),
     EventOccurred(iced_native::Event),
+    Transport(TransportMessage),
+    Quantize,
+    QuantizeSettingsChanged(QuantizeSettings),
+    MetronomeSettingsChanged(MetronomeSettings),
+    // starts recording into the armed tracks, or ends the take
+    ToggleRecord,
+    RecordSettingsChanged(RecordSettings),
+    ToggleAudition,
+    // saves the pitches of the selected notes of the active track as a custom scale
+    SaveCustomScale,
+    ToggleRemapNotes,
+    ChordStampChanged(ChordStamp),
+    SaveVoicing,
+    ToggleChordStamp,
+    AddTrack,
+    RemoveTrack(TrackId),
+    DuplicateTrack(TrackId),
+    // moves a track to an index of the track order
+    MoveTrack(TrackId, usize),
+    SelectTrack(TrackId),
+    TrackNameEdited(TrackId, String),
+    RenameTrack(TrackId),
+    ExportMidiFile,
+    SetExportPpq(u16),
+    RenderAudio,
+    SaveProject,
+    OpenProject,
+    // the tempo and the meter from the bar of the playhead on
+    SetTempo(f32),
+    SetMeter(u8, u8),
+    RemoveTimingChanges,
+    // loops over the bars of the selected notes of the active track
+    LoopSelection,
     ShowDebug(String),
 }
 
 impl MidiEditor {
+    // replaces the current tracks with the tracks of a midi file
+    fn open_midi_file(&mut self, path: &PathBuf) {
+        let imported_song = match smf::import::import_file(path) {
+            Ok(imported_song) => imported_song,
+            Err(e) => {
+                println!("Could not open {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        if imported_song.tracks.is_empty() {
+            println!("No notes found in {}", path.display());
+            return;
+        }
+
+        self.tracks.clear();
+        self.track_order.clear();
+
+        for (track_id, imported_track) in imported_song.tracks.into_iter().enumerate() {
+            let track_id = track_id as TrackId;
+            self.tracks.insert(track_id, imported_track.into_track(track_id));
+            self.track_order.push(track_id);
+        }
+
+        let first_track_id = self.track_order[0];
+        if let Some(track) = self.tracks.get_mut(&first_track_id) {
+            track.is_active = true;
+        }
+
+        self.active_element = ActiveElement::Track(first_track_id);
+        self.set_timing_info(TimingInfo::from_tempo_map(&imported_song.tempo_map));
+        self.sync_loop_regions();
+        self.sync_chord_stamps();
+        self.history = History::default();
+        self.removed_tracks.clear();
+        self.renaming = None;
+        self.project_path = path.with_extension("json");
+        self.export_path = path.clone();
+        self.export_ppq = match imported_song.tempo_map.timing {
+            TickTiming::Metrical { ticks_per_beat } => ticks_per_beat as u16,
+            TickTiming::Timecode { .. } => EXPORT_PPQ,
+        };
+    }
+
+    fn to_project(&self) -> ProjectFile {
+        let active_track = match self.active_element {
+            ActiveElement::Track(track_id) => Some(track_id),
+            ActiveElement::None => None,
+        };
+
+        ProjectFile {
+            version: PROJECT_VERSION,
+            timing_info: self.timing_info.clone(),
+            main_player_head: self.main_player_head,
+            loop_region: self.transport.loop_region,
+            is_looping: self.transport.is_looping,
+            metronome: self.metronome,
+            active_track,
+            track_order: self.track_order.clone(),
+            tracks: self
+                .track_order
+                .iter()
+                .filter_map(|track_id| self.tracks.get(track_id))
+                .map(TrackFile::from_track)
+                .collect(),
+        }
+    }
+
+    fn save_project(&mut self) {
+        let path = &self.project_path;
+        match self.to_project().save(path) {
+            Ok(()) => {
+                println!("Saved {}", path.display());
+                self.history.mark_saved();
+            }
+            Err(e) => println!("Could not save {}: {}", path.display(), e),
+        }
+    }
+
+    // replaces the whole editor state with the content of a project file
+    fn open_project(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let project = match ProjectFile::load(path) {
+            Ok(project) => project,
+            Err(e) => {
+                println!("Could not open {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        if project.tracks.is_empty() {
+            println!("No tracks found in {}", path.display());
+            return;
+        }
+
+        self.tracks = project
+            .tracks
+            .into_iter()
+            .map(|track_file| {
+                (track_file.track_id, track_file.into_track(project.timing_info.clone()))
+            })
+            .collect();
+
+        // the order only keeps the tracks that exist, and tracks missing
+        // from the order are appended at the end
+        let mut track_order: Vec<TrackId> = project
+            .track_order
+            .into_iter()
+            .filter(|track_id| self.tracks.contains_key(track_id))
+            .collect();
+        let mut unordered: Vec<TrackId> = self
+            .tracks
+            .keys()
+            .filter(|track_id| !track_order.contains(track_id))
+            .cloned()
+            .collect();
+        unordered.sort();
+        track_order.extend(unordered);
+        self.track_order = track_order;
+
+        let active_track = project
+            .active_track
+            .filter(|track_id| self.tracks.contains_key(track_id))
+            .unwrap_or(self.track_order[0]);
+        if let Some(track) = self.tracks.get_mut(&active_track) {
+            track.is_active = true;
+        }
+        self.active_element = ActiveElement::Track(active_track);
+
+        self.set_timing_info(project.timing_info);
+        self.transport = Transport::new(project.main_player_head);
+        self.transport.loop_region = project.loop_region;
+        self.transport.is_looping = project.is_looping && project.loop_region.is_some();
+        self.metronome = project.metronome;
+        self.sync_player_heads();
+        self.sync_loop_regions();
+        self.sync_chord_stamps();
+        self.history = History::default();
+        self.removed_tracks.clear();
+        self.renaming = None;
+        self.project_path = path.to_path_buf();
+        self.export_path = path.with_extension("mid");
+        self.export_ppq = EXPORT_PPQ;
+    }
+
+    // opening again would lose the actions done since the project was saved
+    fn reopen_project(&mut self) {
+        if self.history.has_unsaved_actions() {
+            println!(
+                "Save before opening {}, the changes would be lost",
+                self.project_path.display()
+            );
+            return;
+        }
+        self.open_project(self.project_path.clone());
+    }
+
+    fn active_track_id(&self) -> Option<TrackId> {
+        match self.active_element {
+            ActiveElement::Track(track_id) => Some(track_id),
+            ActiveElement::None => None,
+        }
+    }
+
+    fn set_active_track(&mut self, track_id: TrackId) {
+        for (id, track) in self.tracks.iter_mut() {
+            track.is_active = *id == track_id;
+        }
+        self.active_element = ActiveElement::Track(track_id);
+    }
+
+    // ids are never reused, not even the ones of removed tracks, since the history
+    // refers to tracks by id
+    fn new_track_id(&self) -> TrackId {
+        self.tracks.keys().chain(self.removed_tracks.keys()).max().map_or(0, |id| id + 1)
+    }
+
+    // puts a removed track back at index in the track order
+    fn restore_track(&mut self, track_id: TrackId, index: usize) {
+        if let Some(mut track) = self.removed_tracks.remove(&track_id) {
+            track.set_timing_info(self.timing_info.clone());
+            track.set_loop_region(self.transport.loop_region, self.transport.is_looping);
+            track.set_player_head(self.main_player_head);
+            track.chord_stamp =
+                if self.stamps_chords { Some(self.chord_stamp.clone()) } else { None };
+            self.tracks.insert(track_id, track);
+            self.track_order.insert(index.min(self.track_order.len()), track_id);
+            self.set_active_track(track_id);
+        } else {
+            println!("Called non-existent removed track id: {}", track_id);
+        }
+    }
+
+    // sets a track aside so that it can be restored, and returns where it was in the order
+    fn set_track_aside(&mut self, track_id: TrackId) -> Option<usize> {
+        let index = self.track_order.iter().position(|id| *id == track_id)?;
+        let mut track = self.tracks.remove(&track_id)?;
+        track.is_active = false;
+        self.track_order.remove(index);
+        self.removed_tracks.insert(track_id, track);
+
+        if self.active_track_id() == Some(track_id) {
+            match self.track_order.get(index.min(self.track_order.len().saturating_sub(1))) {
+                Some(&neighbour) => self.set_active_track(neighbour),
+                None => self.active_element = ActiveElement::None,
+            }
+        }
+
+        Some(index)
+    }
+
+    fn add_track(&mut self) {
+        let track_id = self.new_track_id();
+        let mut track = Track::new(track_id);
+        track.meta.name = format!("Track {}", track_id + 1);
+
+        let index = self.track_order.len();
+        self.removed_tracks.insert(track_id, track);
+        self.restore_track(track_id, index);
+        self.history.add_action(Action::AddTrack { track_id, index });
+    }
+
+    // the copy comes right after the original, with the same notes and settings
+    fn duplicate_track(&mut self, track_id: TrackId) {
+        let (original, index) = match (
+            self.tracks.get(&track_id),
+            self.track_order.iter().position(|id| *id == track_id),
+        ) {
+            (Some(original), Some(index)) => (original, index),
+            _ => {
+                println!("Called non-existent track id: {}", track_id);
+                return;
+            }
+        };
+
+        let mut track_file = TrackFile::from_track(original);
+        let new_track_id = self.new_track_id();
+        track_file.track_id = new_track_id;
+        track_file.meta.name = format!("{} copy", track_file.meta.name);
+
+        let index = index + 1;
+        self.removed_tracks.insert(new_track_id, track_file.into_track(self.timing_info.clone()));
+        self.restore_track(new_track_id, index);
+        self.history.add_action(Action::AddTrack { track_id: new_track_id, index });
+    }
+
+    fn remove_track(&mut self, track_id: TrackId) {
+        if self.track_order.len() <= 1 {
+            println!("Cannot remove the last track");
+            return;
+        }
+
+        if let Some(index) = self.set_track_aside(track_id) {
+            self.history.add_action(Action::RemoveTrack { track_id, index });
+        } else {
+            println!("Called non-existent track id: {}", track_id);
+        }
+    }
+
+    fn rename_track(&mut self, track_id: TrackId, name: String) {
+        if let Some(track) = self.tracks.get_mut(&track_id) {
+            if track.meta.name != name {
+                let previous_name = std::mem::replace(&mut track.meta.name, name.clone());
+                self.history.add_action(Action::RenameTrack { track_id, previous_name, name });
+            }
+        } else {
+            println!("Called non-existent track id: {}", track_id);
+        }
+    }
+
+    fn move_track(&mut self, track_id: TrackId, to: usize) {
+        if let Some(from) = self.track_order.iter().position(|id| *id == track_id) {
+            let to = to.min(self.track_order.len() - 1);
+            if from != to {
+                self.track_order.remove(from);
+                self.track_order.insert(to, track_id);
+                self.history.add_action(Action::MoveTrack { track_id, from, to });
+            }
+        } else {
+            println!("Called non-existent track id: {}", track_id);
+        }
+    }
+
+    fn set_track_name(&mut self, track_id: TrackId, name: String) {
+        if let Some(track) = self.tracks.get_mut(&track_id) {
+            track.meta.name = name;
+        }
+    }
+
+    fn set_track_index(&mut self, track_id: TrackId, index: usize) {
+        if let Some(from) = self.track_order.iter().position(|id| *id == track_id) {
+            self.track_order.remove(from);
+            self.track_order.insert(index.min(self.track_order.len()), track_id);
+        }
+    }
+
+    // undoes the actions on the tracks themselves, the actions on the notes are
+    // forwarded to the track they belong to
+    fn undo_action(&mut self, action: Action) -> Command<EditorMessage> {
+        match action {
+            Action::FromTrackId(track_id) => {
+                return Command::perform(async move { track_id }, move |track_id| {
+                    EditorMessage::Track(track_id, TrackMessage::Undo)
+                });
+            }
+            Action::AddTrack { track_id, .. } => {
+                self.set_track_aside(track_id);
+            }
+            Action::RemoveTrack { track_id, index } => self.restore_track(track_id, index),
+            Action::RenameTrack { track_id, previous_name, .. } => {
+                self.set_track_name(track_id, previous_name)
+            }
+            Action::MoveTrack { track_id, from, .. } => self.set_track_index(track_id, from),
+            Action::ChangeTiming { previous, .. } => self.set_timing_info(previous),
+            Action::None => {}
+        }
+        Command::none()
+    }
+
+    fn redo_action(&mut self, action: Action) -> Command<EditorMessage> {
+        match action {
+            Action::FromTrackId(track_id) => {
+                return Command::perform(async move { track_id }, move |track_id| {
+                    EditorMessage::Track(track_id, TrackMessage::Redo)
+                });
+            }
+            Action::AddTrack { track_id, index } => self.restore_track(track_id, index),
+            Action::RemoveTrack { track_id, .. } => {
+                self.set_track_aside(track_id);
+            }
+            Action::RenameTrack { track_id, name, .. } => self.set_track_name(track_id, name),
+            Action::MoveTrack { track_id, to, .. } => self.set_track_index(track_id, to),
+            Action::ChangeTiming { timing_info, .. } => self.set_timing_info(timing_info),
+            Action::None => {}
+        }
+        Command::none()
+    }
+
+    // the tempo map and the bar lines of every track follow the tempo and meter tracks
+    fn set_timing_info(&mut self, timing_info: TimingInfo) {
+        self.tempo_map = timing_info.tempo_map();
+        for track in self.tracks.values_mut().chain(self.removed_tracks.values_mut()) {
+            track.set_timing_info(timing_info.clone());
+        }
+        self.timing_info = timing_info;
+
+        // playback goes on from the same position, at the new tempo
+        if self.transport.is_playing() {
+            self.transport.seek(self.transport.position, Instant::now());
+            self.send_tracks_to_audio_engine();
+            self.seek_audio();
+        }
+        self.send_loop_to_audio_engine();
+    }
+
+    fn change_timing(&mut self, change: impl FnOnce(&mut TimingInfo, u32)) {
+        let bar = self.timing_info.bar_beat_tick(self.main_player_head).bar.max(1) as u32;
+        let mut timing_info = self.timing_info.clone();
+        change(&mut timing_info, bar);
+
+        if timing_info != self.timing_info {
+            let previous = self.timing_info.clone();
+            self.history
+                .add_action(Action::ChangeTiming { previous, timing_info: timing_info.clone() });
+            self.set_timing_info(timing_info);
+        }
+    }
+
+    // the tempo and the meter at the bar of the playhead, which is where they are changed
+    fn timing_row(&self) -> EditorElement {
+        let bar = self.timing_info.bar_beat_tick(self.main_player_head).bar.max(1) as u32;
+        let bar_start = self.timing_info.bar_start(bar);
+        let tempo = self.timing_info.tempo_at(bar_start);
+        let meter = self.timing_info.meter_at(bar_start);
+
+        let mut remove_button = button(text("Clear")).padding(5);
+        if self.timing_info.has_changes(bar) {
+            remove_button = remove_button.on_press(EditorMessage::RemoveTimingChanges);
+        }
+
+        Row::with_children(vec![
+            text(format!("Bar {}", bar)).width(Length::Units(60)).into(),
+            step_button("<<", EditorMessage::SetTempo(tempo.bpm - 10.0)),
+            step_button("<", EditorMessage::SetTempo(tempo.bpm - 1.0)),
+            text(format!("{:.0} bpm", tempo.bpm)).width(Length::Units(70)).into(),
+            step_button(">", EditorMessage::SetTempo(tempo.bpm + 1.0)),
+            step_button(">>", EditorMessage::SetTempo(tempo.bpm + 10.0)),
+            step_button(
+                "-",
+                EditorMessage::SetMeter(meter.numerator.saturating_sub(1), meter.denominator),
+            ),
+            text(format!("{}/{}", meter.numerator, meter.denominator))
+                .width(Length::Units(40))
+                .into(),
+            step_button("+", EditorMessage::SetMeter(meter.numerator + 1, meter.denominator)),
+            step_button("/2", EditorMessage::SetMeter(meter.numerator, meter.denominator / 2)),
+            step_button("x2", EditorMessage::SetMeter(meter.numerator, meter.denominator * 2)),
+            remove_button.into(),
+        ])
+        .spacing(5)
+        .align_items(Alignment::Center)
+        .into()
+    }
+
+    // the ruler follows the time axis of the active track
+    fn ruler(&self) -> EditorElement {
+        let track = self
+            .active_track_id()
+            .and_then(|track_id| self.tracks.get(&track_id))
+            .or_else(|| self.track_order.first().and_then(|track_id| self.tracks.get(track_id)));
+
+        let ruler = match track {
+            Some(track) => Ruler::new(&track.grid, &self.timing_info, &self.transport)
+                .view()
+                .map(EditorMessage::Transport),
+            None => Space::with_height(Length::Units(RULER_HEIGHT)).into(),
+        };
+
+        Row::with_children(vec![
+            Space::with_width(Length::Units(TRACK_HEADER_WIDTH + PIANO_WIDTH)).into(),
+            ruler,
+        ])
+        .spacing(5)
+        .into()
+    }
+
+    // the column on the left of each track: the name and the position of the track,
+    // then the controls of the track itself
+    fn track_header(&self, track_id: TrackId, index: usize) -> EditorElement {
+        let track = &self.tracks[&track_id];
+
+        let small_button = |label: &'static str| {
+            button(text(label).horizontal_alignment(alignment::Horizontal::Center))
+                .padding(5)
+                .width(Length::Units(40))
+        };
+
+        let name = match &self.renaming {
+            Some((renamed_track_id, name)) if *renamed_track_id == track_id => name.clone(),
+            _ => track.meta.name.clone(),
+        };
+
+        let mut move_up = small_button("Up");
+        if index > 0 {
+            move_up = move_up.on_press(EditorMessage::MoveTrack(track_id, index - 1));
+        }
+
+        let mut move_down = small_button("Down");
+        if index + 1 < self.track_order.len() {
+            move_down = move_down.on_press(EditorMessage::MoveTrack(track_id, index + 1));
+        }
+
+        let mut remove = small_button("Del");
+        if self.track_order.len() > 1 {
+            remove = remove.on_press(EditorMessage::RemoveTrack(track_id));
+        }
+
+        let name_row = Row::with_children(vec![
+            small_button(if track.is_active { "(o)" } else { "( )" })
+                .on_press(EditorMessage::SelectTrack(track_id))
+                .into(),
+            text_input("Track name", &name, move |name| {
+                EditorMessage::TrackNameEdited(track_id, name)
+            })
+            .on_submit(EditorMessage::RenameTrack(track_id))
+            .padding(5)
+            .width(Length::Fill)
+            .into(),
+        ])
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+        let order_row = Row::with_children(vec![
+            move_up.into(),
+            move_down.into(),
+            small_button("Dup").on_press(EditorMessage::DuplicateTrack(track_id)).into(),
+            remove.into(),
+        ])
+        .spacing(5);
+
+        let controls: EditorElement =
+            track.header_view().map(move |message| EditorMessage::Track(track_id, message));
+
+        Column::with_children(vec![name_row.into(), order_row.into(), controls])
+            .spacing(5)
+            .width(Length::Units(TRACK_HEADER_WIDTH))
+            .into()
+    }
+
+    // the transport owns the position of the playhead, and every track follows it
+    fn sync_player_heads(&mut self) {
+        self.main_player_head = self.transport.position;
+        let is_playing = self.transport.is_playing();
+        for track in self.tracks.values_mut() {
+            track.is_playing = is_playing;
+            track.set_player_head(self.main_player_head);
+        }
+    }
+
+    fn sync_chord_stamps(&mut self) {
+        let chord_stamp = if self.stamps_chords { Some(self.chord_stamp.clone()) } else { None };
+        for track in self.tracks.values_mut() {
+            track.chord_stamp = chord_stamp.clone();
+        }
+    }
+
+    fn sync_loop_regions(&mut self) {
+        for track in self.tracks.values_mut() {
+            track.set_loop_region(self.transport.loop_region, self.transport.is_looping);
+        }
+        self.send_loop_to_audio_engine();
+    }
+
+    fn loop_selection(&mut self) {
+        let track = match self.active_track_id().and_then(|track_id| self.tracks.get(&track_id)) {
+            Some(track) => track,
+            None => return,
+        };
+
+        let notes = track.selected.notes.notes.iter().flatten();
+        let start = notes.clone().map(|note| note.start).min();
+        let end = notes.map(|note| note.end).max();
+
+        if let (Some(start), Some(end)) = (start, end) {
+            let loop_region = self.timing_info.bars_around(start, end);
+            self.handle_transport(TransportMessage::SetLoop(loop_region));
+        } else {
+            println!("Select notes to loop over");
+        }
+    }
+
+    // the count-in before playing from the playhead, if any
+    fn count_in(&self) -> Option<(Vec<SynthEvent>, f64)> {
+        self.metronome.count_in(&self.timing_info, &self.tempo_map, self.transport.position)
+    }
+
+    // the playhead waits for the count-in
+    fn play(&mut self) {
+        let count_in_seconds = self.count_in().map_or(0.0, |(_, seconds)| seconds);
+        self.transport.play_after(Instant::now(), Duration::from_secs_f64(count_in_seconds));
+    }
+
+    // the end of the bar of the last note
+    fn song_end(&self) -> Tick {
+        let last_end = self
+            .audible_tracks()
+            .flat_map(|track| {
+                track.midi_notes.notes.iter().chain(track.selected.notes.notes.iter()).flatten()
+            })
+            .map(|note| note.end)
+            .max()
+            .unwrap_or(FIRST_TICK);
+        self.timing_info.bars_around(FIRST_TICK, last_end).map_or(FIRST_TICK, |bars| bars.end)
+    }
+
+    // the click as a track, for the exported midi file, on the last channel that no
+    // exported track uses, if any
+    fn click_track(&self) -> Track {
+        let mut notes = MidiNotes::new();
+        notes.add_notes_vec(self.metronome.clicks(&self.timing_info, FIRST_TICK, self.song_end()));
+
+        let used_channels: Vec<u8> = self.audible_tracks().map(|track| track.channel).collect();
+        let mut track = Track::with_notes(self.new_track_id(), notes);
+        track.meta.name = "Metronome".to_string();
+        track.channel = (0..=METRONOME_CHANNEL)
+            .rev()
+            .find(|channel| *channel != DRUM_CHANNEL && !used_channels.contains(channel))
+            .unwrap_or(METRONOME_CHANNEL);
+        track.program = METRONOME_PROGRAM;
+        track
+    }
+
+    fn handle_transport(&mut self, message: TransportMessage) {
+        let was_playing = self.transport.is_playing();
+
+        // a take ends when playback does
+        if was_playing
+            && matches!(
+                message,
+                TransportMessage::Pause | TransportMessage::TogglePlay | TransportMessage::Stop
+            )
+        {
+            self.finish_take();
+        }
+
+        match message {
+            TransportMessage::Play => self.play(),
+            TransportMessage::Pause => self.transport.pause(),
+            TransportMessage::TogglePlay => {
+                if was_playing {
+                    self.transport.pause();
+                } else {
+                    self.play();
+                }
+            }
+            TransportMessage::Stop => self.transport.stop(),
+            TransportMessage::ReturnToStart => self.transport.return_to_start(Instant::now()),
+            TransportMessage::Seek(position) => self.transport.seek(position, Instant::now()),
+            TransportMessage::SetLoop(loop_region) => self.transport.set_loop_region(loop_region),
+            TransportMessage::ToggleLoop => self.transport.toggle_loop(),
+            TransportMessage::Tick(now) => {
+                // the input is placed before the playhead wraps around the loop
+                self.record_input(now);
+                self.transport.advance(now, &self.tempo_map);
+            }
+        }
+        self.sync_player_heads();
+
+        if matches!(message, TransportMessage::SetLoop(_) | TransportMessage::ToggleLoop) {
+            self.sync_loop_regions();
+        }
+
+        match (was_playing, self.transport.is_playing()) {
+            (false, true) => self.start_audio(),
+            (true, false) => {
+                if let Some(audio_engine) = &self.audio_engine {
+                    audio_engine.stop();
+                }
+            }
+            (true, true)
+                if matches!(
+                    message,
+                    TransportMessage::ReturnToStart | TransportMessage::Seek(_)
+                ) =>
+            {
+                self.seek_audio()
+            }
+            _ => {}
+        }
+    }
+
+    fn open_midi_input(&mut self) {
+        let midi_input = match &self.midi_replay {
+            Some(path) => ReplayInput::from_file(path)
+                .map(|input| Box::new(input) as Box<dyn MidiInputSource>),
+            None => DeviceInput::open(self.midi_input_port.as_deref())
+                .map(|input| Box::new(input) as Box<dyn MidiInputSource>),
+        };
+
+        match midi_input {
+            Ok(midi_input) => self.midi_input = Some(midi_input),
+            Err(e) => println!("Could not open the midi input: {}", e),
+        }
+    }
+
+    fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    // records from the playhead, after the count-in if playback has to start
+    fn start_recording(&mut self) {
+        if !self.tracks.values().any(|track| track.is_armed) {
+            println!("Arm a track to record");
+            return;
+        }
+
+        if self.midi_input.is_none() {
+            self.open_midi_input();
+        }
+        if self.midi_input.is_none() {
+            return;
+        }
+
+        let mut take_start = Instant::now();
+        if !self.transport.is_playing() {
+            let count_in_seconds = self.count_in().map_or(0.0, |(_, seconds)| seconds);
+            take_start += Duration::from_secs_f64(count_in_seconds);
+            self.handle_transport(TransportMessage::Play);
+        }
+
+        self.recorder = Some(Recorder::new(self.transport.position, self.transport.active_loop()));
+        if let Some(midi_input) = &mut self.midi_input {
+            midi_input.start(take_start);
+        }
+    }
+
+    // places the input received until now on the timeline
+    fn record_input(&mut self, now: Instant) {
+        if let (Some(recorder), Some(midi_input)) = (&mut self.recorder, &mut self.midi_input) {
+            for event in midi_input.poll(now) {
+                if let Some(position) = self.transport.position_at(event.instant, &self.tempo_map) {
+                    recorder.record(event.kind, position);
+                }
+            }
+        }
+    }
+
+    // every armed track gets the take as one undoable action
+    fn finish_take(&mut self) {
+        let now = Instant::now();
+        self.record_input(now);
+
+        let recorder = match self.recorder.take() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let position =
+            self.transport.position_at(now, &self.tempo_map).unwrap_or(self.transport.position);
+
+        let take = match recorder.finish(position) {
+            Some(take) => take,
+            None => {
+                println!("Nothing was recorded");
+                return;
+            }
+        };
+
+        let message = TrackMessage::RecordTake {
+            notes: take.notes,
+            start: take.start,
+            end: take.end,
+            mode: self.record_settings.mode,
+            quantize: self.record_settings.quantize,
+        };
+        for track_id in self.track_order.iter() {
+            if let Some(track) = self.tracks.get_mut(track_id).filter(|track| track.is_armed) {
+                track.update(&message, &mut self.history);
+            }
+        }
+
+        if self.transport.is_playing() {
+            self.send_tracks_to_audio_engine();
+        }
+    }
+
+    fn toggle_record(&mut self) {
+        if self.is_recording() {
+            self.finish_take();
+        } else {
+            self.start_recording();
+        }
+    }
+
+    // the engine is started if needed, so that notes can be heard before playing
+    fn audition(&mut self, channel: u8, program: u8, keys: Vec<u8>) {
+        if !self.is_auditioning {
+            return;
+        }
+        if self.audio_engine.is_none() {
+            self.start_audio_engine();
+        }
+        if let Some(audio_engine) = &self.audio_engine {
+            audio_engine.audition(channel, program, keys, DEFAULT_VELOCITY, AUDITION_SECONDS);
+        }
+    }
+
+    fn start_audio_engine(&mut self) {
+        let sink: Box<dyn AudioSink> = match DeviceSink::open() {
+            Ok(sink) => Box::new(sink),
+            Err(e) => {
+                println!("Playing without sound: {}", e);
+                Box::new(NullSink { sample_rate: NULL_SINK_SAMPLE_RATE })
+            }
+        };
+
+        self.audio_engine = Some(AudioEngine::start(self.soundfont.clone(), sink));
+    }
+
+    fn transport_seconds(&self) -> f64 {
+        self.tempo_map.beats_to_seconds(tick::to_song_beats(self.transport.position))
+    }
+
+    fn start_audio(&mut self) {
+        if self.audio_engine.is_none() {
+            self.start_audio_engine();
+        }
+        self.send_tracks_to_audio_engine();
+        self.send_loop_to_audio_engine();
+        if let Some(audio_engine) = &self.audio_engine {
+            match self.count_in() {
+                Some((events, seconds)) => {
+                    audio_engine.play_after_count_in(self.transport_seconds(), events, seconds)
+                }
+                None => audio_engine.play(self.transport_seconds()),
+            }
+        }
+    }
+
+    fn send_loop_to_audio_engine(&self) {
+        if let Some(audio_engine) = &self.audio_engine {
+            let to_seconds =
+                |position| self.tempo_map.beats_to_seconds(tick::to_song_beats(position));
+            audio_engine.set_loop(
+                self.transport.active_loop().map(|loop_region| {
+                    (to_seconds(loop_region.start), to_seconds(loop_region.end))
+                }),
+            );
+        }
+    }
+
+    fn seek_audio(&self) {
+        if let Some(audio_engine) = &self.audio_engine {
+            audio_engine.seek(self.transport_seconds());
+        }
+    }
+
+    // the tracks heard by playback, rendering and export, in order: the soloed tracks if
+    // any track is soloed, or else the tracks that are not muted
+    fn audible_tracks(&self) -> impl Iterator<Item = &Track> {
+        let any_soloed = self.tracks.values().any(|track| track.is_soloed);
+        self.track_order
+            .iter()
+            .filter_map(|track_id| self.tracks.get(track_id))
+            .filter(move |track| if any_soloed { track.is_soloed } else { !track.is_muted })
+    }
+
+    fn send_tracks_to_audio_engine(&self) {
+        if let Some(audio_engine) = &self.audio_engine {
+            let mut tracks: Vec<RenderTrack> =
+                self.audible_tracks().map(RenderTrack::from_track).collect();
+
+            // the click goes on for a while after the last note, and after the playhead
+            if self.metronome.is_on {
+                let end = self.song_end().max(self.transport.position);
+                let last_bar = self.timing_info.bar_beat_tick(end).bar.max(1) as u32;
+                let click_end = self.timing_info.bar_start(last_bar + METRONOME_EXTRA_BARS);
+                tracks.push(self.metronome.render_track(&self.timing_info, FIRST_TICK, click_end));
+            }
+
+            audio_engine.set_tracks(tracks, self.tempo_map.clone());
+        }
+    }
+
+    fn quantize_active_track(&mut self) {
+        if let ActiveElement::Track(track_id) = self.active_element {
+            if let Some(track) = self.tracks.get_mut(&track_id) {
+                let message = TrackMessage::Quantize { settings: self.quantize_settings };
+                track.update(&message, &mut self.history);
+            }
+        }
+    }
+
+    fn transpose_active_track(&mut self, interval: Interval) {
+        if let ActiveElement::Track(track_id) = self.active_element {
+            if let Some(track) = self.tracks.get_mut(&track_id) {
+                let message = TrackMessage::Transpose { interval };
+                track.update(&message, &mut self.history);
+            }
+        }
+    }
+
+    fn quantize_settings_row(&self) -> EditorElement {
+        let settings = self.quantize_settings;
+
+        // the strength, the swing and the window change by steps of 10%
+        let stepper =
+            |label: String, value: f32, set: fn(&mut QuantizeSettings, f32)| -> EditorElement {
+                let mut decreased = settings;
+                set(&mut decreased, ((value - 0.1) * 10.0).round() / 10.0);
+                let mut increased = settings;
+                set(&mut increased, ((value + 0.1) * 10.0).round() / 10.0);
+
+                widgets::stepper(
+                    text(label).width(Length::Units(110)),
+                    EditorMessage::QuantizeSettingsChanged(decreased),
+                    EditorMessage::QuantizeSettingsChanged(increased),
+                )
+            };
+
+        let quantize_ends = QuantizeSettings { quantize_ends: !settings.quantize_ends, ..settings };
+
+        Row::with_children(vec![
+            button(text("Quantize")).padding(10).on_press(EditorMessage::Quantize).into(),
+            stepper(
+                format!("Strength {:.0}%", settings.strength * 100.0),
+                settings.strength,
+                |s, v| s.strength = v.clamp(0.0, 1.0),
+            ),
+            stepper(format!("Swing {:.0}%", settings.swing * 100.0), settings.swing, |s, v| {
+                s.swing = v.clamp(0.0, 1.0)
+            }),
+            stepper(format!("Window {:.0}%", settings.window * 100.0), settings.window, |s, v| {
+                s.window = v.clamp(0.0, 1.0)
+            }),
+            button(text(if settings.quantize_ends { "Ends: on" } else { "Ends: off" }))
+                .padding(10)
+                .on_press(EditorMessage::QuantizeSettingsChanged(quantize_ends))
+                .into(),
+        ])
+        .spacing(15)
+        .align_items(Alignment::Center)
+        .into()
+    }
+
+    // the scale that follows the one of the active track in the scale picker, or
+    // precedes it, with the custom scales after the presets
+    fn step_scale(&self, scale_type: &ScaleType, step: isize) -> ScaleType {
+        let scale_types: Vec<ScaleType> = ScaleType::PRESETS
+            .iter()
+            .cloned()
+            .chain(self.custom_scales.iter().map(CustomScale::scale_type))
+            .collect();
+
+        let count = scale_types.len() as isize;
+        let index = match scale_types.iter().position(|x| x == scale_type) {
+            Some(index) => (index as isize + step).rem_euclid(count),
+            None => 0,
+        };
+        scale_types[index as usize].clone()
+    }
+
+    fn scale_name(&self, scale_type: &ScaleType) -> String {
+        match scale_type {
+            ScaleType::Custom(intervals) => self
+                .custom_scales
+                .iter()
+                .find(|custom_scale| custom_scale.intervals == *intervals)
+                .map_or_else(|| "Custom".to_string(), |custom_scale| custom_scale.name.clone()),
+            scale_type => scale_type.name().to_string(),
+        }
+    }
+
+    fn save_custom_scale(&mut self) {
+        let track = match self.active_track_id().and_then(|track_id| self.tracks.get_mut(&track_id))
+        {
+            Some(track) => track,
+            None => return,
+        };
+
+        let pitches: Vec<u8> =
+            track.selected.notes.notes.iter().flatten().map(|note| note.get_pitch()).collect();
+        if pitches.is_empty() {
+            println!("Select the notes of the scale to save it");
+            return;
+        }
+
+        let root = track.grid.scale.root;
+        let name = format!("Custom {}", self.custom_scales.len() + 1);
+        let custom_scale = CustomScale::new(name, root, pitches);
+        track.set_scale(custom_scale.scale_type(), root);
+
+        if self.custom_scales.iter().any(|x| x.intervals == custom_scale.intervals) {
+            return;
+        }
+        self.custom_scales.push(custom_scale);
+
+        if let Err(e) = save_custom_scales(CUSTOM_SCALES_FILE_NAME, &self.custom_scales) {
+            println!("Could not save the custom scales to {}: {}", CUSTOM_SCALES_FILE_NAME, e);
+        }
+    }
+
+    // the root and the scale of the active track
+    fn scale_row(&self) -> EditorElement {
+        let (track_id, scale) =
+            match self.active_track_id().and_then(|track_id| self.tracks.get(&track_id)) {
+                Some(track) => (track.id(), &track.grid.scale),
+                None => return Space::with_height(Length::Shrink).into(),
+            };
+
+        let remaps_notes = self.remaps_notes;
+        let set_scale = |scale_type: ScaleType, root: u8| {
+            let message = if remaps_notes {
+                TrackMessage::ChangeKey { scale_type, root }
+            } else {
+                TrackMessage::SetScale { scale_type, root }
+            };
+            EditorMessage::Track(track_id, message)
+        };
+
+        let root = widgets::stepper(
+            text(format!("Root {}", scale.root_label())).width(Length::Units(90)),
+            set_scale(scale.scale_type.clone(), (scale.root + 11) % 12),
+            set_scale(scale.scale_type.clone(), (scale.root + 1) % 12),
+        );
+
+        let scale_type = widgets::stepper(
+            text(self.scale_name(&scale.scale_type)).width(Length::Units(150)),
+            set_scale(self.step_scale(&scale.scale_type, -1), scale.root),
+            set_scale(self.step_scale(&scale.scale_type, 1), scale.root),
+        );
+
+        let save_button =
+            button(text("Save scale")).padding(10).on_press(EditorMessage::SaveCustomScale);
+
+        let conform_button = button(text("Conform to scale")).padding(10).on_press(
+            EditorMessage::Track(track_id, TrackMessage::ConformToScale { scale: scale.clone() }),
+        );
+
+        let remap_button =
+            button(text(if self.remaps_notes { "Remap notes: on" } else { "Remap notes: off" }))
+                .padding(10)
+                .on_press(EditorMessage::ToggleRemapNotes);
+
+        Row::with_children(vec![
+            root.into(),
+            scale_type.into(),
+            remap_button.into(),
+            save_button.into(),
+            conform_button.into(),
+        ])
+        .spacing(15)
+        .align_items(Alignment::Center)
+        .into()
+    }
+
+    // the selected notes of the active track become a voicing of the chord stamp
+    fn save_voicing(&mut self) {
+        let pitches: Vec<u8> = match self.active_track_id().and_then(|id| self.tracks.get(&id)) {
+            Some(track) => {
+                track.selected.notes.notes.iter().flatten().map(|note| note.get_pitch()).collect()
+            }
+            None => return,
+        };
+
+        let name = format!("Voicing {}", self.voicings.len() + 1);
+        let voicing = match SavedVoicing::new(name, pitches) {
+            Some(voicing) => voicing,
+            None => {
+                println!("Select the notes of the voicing to save it, at least two pitches");
+                return;
+            }
+        };
+
+        let voicing = match self.voicings.iter().find(|x| x.intervals == voicing.intervals) {
+            Some(saved) => saved.clone(),
+            None => {
+                self.voicings.push(voicing.clone());
+                if let Err(e) = save_voicings(VOICINGS_FILE_NAME, &self.voicings) {
+                    println!("Could not save the voicings to {}: {}", VOICINGS_FILE_NAME, e);
+                }
+                voicing
+            }
+        };
+
+        self.chord_stamp = ChordStamp {
+            chord: StampChord::Saved(voicing),
+            inversion: 0,
+            ..self.chord_stamp.clone()
+        };
+        self.sync_chord_stamps();
+    }
+
+    // the chord that clicks write, built on the clicked pitch from the scale of the track
+    fn chord_stamp_row(&self) -> EditorElement {
+        let chord_stamp = &self.chord_stamp;
+
+        let stepper = |label: String, decreased, increased| -> EditorElement {
+            widgets::stepper(
+                text(label).width(Length::Units(120)),
+                EditorMessage::ChordStampChanged(decreased),
+                EditorMessage::ChordStampChanged(increased),
+            )
+        };
+
+        let spread_index = Spread::ALL.iter().position(|x| *x == chord_stamp.spread).unwrap_or(0);
+        let step_spread = |step: usize| ChordStamp {
+            spread: Spread::ALL[(spread_index + step) % Spread::ALL.len()],
+            ..chord_stamp.clone()
+        };
+
+        let inversion_label = match chord_stamp.inversion {
+            0 => "Root position".to_string(),
+            inversion => format!("Inversion {}", inversion),
+        };
+
+        Row::with_children(vec![
+            button(text(if self.stamps_chords { "Chords: on" } else { "Chords: off" }))
+                .padding(10)
+                .on_press(EditorMessage::ToggleChordStamp)
+                .into(),
+            stepper(
+                chord_stamp.chord.name(),
+                ChordStamp {
+                    chord: chord_stamp.chord.step(-1, &self.voicings),
+                    ..chord_stamp.clone()
+                },
+                ChordStamp {
+                    chord: chord_stamp.chord.step(1, &self.voicings),
+                    ..chord_stamp.clone()
+                },
+            ),
+            stepper(
+                inversion_label,
+                ChordStamp {
+                    inversion: chord_stamp.inversion.saturating_sub(1),
+                    ..chord_stamp.clone()
+                },
+                ChordStamp {
+                    inversion: (chord_stamp.inversion + 1).min(chord_stamp.max_inversion()),
+                    ..chord_stamp.clone()
+                },
+            ),
+            stepper(
+                format!("Spread {}", chord_stamp.spread.name()),
+                step_spread(Spread::ALL.len() - 1),
+                step_spread(1),
+            ),
+            button(text("Save voicing")).padding(10).on_press(EditorMessage::SaveVoicing).into(),
+        ])
+        .spacing(15)
+        .align_items(Alignment::Center)
+        .into()
+    }
+
+    fn metronome_settings_row(&self) -> EditorElement {
+        let settings = self.metronome;
+
+        let stepper = |label: String, decreased, increased| -> EditorElement {
+            widgets::stepper(
+                text(label).width(Length::Units(90)),
+                EditorMessage::MetronomeSettingsChanged(decreased),
+                EditorMessage::MetronomeSettingsChanged(increased),
+            )
+        };
+
+        let toggle = |label: &str, is_on: bool, toggled| -> EditorElement {
+            button(text(format!("{}: {}", label, if is_on { "on" } else { "off" })))
+                .padding(10)
+                .on_press(EditorMessage::MetronomeSettingsChanged(toggled))
+                .into()
+        };
+
+        // the volume changes by steps of 10%
+        let volume = |volume: f32| MetronomeSettings {
+            volume: ((volume * 10.0).round() / 10.0).clamp(0.0, 1.0),
+            ..settings
+        };
+        let subdivision = |subdivision: u8| MetronomeSettings {
+            subdivision: subdivision.clamp(1, 4),
+            ..settings
+        };
+        let count_in = |count_in_bars: u8| MetronomeSettings {
+            count_in_bars: count_in_bars.min(4),
+            ..settings
+        };
+
+        Row::with_children(vec![
+            toggle(
+                "Click",
+                settings.is_on,
+                MetronomeSettings { is_on: !settings.is_on, ..settings },
+            ),
+            stepper(
+                format!("Volume {:.0}%", settings.volume * 100.0),
+                volume(settings.volume - 0.1),
+                volume(settings.volume + 0.1),
+            ),
+            stepper(
+                format!("{} per beat", settings.subdivision),
+                subdivision(settings.subdivision.saturating_sub(1)),
+                subdivision(settings.subdivision + 1),
+            ),
+            stepper(
+                format!("Count-in {}", settings.count_in_bars),
+                count_in(settings.count_in_bars.saturating_sub(1)),
+                count_in(settings.count_in_bars + 1),
+            ),
+            toggle(
+                "In export",
+                settings.is_exported,
+                MetronomeSettings { is_exported: !settings.is_exported, ..settings },
+            ),
+        ])
+        .spacing(15)
+        .align_items(Alignment::Center)
+        .into()
+    }
+
+    fn render_audio(&self) {
+        match self.render_audio_to(RENDER_FILE_NAME) {
+            Ok(()) => println!("Rendered {}", RENDER_FILE_NAME),
+            Err(e) => println!("Could not render {}: {}", RENDER_FILE_NAME, e),
+        }
+    }
+
+    fn render_audio_to(&self, wav_path: impl AsRef<Path>) -> Result<(), RenderError> {
+        let mut tracks: Vec<RenderTrack> =
+            self.audible_tracks().map(RenderTrack::from_track).collect();
+        if self.metronome.is_exported {
+            tracks.push(self.metronome.render_track(
+                &self.timing_info,
+                FIRST_TICK,
+                self.song_end(),
+            ));
+        }
+
+        render_to_wav_file(
+            &self.soundfont,
+            wav_path,
+            &tracks,
+            &self.tempo_map,
+            RenderSettings::default(),
+        )
+    }
+
+    fn export_midi_file(&self) {
+        let click_track = self.metronome.is_exported.then(|| self.click_track());
+
+        match smf::export::export_file(
+            &self.export_path,
+            self.audible_tracks().chain(click_track.as_ref()),
+            &self.timing_info,
+            self.transport.loop_region,
+            self.export_ppq,
+        ) {
+            Ok(()) => println!("Exported {}", self.export_path.display()),
+            Err(e) => println!("Could not export {}: {}", self.export_path.display(), e),
+        }
+    }
+
     fn handle_copy(&mut self) -> Command<EditorMessage> {
         match self.active_element {
             ActiveElement::Track(track_id) => {
@@ -179,10 +1564,9 @@ impl MidiEditor {
                         let minimum_time = notes.start_time;
                         // let delta_player_head = track.player_head - *player_head;
                         let delta_player_head = track.player_head - minimum_time;
-                        let drag = Vector::new(delta_player_head, 0.0);
 
                         let mut notes = notes.clone();
-                        notes.drag_all_notes(drag, &track.grid);
+                        notes.shift_all_notes(delta_player_head);
 
                         track.update(
                             &TrackMessage::AddManyNotes { notes: notes.clone() },
@@ -201,44 +1585,15 @@ impl MidiEditor {
 
     fn handle_undo(&mut self) -> Command<EditorMessage> {
         if let Some(action) = self.history.undo() {
-            match action {
-                Action::FromTrackId(track_id) => {
-                    Command::perform(async move { track_id }, move |track_id| {
-                        EditorMessage::Track(track_id, TrackMessage::Undo)
-                    })
-                }
-
-                Action::None => Command::none(),
-            }
+            self.undo_action(action)
         } else {
             Command::none()
         }
     }
-    // fn handle_undo(&mut self) -> Command<EditorMessage> {
-    //     if let Some(action) = self.history.undo() {
-    //         match action {
-    //             Action::TrackAction(track_id, track_action) => {
-    //                 Command::perform(async move { track_action }, move |act| {
-    //                     // println!("Undoing action: {:?}", act);
-    //                     EditorMessage::Track(track_id, TrackMessage::Undo(act))
-    //                 })
-    //             }
-    //             Action::None => Command::none(),
-    //         }
-    //     } else {
-    //         Command::none()
-    //     }
-    // }
+
     fn handle_redo(&mut self) -> Command<EditorMessage> {
         if let Some(action) = self.history.redo() {
-            match action {
-                Action::FromTrackId(track_id) => {
-                    Command::perform(async move { track_id }, move |track_id| {
-                        EditorMessage::Track(track_id, TrackMessage::Redo)
-                    })
-                }
-                Action::None => Command::none(),
-            }
+            self.redo_action(action)
         } else {
             Command::none()
         }
@@ -251,10 +1606,41 @@ impl Application for MidiEditor {
     type Message = EditorMessage;
     type Theme = TrackTheme;
     type Executor = executor::Default;
-    type Flags = ();
+    type Flags = EditorFlags;
+
+    fn new(flags: EditorFlags) -> (Self, Command<EditorMessage>) {
+        let mut editor = Self::default();
 
-    fn new(_flags: ()) -> (Self, Command<EditorMessage>) {
-        (Self { ..Self::default() }, Command::none())
+        if let Some(soundfont) = flags.soundfont {
+            editor.soundfont = soundfont;
+        }
+        editor.midi_input_port = flags.midi_input;
+
+        editor.custom_scales = match load_custom_scales(CUSTOM_SCALES_FILE_NAME) {
+            Ok(custom_scales) => custom_scales,
+            Err(e) => {
+                println!("Could not load the custom scales of {}: {}", CUSTOM_SCALES_FILE_NAME, e);
+                Vec::new()
+            }
+        };
+        editor.voicings = match load_voicings(VOICINGS_FILE_NAME) {
+            Ok(voicings) => voicings,
+            Err(e) => {
+                println!("Could not load the voicings of {}: {}", VOICINGS_FILE_NAME, e);
+                Vec::new()
+            }
+        };
+        editor.midi_replay = flags.midi_replay;
+
+        if let Some(path) = flags.file {
+            if path.extension().map_or(false, |extension| extension == "json") {
+                editor.open_project(&path);
+            } else {
+                editor.open_midi_file(&path);
+            }
+        }
+
+        (editor, Command::none())
     }
 
     fn title(&self) -> String {
@@ -262,10 +1648,40 @@ impl Application for MidiEditor {
     }
 
     fn update(&mut self, message: EditorMessage) -> Command<EditorMessage> {
-        match message {
+        // the notes can be edited by the tracks, and by undo, redo, cut and paste
+        let may_edit_notes = match &message {
+            EditorMessage::Track(_, TrackMessage::CursorMoved)
+            | EditorMessage::Track(_, TrackMessage::ModifiersChanged(_)) => false,
+            EditorMessage::Track(..)
+            | EditorMessage::Quantize
+            | EditorMessage::AddTrack
+            | EditorMessage::RemoveTrack(_)
+            | EditorMessage::DuplicateTrack(_)
+            | EditorMessage::EventOccurred(Event::Keyboard(_)) => true,
+            _ => false,
+        };
+
+        let command = match message {
             EditorMessage::Track(track_id, message) => {
                 if let Some(track) = self.tracks.get_mut(&track_id) {
                     track.update(&message, &mut self.history);
+
+                    let audition = std::mem::take(&mut track.audition);
+                    if !audition.is_empty() {
+                        let (channel, program) = (track.channel, track.program);
+                        self.audition(channel, program, audition);
+                    }
+                    let track = &self.tracks[&track_id];
+
+                    // a click on the timeline of a track moves the playhead of every track
+                    if track.player_head != self.main_player_head {
+                        let player_head = track.player_head;
+                        self.transport.seek(player_head, Instant::now());
+                        self.sync_player_heads();
+                        if self.transport.is_playing() {
+                            self.seek_audio();
+                        }
+                    }
                 } else {
                     println!("Called non-existent track id: {}", track_id);
                 }
@@ -308,6 +1724,82 @@ impl Application for MidiEditor {
                     println!("Pasting");
                     self.handle_paste()
                 }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if modifiers.command() && key_code == keyboard::KeyCode::E =>
+                {
+                    self.export_midi_file();
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if modifiers.command() && key_code == keyboard::KeyCode::R =>
+                {
+                    self.render_audio();
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if modifiers.command() && key_code == keyboard::KeyCode::S =>
+                {
+                    self.save_project();
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if modifiers.command() && key_code == keyboard::KeyCode::O =>
+                {
+                    self.reopen_project();
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if !modifiers.command() && key_code == keyboard::KeyCode::Space =>
+                {
+                    self.handle_transport(TransportMessage::TogglePlay);
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if !modifiers.command() && key_code == keyboard::KeyCode::Q =>
+                {
+                    self.quantize_active_track();
+                    Command::none()
+                }
+                // up and down move by a degree of the scale, by an octave with shift
+                // and by a semitone with alt
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if !modifiers.command()
+                        && (key_code == keyboard::KeyCode::Up
+                            || key_code == keyboard::KeyCode::Down) =>
+                {
+                    let steps = if key_code == keyboard::KeyCode::Up { 1 } else { -1 };
+                    let interval = if modifiers.shift() {
+                        Interval::Octaves(steps)
+                    } else if modifiers.alt() {
+                        Interval::Semitones(steps)
+                    } else {
+                        Interval::Degrees(steps)
+                    };
+                    self.transpose_active_track(interval);
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if !modifiers.command() && key_code == keyboard::KeyCode::L =>
+                {
+                    if modifiers.shift() {
+                        self.loop_selection();
+                    } else {
+                        self.handle_transport(TransportMessage::ToggleLoop);
+                    }
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
+                    if !modifiers.command() && key_code == keyboard::KeyCode::R =>
+                {
+                    self.toggle_record();
+                    Command::none()
+                }
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code, .. })
+                    if key_code == keyboard::KeyCode::Home =>
+                {
+                    self.handle_transport(TransportMessage::ReturnToStart);
+                    Command::none()
+                }
                 // Event::Keyboard(keyboard::Event::KeyPressed { modifiers, key_code })
                 //     if key_code == keyboard::KeyCode::B =>
                 // {
@@ -317,22 +1809,163 @@ impl Application for MidiEditor {
                 // }
                 _ => Command::none(),
             },
+            EditorMessage::Transport(message) => {
+                self.handle_transport(message);
+                Command::none()
+            }
+            EditorMessage::Quantize => {
+                self.quantize_active_track();
+                Command::none()
+            }
+            EditorMessage::QuantizeSettingsChanged(settings) => {
+                self.quantize_settings = settings;
+                Command::none()
+            }
+            EditorMessage::MetronomeSettingsChanged(settings) => {
+                self.metronome = settings;
+                if self.transport.is_playing() {
+                    self.send_tracks_to_audio_engine();
+                }
+                Command::none()
+            }
+            EditorMessage::ToggleRecord => {
+                self.toggle_record();
+                Command::none()
+            }
+            EditorMessage::RecordSettingsChanged(settings) => {
+                self.record_settings = settings;
+                Command::none()
+            }
+            EditorMessage::ToggleAudition => {
+                self.is_auditioning = !self.is_auditioning;
+                Command::none()
+            }
+            EditorMessage::SaveCustomScale => {
+                self.save_custom_scale();
+                Command::none()
+            }
+            EditorMessage::ToggleRemapNotes => {
+                self.remaps_notes = !self.remaps_notes;
+                Command::none()
+            }
+            EditorMessage::ChordStampChanged(mut chord_stamp) => {
+                // a chord with fewer notes has fewer inversions
+                chord_stamp.inversion = chord_stamp.inversion.min(chord_stamp.max_inversion());
+                self.chord_stamp = chord_stamp;
+                self.sync_chord_stamps();
+                Command::none()
+            }
+            EditorMessage::SaveVoicing => {
+                self.save_voicing();
+                Command::none()
+            }
+            EditorMessage::ToggleChordStamp => {
+                self.stamps_chords = !self.stamps_chords;
+                self.sync_chord_stamps();
+                Command::none()
+            }
+            EditorMessage::AddTrack => {
+                self.add_track();
+                Command::none()
+            }
+            EditorMessage::RemoveTrack(track_id) => {
+                self.remove_track(track_id);
+                Command::none()
+            }
+            EditorMessage::DuplicateTrack(track_id) => {
+                self.duplicate_track(track_id);
+                Command::none()
+            }
+            EditorMessage::MoveTrack(track_id, index) => {
+                self.move_track(track_id, index);
+                Command::none()
+            }
+            EditorMessage::SelectTrack(track_id) => {
+                self.set_active_track(track_id);
+                Command::none()
+            }
+            EditorMessage::TrackNameEdited(track_id, name) => {
+                self.renaming = Some((track_id, name));
+                Command::none()
+            }
+            EditorMessage::RenameTrack(track_id) => {
+                if let Some((renamed_track_id, name)) = self.renaming.take() {
+                    if renamed_track_id == track_id {
+                        self.rename_track(track_id, name);
+                    }
+                }
+                Command::none()
+            }
+            EditorMessage::ExportMidiFile => {
+                self.export_midi_file();
+                Command::none()
+            }
+            EditorMessage::SetExportPpq(ppq) => {
+                self.export_ppq = ppq;
+                Command::none()
+            }
+            EditorMessage::RenderAudio => {
+                self.render_audio();
+                Command::none()
+            }
+            EditorMessage::SaveProject => {
+                self.save_project();
+                Command::none()
+            }
+            EditorMessage::OpenProject => {
+                self.reopen_project();
+                Command::none()
+            }
+            EditorMessage::SetTempo(bpm) => {
+                self.change_timing(|timing_info, bar| timing_info.set_tempo(bar, bpm));
+                Command::none()
+            }
+            EditorMessage::SetMeter(numerator, denominator) => {
+                self.change_timing(|timing_info, bar| {
+                    timing_info.set_meter(bar, numerator, denominator)
+                });
+                Command::none()
+            }
+            EditorMessage::RemoveTimingChanges => {
+                self.change_timing(|timing_info, bar| timing_info.remove_changes(bar));
+                Command::none()
+            }
+            EditorMessage::LoopSelection => {
+                self.loop_selection();
+                Command::none()
+            }
             EditorMessage::ShowDebug(_) => {
                 // println!("{}", msg);
                 println!("");
                 println!("");
                 println!("");
                 println!("{:#?}", self.history);
-                println!("");
-                println!("track 0:");
-                println!("{:#?}", self.tracks[&0].selected.notes);
+                if let Some(track) = self.active_track_id().and_then(|id| self.tracks.get(&id)) {
+                    println!("");
+                    println!("track {}:", track.id());
+                    println!("{:#?}", track.selected.notes);
+                }
                 Command::none()
             }
+        };
+
+        if may_edit_notes && self.transport.is_playing() {
+            self.send_tracks_to_audio_engine();
         }
+
+        command
     }
 
     fn subscription(&self) -> Subscription<EditorMessage> {
-        iced_native::subscription::events().map(EditorMessage::EventOccurred)
+        let events = iced_native::subscription::events().map(EditorMessage::EventOccurred);
+
+        if self.transport.is_playing() {
+            let ticks = time::every(Duration::from_millis(TRANSPORT_FRAME_MILLIS))
+                .map(|now| EditorMessage::Transport(TransportMessage::Tick(now)));
+            Subscription::batch(vec![events, ticks])
+        } else {
+            events
+        }
     }
 
     fn view(&self) -> EditorElement {
@@ -342,31 +1975,160 @@ impl Application for MidiEditor {
                 .width(Length::Units(80))
         };
 
-        let toggle_button =
-            button("Toggle").on_press(EditorMessage::Track(1, TrackMessage::Toggle));
+        // toggles the active track
+        let mut toggle_button = button("Toggle");
+        if let Some(track_id) = self.active_track_id() {
+            toggle_button =
+                toggle_button.on_press(EditorMessage::Track(track_id, TrackMessage::Toggle));
+        }
+
+        let add_track_button = button("Add track").on_press(EditorMessage::AddTrack);
 
         let debug_button =
             button("Debug").on_press(EditorMessage::ShowDebug(self.debug_text.clone()));
 
-        let mut elements: Vec<EditorElement> = self
+        let export_button = button("Export").on_press(EditorMessage::ExportMidiFile);
+
+        // steps to the next resolution of EXPORT_PPQS, from the one of the opened file
+        let export_ppq = self.export_ppq;
+        let lower_ppq = EXPORT_PPQS.iter().rev().find(|&&ppq| ppq < export_ppq);
+        let higher_ppq = EXPORT_PPQS.iter().find(|&&ppq| ppq > export_ppq);
+        let export_ppq_stepper = widgets::stepper(
+            text(format!("{} ppq", export_ppq)),
+            EditorMessage::SetExportPpq(*lower_ppq.unwrap_or(&export_ppq)),
+            EditorMessage::SetExportPpq(*higher_ppq.unwrap_or(&export_ppq)),
+        );
+
+        let play_button = if self.transport.is_playing() {
+            button("Pause").on_press(EditorMessage::Transport(TransportMessage::Pause))
+        } else {
+            button("Play").on_press(EditorMessage::Transport(TransportMessage::Play))
+        };
+
+        let stop_button = button("Stop").on_press(EditorMessage::Transport(TransportMessage::Stop));
+
+        let return_button =
+            button("|<").on_press(EditorMessage::Transport(TransportMessage::ReturnToStart));
+
+        let loop_button = button(if self.transport.is_looping { "Loop: on" } else { "Loop: off" })
+            .on_press(EditorMessage::Transport(TransportMessage::ToggleLoop));
+
+        let loop_selection_button = button("Loop sel.").on_press(EditorMessage::LoopSelection);
+
+        let record_button = button(if self.is_recording() { "Rec: on" } else { "Rec: off" })
+            .on_press(EditorMessage::ToggleRecord);
+
+        let settings = self.record_settings;
+        let record_mode_button =
+            match settings.mode {
+                RecordMode::Overdub => {
+                    button("Overdub").on_press(EditorMessage::RecordSettingsChanged(
+                        RecordSettings { mode: RecordMode::Replace, ..settings },
+                    ))
+                }
+                RecordMode::Replace => {
+                    button("Replace").on_press(EditorMessage::RecordSettingsChanged(
+                        RecordSettings { mode: RecordMode::Overdub, ..settings },
+                    ))
+                }
+            };
+
+        let record_quantize_button =
+            button(if settings.quantize { "Rec. quantize: on" } else { "Rec. quantize: off" })
+                .width(Length::Units(150))
+                .on_press(EditorMessage::RecordSettingsChanged(RecordSettings {
+                    quantize: !settings.quantize,
+                    ..settings
+                }));
+
+        let render_button = button("Render").on_press(EditorMessage::RenderAudio);
+
+        let save_button = button("Save").on_press(EditorMessage::SaveProject);
+
+        let open_button = button("Open").on_press(EditorMessage::OpenProject);
+
+        let audition_button =
+            button(if self.is_auditioning { "Audition: on" } else { "Audition: off" })
+                .width(Length::Units(130))
+                .on_press(EditorMessage::ToggleAudition);
+
+        let track_views: Vec<EditorElement> = self
             .track_order
             .iter()
-            .map(|track_id| {
+            .enumerate()
+            .map(|(index, track_id)| {
                 let track = &self.tracks[track_id];
-                let canvas: track::TrackElement = (&track).view();
+                // the chord and velocity lanes are shifted by the width of the piano, so
+                // that they share the time axis of the grid
+                let chord_lane = Row::with_children(vec![
+                    Space::with_width(Length::Units(PIANO_WIDTH)).into(),
+                    track.chord_lane_view(),
+                ]);
+                let piano_roll = Row::with_children(vec![track.piano_view(), track.view()]);
+                let velocity_lane = Row::with_children(vec![
+                    Space::with_width(Length::Units(PIANO_WIDTH)).into(),
+                    track.velocity_lane_view(),
+                ]);
+                let canvas: track::TrackElement = Column::with_children(vec![
+                    chord_lane.into(),
+                    piano_roll.into(),
+                    velocity_lane.into(),
+                ])
+                .spacing(2)
+                .into();
                 let editor_canvas: EditorElement =
                     canvas.map(move |message| EditorMessage::Track(*track_id, message));
-                editor_canvas
+
+                Row::with_children(vec![self.track_header(*track_id, index), editor_canvas])
+                    .spacing(5)
+                    .into()
             })
             .collect();
 
-        elements.push(toggle_button.into());
-        elements.push(debug_button.into());
+        let mut elements: Vec<EditorElement> = vec![
+            self.ruler(),
+            scrollable(Column::with_children(track_views).spacing(15)).height(Length::Fill).into(),
+        ];
+
+        let transport_row: EditorElement = Row::with_children(vec![
+            return_button.into(),
+            play_button.into(),
+            stop_button.into(),
+            loop_button.into(),
+            loop_selection_button.into(),
+            record_button.into(),
+            record_mode_button.into(),
+            record_quantize_button.into(),
+        ])
+        .spacing(10)
+        .into();
+
+        elements.push(transport_row);
+        elements.push(self.timing_row());
+        elements.push(self.quantize_settings_row());
+        elements.push(self.metronome_settings_row());
+        elements.push(self.scale_row());
+        elements.push(self.chord_stamp_row());
+        elements.push(
+            Row::with_children(vec![
+                add_track_button.width(Length::Units(110)).into(),
+                toggle_button.into(),
+                debug_button.into(),
+                export_button.into(),
+                export_ppq_stepper,
+                render_button.into(),
+                save_button.into(),
+                open_button.into(),
+                audition_button.into(),
+            ])
+            .spacing(10)
+            .into(),
+        );
 
         let content = Column::with_children(elements).spacing(15);
 
         let tainer: EditorElement = container(content)
-            .width(Length::Units(INIT_GRID_SIZE.width as u16))
+            .width(Length::Units(INIT_GRID_SIZE.width as u16 + TRACK_HEADER_WIDTH))
             .height(Length::Units(INIT_GRID_SIZE.height as u16 * 2))
             .padding(iced::Padding::from(4))
             .center_x()
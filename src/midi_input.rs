@@ -0,0 +1,510 @@
+//! MIDI input
+//!
+//! Notes played on a midi keyboard are recorded into the armed tracks while the
+//! transport plays. A source only gives timestamped note-ons and note-offs: the
+//! editor places them on the timeline with the position of the playhead at their
+//! instant, and the recorder pairs them into the notes of a take. The device input
+//! listens to a hardware port, while the replay input plays back a list of events or
+//! a midi file, so that recording can be tried without a keyboard.
+
+use iced::time::{Duration, Instant};
+use midly::live::LiveEvent;
+use midly::MidiMessage;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::mpsc;
+
+use crate::note::midi_notes::{MidiNote, MidiNotes, Pitch};
+use crate::smf::import::{import_file, ImportError};
+use crate::tick::{Tick, NOTE_MIN_TICKS};
+use crate::timeline::LoopRegion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEventKind {
+    NoteOn { key: u8, velocity: u8 },
+    NoteOff { key: u8 },
+}
+
+impl InputEventKind {
+    // a note-on with a velocity of 0 is a note-off, and everything but notes is ignored
+    fn parse(message: &[u8]) -> Option<Self> {
+        match LiveEvent::parse(message).ok()? {
+            LiveEvent::Midi { message: MidiMessage::NoteOn { key, vel }, .. }
+                if vel.as_int() > 0 =>
+            {
+                Some(InputEventKind::NoteOn { key: key.as_int(), velocity: vel.as_int() })
+            }
+            LiveEvent::Midi {
+                message: MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. },
+                ..
+            } => Some(InputEventKind::NoteOff { key: key.as_int() }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InputEvent {
+    pub instant: Instant,
+    pub kind: InputEventKind,
+}
+
+pub trait MidiInputSource {
+    // called when a take starts at an instant, ex: after the count-in. The events
+    // received before are dropped
+    fn start(&mut self, instant: Instant);
+
+    // the events received until now, oldest first
+    fn poll(&mut self, now: Instant) -> Vec<InputEvent>;
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    NoPort,
+    Device(String),
+    Import(ImportError),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::NoPort => write!(f, "no midi input port"),
+            InputError::Device(e) => write!(f, "midi input error: {}", e),
+            InputError::Import(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+// Listens to a midi input port. The events are timestamped as they arrive, on the
+// thread of the port, and wait in a channel until they are polled.
+pub struct DeviceInput {
+    receiver: mpsc::Receiver<InputEvent>,
+    _connection: midir::MidiInputConnection<()>,
+}
+
+impl DeviceInput {
+    // the first port whose name contains port_name, or the first port
+    pub fn open(port_name: Option<&str>) -> Result<Self, InputError> {
+        let input = midir::MidiInput::new("midi_composer")
+            .map_err(|e| InputError::Device(e.to_string()))?;
+
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| match port_name {
+                Some(port_name) => {
+                    input.port_name(port).map_or(false, |name| name.contains(port_name))
+                }
+                None => true,
+            })
+            .ok_or(InputError::NoPort)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let connection = input
+            .connect(
+                &port,
+                "midi_composer input",
+                move |_, message, _| {
+                    if let Some(kind) = InputEventKind::parse(message) {
+                        let _ = sender.send(InputEvent { instant: Instant::now(), kind });
+                    }
+                },
+                (),
+            )
+            .map_err(|e| InputError::Device(e.to_string()))?;
+
+        Ok(Self { receiver, _connection: connection })
+    }
+}
+
+impl MidiInputSource for DeviceInput {
+    fn start(&mut self, _instant: Instant) {
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    fn poll(&mut self, _now: Instant) -> Vec<InputEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+// Plays back events given in seconds from the start of the take, ex: in tests
+#[derive(Debug, Clone)]
+pub struct ReplayInput {
+    // sorted by second
+    events: Vec<(f64, InputEventKind)>,
+    // the next event to give
+    next: usize,
+    start: Option<Instant>,
+}
+
+impl ReplayInput {
+    pub fn new(mut events: Vec<(f64, InputEventKind)>) -> Self {
+        events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Self { events, next: 0, start: None }
+    }
+
+    // the notes of every track of a midi file, at their time in the file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, InputError> {
+        let song = import_file(path).map_err(InputError::Import)?;
+
+        let mut events = Vec::new();
+        for note in song.tracks.iter().flat_map(|track| track.notes.notes.iter().flatten()) {
+            let (start, end) = note.to_seconds(&song.tempo_map);
+            let key = note.get_pitch();
+            events.push((start as f64, InputEventKind::NoteOn { key, velocity: note.velocity }));
+            events.push((end as f64, InputEventKind::NoteOff { key }));
+        }
+
+        Ok(Self::new(events))
+    }
+}
+
+impl MidiInputSource for ReplayInput {
+    // every take replays the events from the beginning
+    fn start(&mut self, instant: Instant) {
+        self.start = Some(instant);
+        self.next = 0;
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<InputEvent> {
+        let start = match self.start {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+
+        let mut polled = Vec::new();
+        while let Some((second, kind)) = self.events.get(self.next) {
+            let instant = start + Duration::from_secs_f64(*second);
+            if instant > now {
+                break;
+            }
+            polled.push(InputEvent { instant, kind: *kind });
+            self.next += 1;
+        }
+        polled
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    // the take is merged with the notes of the track
+    Overdub,
+    // the notes that start within the take are removed first
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordSettings {
+    pub mode: RecordMode,
+    // the starts of the recorded notes snap to the beat fraction of the grid
+    pub quantize: bool,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self { mode: RecordMode::Overdub, quantize: false }
+    }
+}
+
+// The notes of a take, and the range of ticks it covers
+#[derive(Debug, Clone)]
+pub struct Take {
+    pub notes: MidiNotes,
+    pub start: Tick,
+    pub end: Tick,
+}
+
+// Pairs the note-ons and the note-offs of a take into notes
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    // where the playhead was when the take started
+    start: Tick,
+    // the loop that playback cycles over during the take, if any
+    loop_region: Option<LoopRegion>,
+    // the start and the velocity of the keys being held
+    held: HashMap<u8, (Tick, u8)>,
+    notes: Vec<MidiNote>,
+}
+
+impl Recorder {
+    pub fn new(start: Tick, loop_region: Option<LoopRegion>) -> Self {
+        // the loop only matters if playback starts before its end
+        let loop_region = loop_region.filter(|loop_region| start < loop_region.end);
+        Self { start, loop_region, held: HashMap::new(), notes: Vec::new() }
+    }
+
+    pub fn record(&mut self, kind: InputEventKind, position: Tick) {
+        match kind {
+            InputEventKind::NoteOn { key, velocity } => {
+                // a key pressed again before being released ends its previous note
+                if let Some((start, velocity)) = self.held.remove(&key) {
+                    self.end_note(key, start, velocity, position);
+                }
+                self.held.insert(key, (position, velocity));
+            }
+            InputEventKind::NoteOff { key } => {
+                if let Some((start, velocity)) = self.held.remove(&key) {
+                    self.end_note(key, start, velocity, position);
+                }
+            }
+        }
+    }
+
+    fn end_note(&mut self, key: u8, start: Tick, velocity: u8, end: Tick) {
+        // a note held across the end of the loop ends with the loop
+        let end = match self.loop_region {
+            Some(loop_region) if end < start => loop_region.end,
+            _ => end,
+        };
+        let end = end.max(start + NOTE_MIN_TICKS);
+        self.notes.push(MidiNote::new(start, end, Pitch::new(key)).with_velocity(velocity));
+    }
+
+    // the keys still held are released where the playhead stopped. None if nothing
+    // was played
+    pub fn finish(mut self, position: Tick) -> Option<Take> {
+        let held: Vec<(u8, (Tick, u8))> = self.held.drain().collect();
+        for (key, (start, velocity)) in held {
+            self.end_note(key, start, velocity, position);
+        }
+
+        if self.notes.is_empty() {
+            return None;
+        }
+
+        // a take within a loop covers the whole loop, since every cycle was recorded
+        let (start, end) = match self.loop_region {
+            Some(loop_region) => (
+                loop_region.start.min(self.start),
+                self.notes.iter().map(|note| note.end).max().unwrap().max(loop_region.end),
+            ),
+            None => (
+                self.notes.iter().map(|note| note.start).min().unwrap().min(self.start),
+                self.notes.iter().map(|note| note.end).max().unwrap().max(position),
+            ),
+        };
+
+        Some(Take { notes: MidiNotes::from(self.notes), start, end })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::{FIRST_TICK, PPQ};
+    use crate::track::{Track, TrackMessage};
+    use crate::util::History;
+
+    // two beats per second, as at 120 bpm
+    const TICKS_PER_SECOND: f64 = 2.0 * PPQ as f64;
+
+    fn on(key: u8) -> InputEventKind {
+        InputEventKind::NoteOn { key, velocity: 100 }
+    }
+
+    fn off(key: u8) -> InputEventKind {
+        InputEventKind::NoteOff { key }
+    }
+
+    // replays the events into a recorder, placing them like the transport does: from
+    // the start, and back to the start of the loop when playback reaches its end
+    fn record(
+        events: Vec<(f64, InputEventKind)>,
+        start: Tick,
+        loop_region: Option<LoopRegion>,
+        seconds: f64,
+    ) -> Option<Take> {
+        let position_at = |seconds: f64| {
+            let position = start + (seconds * TICKS_PER_SECOND).round() as Tick;
+            match loop_region {
+                Some(loop_region) if position >= loop_region.end => {
+                    let length = loop_region.end - loop_region.start;
+                    loop_region.start + (position - loop_region.start) % length
+                }
+                _ => position,
+            }
+        };
+
+        let mut input = ReplayInput::new(events);
+        let take_start = Instant::now();
+        input.start(take_start);
+
+        let mut recorder = Recorder::new(start, loop_region);
+        let end = take_start + Duration::from_secs_f64(seconds);
+        for event in input.poll(end) {
+            recorder.record(event.kind, position_at((event.instant - take_start).as_secs_f64()));
+        }
+        recorder.finish(position_at(seconds))
+    }
+
+    fn notes(take: &Take) -> Vec<(u8, Tick, Tick)> {
+        notes_of(&take.notes)
+    }
+
+    fn notes_of(notes: &MidiNotes) -> Vec<(u8, Tick, Tick)> {
+        let mut notes: Vec<(u8, Tick, Tick)> = notes
+            .notes
+            .iter()
+            .flatten()
+            .map(|note| (note.get_pitch(), note.start, note.end))
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    fn track_notes(track: &Track) -> Vec<(u8, Tick, Tick)> {
+        let mut notes = notes_of(&track.midi_notes);
+        notes.extend(notes_of(&track.selected.notes));
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn replay_gives_the_events_due_in_order() {
+        let mut input = ReplayInput::new(vec![(1.0, off(60)), (0.0, on(60)), (2.0, on(62))]);
+        let take_start = Instant::now();
+        assert!(input.poll(take_start + Duration::from_secs(5)).is_empty());
+
+        input.start(take_start);
+        let kinds = |events: Vec<InputEvent>| -> Vec<InputEventKind> {
+            events.iter().map(|event| event.kind).collect()
+        };
+        assert_eq!(kinds(input.poll(take_start + Duration::from_millis(1500))), [on(60), off(60)]);
+        assert!(input.poll(take_start + Duration::from_millis(1500)).is_empty());
+        assert_eq!(kinds(input.poll(take_start + Duration::from_secs(3))), [on(62)]);
+
+        // every take replays from the beginning
+        input.start(take_start);
+        assert_eq!(input.poll(take_start + Duration::from_secs(3)).len(), 3);
+    }
+
+    #[test]
+    fn pairs_note_ons_and_note_offs() {
+        let take =
+            record(vec![(0.5, on(60)), (1.0, off(60)), (1.0, on(64))], FIRST_TICK, None, 2.0)
+                .unwrap();
+
+        // a key still held when the take ends is released there
+        assert_eq!(
+            notes(&take),
+            [
+                (60, FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ),
+                (64, FIRST_TICK + 2 * PPQ, FIRST_TICK + 4 * PPQ)
+            ]
+        );
+        assert_eq!((take.start, take.end), (FIRST_TICK, FIRST_TICK + 4 * PPQ));
+    }
+
+    #[test]
+    fn a_key_pressed_again_while_held_ends_its_note() {
+        let events = vec![(0.0, on(60)), (0.5, on(60)), (1.0, off(60)), (1.5, off(60))];
+        let take = record(events, FIRST_TICK, None, 2.0).unwrap();
+        assert_eq!(
+            notes(&take),
+            [(60, FIRST_TICK, FIRST_TICK + PPQ), (60, FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ)]
+        );
+    }
+
+    #[test]
+    fn a_note_held_across_the_loop_end_ends_with_the_loop() {
+        let loop_region = LoopRegion::new(FIRST_TICK, FIRST_TICK + 4 * PPQ);
+        // pressed on the fourth beat, released on the second beat of the next cycle
+        let take =
+            record(vec![(1.5, on(60)), (2.5, off(60))], FIRST_TICK, loop_region, 3.0).unwrap();
+
+        assert_eq!(notes(&take), [(60, FIRST_TICK + 3 * PPQ, FIRST_TICK + 4 * PPQ)]);
+        // the take covers the whole loop
+        assert_eq!((take.start, take.end), (FIRST_TICK, FIRST_TICK + 4 * PPQ));
+    }
+
+    #[test]
+    fn nothing_played_is_no_take() {
+        assert!(record(Vec::new(), FIRST_TICK, None, 2.0).is_none());
+    }
+
+    fn track_with_note(history: &mut History) -> Track {
+        let mut track = Track::new(0);
+        let note = MidiNote::new(FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ, Pitch::new(67));
+        track.update(&TrackMessage::AddManyNotes { notes: MidiNotes::from(vec![note]) }, history);
+        track
+    }
+
+    fn record_take(
+        track: &mut Track,
+        take: Take,
+        mode: RecordMode,
+        quantize: bool,
+        history: &mut History,
+    ) {
+        let message = TrackMessage::RecordTake {
+            notes: take.notes,
+            start: take.start,
+            end: take.end,
+            mode,
+            quantize,
+        };
+        track.update(&message, history);
+    }
+
+    #[test]
+    fn overdub_keeps_the_notes_of_the_track() {
+        let mut history = History::default();
+        let mut track = track_with_note(&mut history);
+        let before = track_notes(&track);
+
+        let take = record(vec![(0.0, on(60)), (1.0, off(60))], FIRST_TICK, None, 1.0).unwrap();
+        record_take(&mut track, take, RecordMode::Overdub, false, &mut history);
+        assert_eq!(
+            track_notes(&track),
+            [(60, FIRST_TICK, FIRST_TICK + 2 * PPQ), (67, FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ)]
+        );
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(track_notes(&track), before);
+    }
+
+    #[test]
+    fn replace_removes_the_notes_starting_within_the_take() {
+        let mut history = History::default();
+        let mut track = track_with_note(&mut history);
+        let before = track_notes(&track);
+
+        let take = record(vec![(0.0, on(60)), (1.0, off(60))], FIRST_TICK, None, 1.0).unwrap();
+        record_take(&mut track, take, RecordMode::Replace, false, &mut history);
+        assert_eq!(track_notes(&track), [(60, FIRST_TICK, FIRST_TICK + 2 * PPQ)]);
+
+        // undo restores the track as it was, and redo records the take again
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(track_notes(&track), before);
+        track.update(&TrackMessage::Redo, &mut history);
+        assert_eq!(track_notes(&track), [(60, FIRST_TICK, FIRST_TICK + 2 * PPQ)]);
+    }
+
+    #[test]
+    fn quantize_on_record_snaps_the_starts() {
+        let mut history = History::default();
+        let mut track = Track::new(0);
+        let period = track.grid.beat_fraction_ticks();
+
+        // a little late on the second beat
+        let take = record(vec![(0.52, on(60)), (0.9, off(60))], FIRST_TICK, None, 1.0).unwrap();
+        let played = notes(&take)[0];
+        record_take(&mut track, take, RecordMode::Overdub, true, &mut history);
+
+        let recorded = track_notes(&track);
+        assert_eq!(recorded.len(), 1);
+        let (pitch, start, end) = recorded[0];
+        assert_eq!(pitch, 60);
+        assert_eq!(start, FIRST_TICK + PPQ);
+        assert_eq!(start % period, 0);
+        // the length is kept
+        assert_eq!(end - start, played.2 - played.1);
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert!(track_notes(&track).is_empty());
+    }
+}
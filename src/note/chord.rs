@@ -0,0 +1,390 @@
+//! Chords
+//!
+//! Names the chord formed by a set of pitches, ex: "Am7/G" for A, C, E and G over a G
+//! in the bass. Only the pitch classes matter, so the voicing can be anything, and the
+//! fifth can be left out of the chords of four notes or more. When a set of pitches has
+//! more than one name, like Am7 and C6, the chord whose root is in the bass wins, then
+//! the simpler one.
+//!
+//! The analyzer cuts a range of ticks into windows and names the notes that sound for
+//! at least half of each window. Consecutive windows with the same chord are merged.
+
+use std::fmt;
+
+use crate::note::midi_notes::MidiNote;
+use crate::note::scale::{Scale, NOTE_LABELS};
+use crate::tick::Tick;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Power,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+    MinorMajor7,
+    Sus4Dominant7,
+    Major6,
+    Minor6,
+    Add9,
+    MinorAdd9,
+    Dominant9,
+    Major9,
+    Minor9,
+}
+
+impl ChordQuality {
+    // from the simplest to the most complex, which is the order of preference
+    pub const ALL: [ChordQuality; 21] = [
+        ChordQuality::Major,
+        ChordQuality::Minor,
+        ChordQuality::Diminished,
+        ChordQuality::Augmented,
+        ChordQuality::Sus2,
+        ChordQuality::Sus4,
+        ChordQuality::Power,
+        ChordQuality::Dominant7,
+        ChordQuality::Major7,
+        ChordQuality::Minor7,
+        ChordQuality::HalfDiminished7,
+        ChordQuality::Diminished7,
+        ChordQuality::MinorMajor7,
+        ChordQuality::Sus4Dominant7,
+        ChordQuality::Major6,
+        ChordQuality::Minor6,
+        ChordQuality::Add9,
+        ChordQuality::MinorAdd9,
+        ChordQuality::Dominant9,
+        ChordQuality::Major9,
+        ChordQuality::Minor9,
+    ];
+
+    // in semitones above the root, within an octave
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus2 => &[0, 2, 7],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Power => &[0, 7],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+            ChordQuality::MinorMajor7 => &[0, 3, 7, 11],
+            ChordQuality::Sus4Dominant7 => &[0, 5, 7, 10],
+            ChordQuality::Major6 => &[0, 4, 7, 9],
+            ChordQuality::Minor6 => &[0, 3, 7, 9],
+            ChordQuality::Add9 => &[0, 2, 4, 7],
+            ChordQuality::MinorAdd9 => &[0, 2, 3, 7],
+            ChordQuality::Dominant9 => &[0, 2, 4, 7, 10],
+            ChordQuality::Major9 => &[0, 2, 4, 7, 11],
+            ChordQuality::Minor9 => &[0, 2, 3, 7, 10],
+        }
+    }
+
+    // the intervals to write the chord with, from the lowest, the ninth being above the
+    // octave
+    pub fn voicing(&self) -> Vec<u8> {
+        let has_ninth = matches!(
+            self,
+            ChordQuality::Add9
+                | ChordQuality::MinorAdd9
+                | ChordQuality::Dominant9
+                | ChordQuality::Major9
+                | ChordQuality::Minor9
+        );
+        let mut voicing: Vec<u8> = self
+            .intervals()
+            .iter()
+            .map(|interval| if has_ninth && *interval == 2 { 14 } else { *interval })
+            .collect();
+        voicing.sort_unstable();
+        voicing
+    }
+
+    // what follows the root in the name of the chord
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Sus2 => "sus2",
+            ChordQuality::Sus4 => "sus4",
+            ChordQuality::Power => "5",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+            ChordQuality::MinorMajor7 => "m(maj7)",
+            ChordQuality::Sus4Dominant7 => "7sus4",
+            ChordQuality::Major6 => "6",
+            ChordQuality::Minor6 => "m6",
+            ChordQuality::Add9 => "add9",
+            ChordQuality::MinorAdd9 => "madd9",
+            ChordQuality::Dominant9 => "9",
+            ChordQuality::Major9 => "maj9",
+            ChordQuality::Minor9 => "m9",
+        }
+    }
+
+    // the pitch classes above the root as bits, the root being the lowest bit
+    fn mask(&self) -> u16 {
+        self.intervals().iter().fold(0, |mask, interval| mask | 1 << interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    // pitch classes, from 0 for C to 11 for B
+    pub root: u8,
+    pub quality: ChordQuality,
+    pub bass: u8,
+}
+
+impl Chord {
+    // the name with the spelling of the key of a scale, ex: "Bbmaj7" in F major
+    pub fn name(&self, scale: &Scale) -> String {
+        let mut name = format!("{}{}", scale.pitch_class_label(self.root), self.quality.suffix());
+        if self.bass != self.root {
+            name.push('/');
+            name.push_str(&scale.pitch_class_label(self.bass));
+        }
+        name
+    }
+}
+
+// the name with sharps
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", NOTE_LABELS[self.root as usize], self.quality.suffix())?;
+        if self.bass != self.root {
+            write!(f, "/{}", NOTE_LABELS[self.bass as usize])?;
+        }
+        Ok(())
+    }
+}
+
+// None for fewer than two pitch classes, or for a set of pitches that is not a chord
+pub fn identify(pitches: &[u8]) -> Option<Chord> {
+    let bass = pitches.iter().min()? % 12;
+    let pitch_classes: u16 = pitches.iter().fold(0, |mask, pitch| mask | 1 << (pitch % 12));
+    if pitch_classes.count_ones() < 2 {
+        return None;
+    }
+
+    // the preference of a match: exact, then with the root in the bass, then simple
+    let mut best: Option<((bool, bool, isize), Chord)> = None;
+
+    for root in (0..12).filter(|root| pitch_classes & 1 << root != 0) {
+        // the pitch classes above the root
+        let relative = (pitch_classes >> root | pitch_classes << (12 - root)) & 0xfff;
+
+        for (index, quality) in ChordQuality::ALL.iter().enumerate() {
+            let mask = quality.mask();
+            let is_exact = relative == mask;
+            let without_fifth = mask.count_ones() >= 4 && relative == mask & !(1 << 7);
+            if !is_exact && !without_fifth {
+                continue;
+            }
+
+            let preference = (is_exact, root == bass, -(index as isize));
+            if best.map_or(true, |(best_preference, _)| preference > best_preference) {
+                best = Some((preference, Chord { root, quality: *quality, bass }));
+            }
+        }
+    }
+
+    best.map(|(_, chord)| chord)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChordSpan {
+    pub start: Tick,
+    pub end: Tick,
+    pub chord: Chord,
+}
+
+// the chords of the notes between start and end. The windows are aligned on multiples
+// of their length, so that they do not depend on where the range starts
+pub fn analyze<'a>(
+    notes: impl IntoIterator<Item = &'a MidiNote>,
+    start: Tick,
+    end: Tick,
+    window: Tick,
+) -> Vec<ChordSpan> {
+    let window = window.max(1);
+    let notes: Vec<&MidiNote> =
+        notes.into_iter().filter(|note| note.start < end && note.end > start).collect();
+
+    let mut spans: Vec<ChordSpan> = Vec::new();
+    let mut window_start = start.div_euclid(window) * window;
+    while window_start < end {
+        let window_end = window_start + window;
+
+        let pitches: Vec<u8> = notes
+            .iter()
+            .filter(|note| note.end.min(window_end) - note.start.max(window_start) >= window / 2)
+            .map(|note| note.get_pitch())
+            .collect();
+
+        if let Some(chord) = identify(&pitches) {
+            match spans.last_mut() {
+                Some(span) if span.end == window_start && span.chord == chord => {
+                    span.end = window_end
+                }
+                _ => spans.push(ChordSpan { start: window_start, end: window_end, chord }),
+            }
+        }
+
+        window_start = window_end;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::midi_notes::Pitch;
+    use crate::note::scale::ScaleType;
+    use crate::tick::PPQ;
+
+    fn name(pitches: &[u8]) -> Option<String> {
+        identify(pitches).map(|chord| chord.to_string())
+    }
+
+    fn note(start: Tick, end: Tick, pitch: u8) -> MidiNote {
+        MidiNote::new(start, end, Pitch::new(pitch))
+    }
+
+    #[test]
+    fn identifies_root_position_chords() {
+        let cases: [(&[u8], &str); 8] = [
+            (&[60, 64, 67], "C"),
+            (&[57, 60, 64], "Am"),
+            (&[59, 62, 65], "Bdim"),
+            (&[60, 64, 68], "Caug"),
+            (&[60, 67], "C5"),
+            (&[57, 60, 64, 67], "Am7"),
+            (&[59, 62, 65, 69], "Bm7b5"),
+            (&[60, 64, 67, 74], "Cadd9"),
+        ];
+        for (pitches, expected) in cases {
+            assert_eq!(name(pitches).as_deref(), Some(expected), "{:?}", pitches);
+        }
+    }
+
+    #[test]
+    fn identifies_inversions_by_the_bass() {
+        let cases: [(&[u8], &str); 4] = [
+            (&[55, 57, 60, 64], "Am7/G"),
+            (&[64, 67, 72], "C/E"),
+            (&[67, 72, 76], "C/G"),
+            // the voicing does not matter, only the pitch classes and the bass
+            (&[43, 69, 72, 76], "Am7/G"),
+        ];
+        for (pitches, expected) in cases {
+            assert_eq!(name(pitches).as_deref(), Some(expected), "{:?}", pitches);
+        }
+    }
+
+    #[test]
+    fn prefers_the_root_in_the_bass() {
+        // the same pitch classes are Am7 and C6
+        assert_eq!(name(&[57, 60, 64, 67]).as_deref(), Some("Am7"));
+        assert_eq!(name(&[60, 64, 67, 69]).as_deref(), Some("C6"));
+        // with neither root in the bass, the simpler quality wins
+        assert_eq!(name(&[52, 57, 60, 67]).as_deref(), Some("Am7/E"));
+    }
+
+    #[test]
+    fn identifies_sevenths_without_their_fifth() {
+        assert_eq!(name(&[60, 64, 70]).as_deref(), Some("C7"));
+        assert_eq!(name(&[60, 64, 71]).as_deref(), Some("Cmaj7"));
+        assert_eq!(name(&[57, 60, 67]).as_deref(), Some("Am7"));
+        // a triad without its fifth is not a chord
+        assert_eq!(name(&[60, 64]), None);
+    }
+
+    #[test]
+    fn needs_two_pitch_classes() {
+        assert_eq!(name(&[]), None);
+        assert_eq!(name(&[60]), None);
+        assert_eq!(name(&[48, 60, 72]), None);
+        assert_eq!(name(&[60, 61, 62]), None);
+    }
+
+    #[test]
+    fn spells_names_in_the_key_of_the_scale() {
+        let f_major = Scale::new(ScaleType::Major, 5);
+        let chord = identify(&[58, 62, 65, 69]).unwrap();
+        assert_eq!(chord.to_string(), "A#maj7");
+        assert_eq!(chord.name(&f_major), "Bbmaj7");
+    }
+
+    #[test]
+    fn aligns_windows_on_multiples_of_their_length() {
+        let notes = [note(0, 2 * PPQ, 57), note(0, 2 * PPQ, 60), note(0, 2 * PPQ, 64)];
+
+        // a range starting inside a window starts with the whole window
+        let spans = analyze(&notes, PPQ / 2, 2 * PPQ, PPQ);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end), (0, 2 * PPQ));
+    }
+
+    #[test]
+    fn counts_notes_sounding_for_half_a_window() {
+        let notes = [
+            note(0, PPQ, 60),
+            note(0, PPQ, 64),
+            // half of the window
+            note(PPQ / 2, PPQ, 67),
+            // less than half of the window
+            note(PPQ / 2 + 1, PPQ, 70),
+        ];
+        let spans = analyze(&notes, 0, PPQ, PPQ);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].chord.to_string(), "C");
+    }
+
+    #[test]
+    fn merges_consecutive_windows_of_the_same_chord() {
+        let notes = [
+            note(0, 2 * PPQ, 57),
+            note(0, 2 * PPQ, 60),
+            note(0, 2 * PPQ, 64),
+            // slightly early, which does not count in the second window
+            note(2 * PPQ - 20, 4 * PPQ, 53),
+            note(2 * PPQ, 4 * PPQ, 57),
+            note(2 * PPQ, 4 * PPQ, 60),
+            // a beat of silence, then A minor again
+            note(5 * PPQ, 6 * PPQ, 57),
+            note(5 * PPQ, 6 * PPQ, 60),
+            note(5 * PPQ, 6 * PPQ, 64),
+        ];
+        let spans: Vec<(Tick, Tick, String)> = analyze(&notes, 0, 6 * PPQ, PPQ)
+            .iter()
+            .map(|span| (span.start, span.end, span.chord.to_string()))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 2 * PPQ, "Am".to_string()),
+                (2 * PPQ, 4 * PPQ, "F".to_string()),
+                (5 * PPQ, 6 * PPQ, "Am".to_string()),
+            ]
+        );
+    }
+}
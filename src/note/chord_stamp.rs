@@ -0,0 +1,281 @@
+//! Chord stamp
+//!
+//! Builds the chord that a click writes instead of a single note. Triads, sevenths,
+//! sus and add9 chords stack degrees of the scale of the track on the clicked pitch,
+//! so that in C major a click on D writes D minor and a click on G writes G major. On
+//! the chromatic scale, which has no thirds to stack, they are major chords. A custom
+//! voicing is the chord of a quality, the same on every pitch, and a saved voicing is
+//! made from notes selected by the user, kept in a file shared by every project like
+//! the custom scales.
+//!
+//! The inversion moves the lowest notes up an octave, one per inversion, and the spread
+//! opens the voicing once inverted. Notes that would leave the midi range are dropped.
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::note::chord::ChordQuality;
+use crate::note::scale::Scale;
+use crate::note::transpose::Interval;
+
+// A voicing saved by the user, which the chord picker offers after the custom voicings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedVoicing {
+    pub name: String,
+    // semitones above the lowest note, starting with 0
+    pub intervals: Vec<u8>,
+}
+
+impl SavedVoicing {
+    // None with less than two different pitches, which make no chord
+    pub fn new(name: String, pitches: impl IntoIterator<Item = u8>) -> Option<Self> {
+        let mut pitches: Vec<u8> = pitches.into_iter().collect();
+        pitches.sort();
+        pitches.dedup();
+
+        let lowest = *pitches.first()?;
+        let intervals: Vec<u8> = pitches.iter().map(|pitch| pitch - lowest).collect();
+        if intervals.len() < 2 {
+            return None;
+        }
+        Some(Self { name, intervals })
+    }
+}
+
+// A missing file has no voicings
+pub fn load_voicings(path: impl AsRef<Path>) -> io::Result<Vec<SavedVoicing>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn save_voicings(path: impl AsRef<Path>, voicings: &[SavedVoicing]) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(voicings)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, json)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StampChord {
+    Triad,
+    Seventh,
+    Sus2,
+    Sus4,
+    Add9,
+    // the intervals of a quality, whatever the scale
+    Custom(ChordQuality),
+    Saved(SavedVoicing),
+}
+
+impl StampChord {
+    // the chords built from the scale, then the custom voicings, then the saved ones
+    pub fn all(voicings: &[SavedVoicing]) -> Vec<StampChord> {
+        [
+            StampChord::Triad,
+            StampChord::Seventh,
+            StampChord::Sus2,
+            StampChord::Sus4,
+            StampChord::Add9,
+        ]
+        .into_iter()
+        .chain(ChordQuality::ALL.iter().map(|quality| StampChord::Custom(*quality)))
+        .chain(voicings.iter().cloned().map(StampChord::Saved))
+        .collect()
+    }
+
+    // the chord that follows this one in all(), or precedes it
+    pub fn step(&self, step: isize, voicings: &[SavedVoicing]) -> StampChord {
+        let all = StampChord::all(voicings);
+        let index = all.iter().position(|chord| chord == self).unwrap_or(0) as isize;
+        all[(index + step).rem_euclid(all.len() as isize) as usize].clone()
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            StampChord::Triad => "Triad".to_string(),
+            StampChord::Seventh => "Seventh".to_string(),
+            StampChord::Sus2 => "Sus2".to_string(),
+            StampChord::Sus4 => "Sus4".to_string(),
+            StampChord::Add9 => "Add9".to_string(),
+            StampChord::Custom(ChordQuality::Major) => "Custom maj".to_string(),
+            StampChord::Custom(quality) => format!("Custom {}", quality.suffix()),
+            StampChord::Saved(voicing) => voicing.name.clone(),
+        }
+    }
+
+    // the number of notes of the chord, before any leaves the midi range
+    pub fn size(&self) -> usize {
+        self.intervals().len()
+    }
+
+    // the degrees of the scale above the root, for the chords built from the scale
+    fn degrees(&self) -> Option<&'static [i8]> {
+        match self {
+            StampChord::Triad => Some(&[0, 2, 4]),
+            StampChord::Seventh => Some(&[0, 2, 4, 6]),
+            StampChord::Sus2 => Some(&[0, 1, 4]),
+            StampChord::Sus4 => Some(&[0, 3, 4]),
+            StampChord::Add9 => Some(&[0, 2, 4, 8]),
+            StampChord::Custom(_) | StampChord::Saved(_) => None,
+        }
+    }
+
+    // the semitones above the root when there are no degrees to stack
+    fn intervals(&self) -> Vec<u8> {
+        let quality = match self {
+            StampChord::Triad => ChordQuality::Major,
+            StampChord::Seventh => ChordQuality::Dominant7,
+            StampChord::Sus2 => ChordQuality::Sus2,
+            StampChord::Sus4 => ChordQuality::Sus4,
+            StampChord::Add9 => ChordQuality::Add9,
+            StampChord::Custom(quality) => *quality,
+            StampChord::Saved(voicing) => return voicing.intervals.clone(),
+        };
+        quality.voicing()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spread {
+    Close,
+    // the second highest note goes down an octave
+    Drop2,
+    // every other note from the bottom goes up an octave, ex: C-G-E for C-E-G
+    Open,
+}
+
+impl Spread {
+    pub const ALL: [Spread; 3] = [Spread::Close, Spread::Drop2, Spread::Open];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Spread::Close => "Close",
+            Spread::Drop2 => "Drop 2",
+            Spread::Open => "Open",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordStamp {
+    pub chord: StampChord,
+    // 0 for the root position, 1 for the first inversion, and so on
+    pub inversion: u8,
+    pub spread: Spread,
+}
+
+impl Default for ChordStamp {
+    fn default() -> Self {
+        Self { chord: StampChord::Triad, inversion: 0, spread: Spread::Close }
+    }
+}
+
+impl ChordStamp {
+    // the last inversion before the chord is back in root position an octave higher
+    pub fn max_inversion(&self) -> u8 {
+        self.chord.size().saturating_sub(1).min(u8::MAX as usize) as u8
+    }
+
+    // the pitches of the chord built on a pitch, from the lowest to the highest
+    pub fn pitches(&self, pitch: u8, scale: &Scale) -> Vec<u8> {
+        let mut pitches: Vec<i16> = match self.chord.degrees() {
+            Some(degrees) if scale.size() < 12 => degrees
+                .iter()
+                .map(|degree| Interval::Degrees(*degree).transpose(pitch, scale))
+                .filter(|(_, is_clamped)| !is_clamped)
+                .map(|(pitch, _)| pitch as i16)
+                .collect(),
+            _ => self
+                .chord
+                .intervals()
+                .iter()
+                .map(|interval| pitch as i16 + *interval as i16)
+                .collect(),
+        };
+
+        if pitches.len() > 1 {
+            for _ in 0..self.inversion as usize % pitches.len() {
+                let lowest = pitches.remove(0);
+                pitches.push(lowest + 12);
+                pitches.sort_unstable();
+            }
+        }
+
+        match self.spread {
+            Spread::Close => {}
+            Spread::Drop2 => {
+                if pitches.len() > 2 {
+                    let index = pitches.len() - 2;
+                    pitches[index] -= 12;
+                }
+            }
+            Spread::Open => {
+                for pitch in pitches.iter_mut().skip(1).step_by(2) {
+                    *pitch += 12;
+                }
+            }
+        }
+
+        pitches.sort_unstable();
+        pitches.dedup();
+        pitches
+            .into_iter()
+            .filter(|pitch| (0..128).contains(pitch))
+            .map(|pitch| pitch as u8)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::scale::ScaleType;
+
+    fn stamp(chord: StampChord, inversion: u8) -> ChordStamp {
+        ChordStamp { chord, inversion, spread: Spread::Close }
+    }
+
+    fn voicing() -> SavedVoicing {
+        SavedVoicing::new("Voicing 1".to_string(), [64, 60, 71, 60]).unwrap()
+    }
+
+    #[test]
+    fn saved_voicings_are_the_intervals_above_the_lowest_note() {
+        assert_eq!(voicing().intervals, [0, 4, 11]);
+        assert_eq!(SavedVoicing::new("Unison".to_string(), [60, 60]), None);
+        assert_eq!(SavedVoicing::new("Nothing".to_string(), []), None);
+    }
+
+    #[test]
+    fn stamps_a_saved_voicing_on_any_pitch_and_scale() {
+        let saved = StampChord::Saved(voicing());
+        let major = Scale::new(ScaleType::Major, 0);
+
+        assert_eq!(stamp(saved.clone(), 0).pitches(62, &major), [62, 66, 73]);
+        assert_eq!(stamp(saved, 1).pitches(62, &major), [66, 73, 74]);
+    }
+
+    #[test]
+    fn inversions_stop_before_the_root_position() {
+        assert_eq!(stamp(StampChord::Triad, 0).max_inversion(), 2);
+        assert_eq!(stamp(StampChord::Add9, 0).max_inversion(), 3);
+        assert_eq!(stamp(StampChord::Custom(ChordQuality::Dominant7), 0).max_inversion(), 3);
+        assert_eq!(stamp(StampChord::Saved(voicing()), 0).max_inversion(), 2);
+    }
+
+    #[test]
+    fn steps_through_the_saved_voicings_after_the_custom_ones() {
+        let voicings = [voicing()];
+        let last_custom = StampChord::Custom(*ChordQuality::ALL.last().unwrap());
+
+        assert_eq!(last_custom.step(1, &voicings), StampChord::Saved(voicing()));
+        assert_eq!(StampChord::Saved(voicing()).step(1, &voicings), StampChord::Triad);
+        assert_eq!(StampChord::Triad.step(-1, &[]), last_custom);
+    }
+}
@@ -1,29 +1,34 @@
 //! midi notes
 
 use crate::grid::Grid;
-use crate::track::TimingInfo;
+use crate::smf::tempo_map::TempoMap;
 
 // use iced::keyboard::Modifiers;
 use iced::widget::canvas::event::{self};
 use iced::widget::canvas::{Cache, Cursor, Geometry, Path, Stroke};
 use iced::{Color, Point, Rectangle, Size, Vector, keyboard::Modifiers};
 
+use serde::{Deserialize, Serialize};
+
 use std::fmt;
 
 use super::scale::Scale;
-use crate::config::{BEAT_SIZE, NOTE_LABELS, RESIZE_BOX_PIXEL_WIDTH, NOTE_MIN_SIZE};
+use crate::config::{BEAT_SIZE, DEFAULT_VELOCITY, NOTE_LABELS, RESIZE_BOX_PIXEL_WIDTH};
 use crate::piano_theme::TrackTheme;
+use crate::tick::{self, Tick, FIRST_TICK, NOTE_MIN_TICKS, PPQ};
 use crate::track::{AddMode, Pending, TrackMessage};
 
 use crate::track::actions::{AddedNote, ConflictHistory, DeletedNote, ResizedConflicts};
 
-#[derive(Clone)]
+// saved as a flat list of notes, which is rebuilt into the pitch vectors when loaded
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<MidiNote>", into = "Vec<MidiNote>")]
 pub struct MidiNotes {
     // organized by pitch and then by time
     pub notes: Vec<Vec<MidiNote>>,
     pub number_of_notes: usize,
-    pub start_time: f32, // TODO: keep track of start and end time when adding/deleting notes
-    pub end_time: f32,
+    pub start_time: Tick, // TODO: keep track of start and end time when adding/deleting notes
+    pub end_time: Tick,
 }
 
 fn is_sorted<I>(data: I) -> bool
@@ -57,7 +62,7 @@ impl fmt::Debug for MidiNotes {
         non_empty_pitch_vecs.reverse();
         let mut debug_string = String::new();
         non_empty_pitch_vecs.iter().for_each(|v| {
-            let starts = v.iter().map(|n| n.start).collect::<Vec<f32>>();
+            let starts = v.iter().map(|n| n.start).collect::<Vec<Tick>>();
             //
             //
             let pitch = (v[0].pitch.get() as i16) as usize;
@@ -89,13 +94,17 @@ impl MidiNotes {
         for _ in 0..128 {
             notes.push(Vec::new());
         }
-        Self { notes, start_time: 1.0, end_time: 5.0, number_of_notes: 0 }
+        Self { notes, start_time: FIRST_TICK, end_time: 5 * PPQ, number_of_notes: 0 }
     }
 
     pub fn clear(&mut self) {
         *self = Self::new();
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.notes.iter().all(|notes_in_pitch| notes_in_pitch.is_empty())
+    }
+
     // TODO: return Option
     pub fn get(&self, note_index: NoteIndex) -> &MidiNote {
         &self.notes[note_index.pitch_index][note_index.time_index]
@@ -162,7 +171,7 @@ impl MidiNotes {
 
     pub fn sort(&mut self) {
         for notes in self.notes.iter_mut() {
-            notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+            notes.sort_by(|a, b| a.start.cmp(&b.start));
         }
     }
 
@@ -414,8 +423,8 @@ impl MidiNotes {
 
         for i in time_index..self.notes[pitch].len() {
             let curr = &mut self.notes[pitch][i];
-            // if no overlap, then break
-            if note.end < curr.start {
+            // if no overlap, then break. Notes that touch do not overlap.
+            if note.end <= curr.start {
                 break;
             }
 
@@ -515,7 +524,14 @@ impl MidiNotes {
             for row in region.rows() {
 
                 // let pitch_relative_to_grid = row as usize;
-                let pitch_relative_to_grid =  grid.scale.midi_range[row as usize] as usize;
+                // the rows below and above the grid have no pitch
+                let pitch_relative_to_grid = match usize::try_from(row)
+                    .ok()
+                    .and_then(|row| grid.scale.midi_range.get(row))
+                {
+                    Some(pitch) => *pitch as usize,
+                    None => continue,
+                };
 
                 let maybe_note_vec = self.notes.get(pitch_relative_to_grid);
 
@@ -526,15 +542,15 @@ impl MidiNotes {
 
                 for note in maybe_note_vec.unwrap().iter() {
                     //
-                    let pos = Point::new(note.start as f32, row as f32);
-                    let note_len = note.end - note.start;
+                    let pos = Point::new(tick::to_beats(note.start), row as f32);
+                    let note_len = tick::to_beats(note.end - note.start);
 
                     if !is_selected {
                         color = theme.note;
                     } 
 
-                    let pos2 = Point::new(note.start as f32, pitch_relative_to_grid as f32);
-                    let note_rect = Rectangle::new(pos2, Size::new(note_len as f32, 1.0));
+                    let pos2 = Point::new(tick::to_beats(note.start), pitch_relative_to_grid as f32);
+                    let note_rect = Rectangle::new(pos2, Size::new(note_len, 1.0));
 
 
                     if let Some(projected_cursor) = maybe_projected_cursor {
@@ -547,10 +563,14 @@ impl MidiNotes {
                         }
                     }
 
-                    frame.fill_rectangle(pos, Size::new(note_len as f32, 1.0), color);
+                    // louder notes are more opaque
+                    let mut velocity_color = color;
+                    velocity_color.a *= velocity_opacity(note.velocity);
+
+                    frame.fill_rectangle(pos, Size::new(note_len, 1.0), velocity_color);
 
                     frame.stroke(
-                        &Path::rectangle(pos, Size::new(note_len as f32, 1.0)),
+                        &Path::rectangle(pos, Size::new(note_len, 1.0)),
                         Stroke::default().with_width(0.8).with_color(theme.note_contour),
                     );
 
@@ -579,6 +599,25 @@ impl MidiNotes {
                     // }
                 }
             }
+
+            // the notes out of the scale have no row, so they are drawn halfway between the
+            // rows of the scale notes around them, with a warning contour. The notes below
+            // the lowest pitch of the scale are drawn from row -1
+            for row in region.rows() {
+                for note in self.notes[grid.scale.pitches_above_row(row)].iter().flatten() {
+                    let pos = Point::new(tick::to_beats(note.start), row as f32 + 0.5);
+                    let size = Size::new(tick::to_beats(note.end - note.start), 1.0);
+
+                    let mut velocity_color = if is_selected { theme.selected_note } else { theme.note };
+                    velocity_color.a *= velocity_opacity(note.velocity);
+
+                    frame.fill_rectangle(pos, size, velocity_color);
+                    frame.stroke(
+                        &Path::rectangle(pos, size),
+                        Stroke::default().with_width(1.5).with_color(theme.out_of_key_note),
+                    );
+                }
+            }
         });
         notes
     }
@@ -703,13 +742,13 @@ impl MidiNotes {
     // }
 
     // returns the delta time and delta pitch
-    pub fn drag_all_notes(&mut self, delta_cursor: Vector, grid: &Grid) -> (f32, i8) {
+    pub fn drag_all_notes(&mut self, delta_cursor: Vector, grid: &Grid) -> (Tick, i8) {
         // Maybe we should keep track of the minimum start time somewhere to avoid this big O(n) search
         let note_with_minimum_start = self
             .notes
             .iter()
             .flatten()
-            .min_by(|a, b| a.start.partial_cmp(&b.start).unwrap())
+            .min_by_key(|note| note.start)
             .unwrap()
             .clone();
 
@@ -721,7 +760,9 @@ impl MidiNotes {
         let mut note_with_maximum_pitch =
             self.notes.iter().rev().find(|v| !v.is_empty()).unwrap().first().unwrap().clone();
 
-        let new_start = note_with_minimum_start.get_new_start(delta_cursor);
+        // the cursor moves in beats, and the notes in ticks
+        let mut delta_time = tick::from_beats(delta_cursor.x);
+        let new_start = note_with_minimum_start.get_new_start(delta_time);
 
         // get the pitch index relative to the current music scale
         let min_scaled_pitch = note_with_minimum_pitch.get_scaled_pitch(&grid.scale);
@@ -730,14 +771,13 @@ impl MidiNotes {
         // the grid is scaled such that a unit in the x direction is equal to a beat
         // and a unit in the y direction is equal to a pitch
         let mut delta_pitch = delta_cursor.y as i8;
-        let mut delta_time = delta_cursor.x;
 
         // time
         //
-        // if the minimum start is moved below 1.0 (the start beat of the grid),
+        // if the minimum start is moved below FIRST_TICK (the start of the grid),
         // then block it there
-        if new_start < 1.0 {
-            delta_time = 1.0 - note_with_minimum_start.start;
+        if new_start < FIRST_TICK {
+            delta_time = FIRST_TICK - note_with_minimum_start.start;
         }
         // let delta_times = ResizedEdges::splat(delta_time);
 
@@ -775,8 +815,26 @@ impl MidiNotes {
         return (delta_time, delta_pitch);
     }
 
+    // moves all notes in time, without moving the first one before the start of the grid,
+    // and returns the delta time that was applied
+    pub fn shift_all_notes(&mut self, delta_time: Tick) -> Tick {
+        let minimum_start = match self.notes.iter().flatten().map(|note| note.start).min() {
+            Some(minimum_start) => minimum_start,
+            None => return 0,
+        };
+        let delta_time = delta_time.max(FIRST_TICK - minimum_start);
+
+        for note in self.notes.iter_mut().flatten() {
+            note.start += delta_time;
+            note.end += delta_time;
+        }
+        self.start_time = minimum_start + delta_time;
+
+        delta_time
+    }
+
     // TODO: optimize this.
-    pub fn resize_all_notes(&mut self,  delta_time: f32, resize_end: NoteEdge, on_index: NoteIndex) -> ResizedEdgePercent {
+    pub fn resize_all_notes(&mut self,  delta_time: Tick, resize_end: NoteEdge, on_index: NoteIndex) -> ResizedEdgePercent {
         //
         // resize the note that was clicked on first, and compute the % of change in length
         // so that we can resize all other notes by the same amount
@@ -818,8 +876,8 @@ impl MidiNotes {
 
                 let num = 0.1;
                 if note.pitch.get() == pitch.get()
-                    && time >= note.start - num
-                    && time <= note.end + num
+                    && time >= tick::to_beats(note.start) - num
+                    && time <= tick::to_beats(note.end) + num
                 {
                     return true;
                 }
@@ -832,19 +890,30 @@ impl MidiNotes {
     pub fn get_note_under_cursor(&self, grid: &Grid, projected_cursor: Point) -> Option<OverNote> {
         let mut resize_end = NoteEdge::None;
 
+        // the notes are compared in rows, where the notes out of the scale are drawn
+        let cursor_pitch = projected_cursor.y.floor();
+        if !(0.0..128.0).contains(&cursor_pitch) {
+            return None;
+        }
+        let projected_cursor = Point::new(
+            projected_cursor.x,
+            grid.scale.row(cursor_pitch as u8) + projected_cursor.y - cursor_pitch,
+        );
+
         for (pitch_index, notes_in_pitch) in self.notes.iter().enumerate() {
+            let row = grid.scale.row(pitch_index as u8);
             for (time_index, note) in notes_in_pitch.iter().enumerate() {
                 //
                 //
-                let note_len = note.end - note.start;
-                let pos2 = Point::new(note.start as f32, pitch_index as f32);
+                let note_len = tick::to_beats(note.end - note.start);
+                let pos2 = Point::new(tick::to_beats(note.start), row);
 
                 // TODO: make this scale independent (fixed number of pixels away from edge)
                 // This is the size of the box that would make the resize-mode cursor appear
                 let resizing_rect_len = RESIZE_BOX_PIXEL_WIDTH * grid.scaling.x / BEAT_SIZE;
                 let start_rect = Rectangle::new(pos2, Size::new(resizing_rect_len, 1.0));
                 let end_rect = Rectangle::new(
-                    pos2 + Vector::new(note_len as f32 - resizing_rect_len, 0.0),
+                    pos2 + Vector::new(note_len - resizing_rect_len, 0.0),
                     Size::new(resizing_rect_len, 1.0),
                 );
 
@@ -863,7 +932,7 @@ impl MidiNotes {
                 }
 
                 // Start dragging if click is inside note
-                let note_rect = Rectangle::new(pos2, Size::new(note_len as f32, 1.0));
+                let note_rect = Rectangle::new(pos2, Size::new(note_len, 1.0));
                 if note_rect.contains(projected_cursor) {
                     return Some(OverNote {
                         note_index: NoteIndex { pitch_index, time_index },
@@ -903,7 +972,9 @@ impl MidiNotes {
                     .iter()
                     .enumerate()
                     .filter_map(move |(time_index, note)| {
-                        if note.start < time_bounds.1 && note.end > time_bounds.0 {
+                        if tick::to_beats(note.start) < time_bounds.1
+                            && tick::to_beats(note.end) > time_bounds.0
+                        {
                             Some(NoteIndex { pitch_index: note.pitch.get() as usize, time_index })
                         } else {
                             None
@@ -942,13 +1013,29 @@ impl From<Vec<MidiNote>> for MidiNotes {
     }
 }
 
-#[derive(Clone)]
+impl From<MidiNotes> for Vec<MidiNote> {
+    fn from(midi_notes: MidiNotes) -> Self {
+        midi_notes.notes.into_iter().flatten().collect()
+    }
+}
+
+pub fn velocity_opacity(velocity: u8) -> f32 {
+    0.25 + 0.75 * velocity.min(127) as f32 / 127.0
+}
+
+fn default_velocity() -> u8 {
+    DEFAULT_VELOCITY
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct MidiNote {
     // pub id: u32,
-    pub start: f32,
-    pub end: f32,
+    pub start: Tick,
+    pub end: Tick,
     pub pitch: Pitch,
-    _velocity: u16,
+    #[serde(default = "default_velocity")]
+    pub velocity: u8, // 7-bit midi velocity
+    #[serde(skip)]
     _automation: Automation,
     // dynamic: Dynamic,
     // expression: Expression,
@@ -969,33 +1056,42 @@ impl fmt::Debug for MidiNote {
 // struct Vibrato;
 
 impl MidiNote {
-    pub fn new(start: f32, end: f32, pitch: Pitch) -> Self {
-        Self { start, end, pitch, _velocity: u16::MAX, _automation: Automation::default() }
+    pub fn new(start: Tick, end: Tick, pitch: Pitch) -> Self {
+        Self { start, end, pitch, velocity: DEFAULT_VELOCITY, _automation: Automation::default() }
+    }
+
+    pub fn with_velocity(mut self, velocity: u8) -> Self {
+        self.velocity = velocity.min(127);
+        self
     }
 
-    pub fn shorten(&mut self, amount: f32) {
+    pub fn shorten(&mut self, amount: Tick) {
         self.end -= amount;
     }
 
-    pub fn shorten_from_start(&mut self, amount: f32) {
+    pub fn shorten_from_start(&mut self, amount: Tick) {
         self.start += amount;
     }
 
     pub fn shorten_with_percent(&mut self, percentage: f32) {
-        let amount = (self.end - self.start) * percentage;
+        let amount = ((self.end - self.start) as f32 * percentage).round() as Tick;
         self.end -= amount;
     }
 
-    pub fn to_seconds(&self, timing: TimingInfo) -> (f32, f32) {
-        // convert bpm to beats per second
-        let bps = timing.bpm / 60.0;
+    pub fn length(&self) -> Tick {
+        self.end - self.start
+    }
+
+    // the tempo map starts counting beats at 0.0, whereas the grid starts at FIRST_TICK
+    pub fn to_seconds(&self, tempo_map: &TempoMap) -> (f32, f32) {
+        let start = tempo_map.beats_to_seconds(tick::to_song_beats(self.start));
+        let end = tempo_map.beats_to_seconds(tick::to_song_beats(self.end));
 
-        // convert start and end to beats
-        (self.start / bps, self.end / bps)
+        (start as f32, end as f32)
     }
 
     pub fn overlaps_with(&self, rect: &Rectangle) -> bool {
-        let (start, end) = (self.start as f32, self.end as f32);
+        let (start, end) = (tick::to_beats(self.start), tick::to_beats(self.end));
         let (x0, y0) = (rect.x, rect.y);
         let (x1, y1) = (rect.x + rect.width, rect.y + rect.height);
 
@@ -1023,29 +1119,32 @@ impl MidiNote {
         self.pitch.get()
     }
 
+    // the row of the note in the grid, or the row below it if the note is out of the scale
     pub fn get_scaled_pitch(&mut self, scale: &Scale) -> i8 {
         let chromatic_starting_pitch = self.pitch.get();
 
-        let scale_starting_pitch =
-            scale.from_chromatic_index_to_scale_index(chromatic_starting_pitch) as i8;
+        let (scale_starting_pitch, _) = scale.floor_scale_index(chromatic_starting_pitch);
+        let scale_starting_pitch = scale_starting_pitch as i8;
 
         scale_starting_pitch
 
         // scale_starting_pitch as i16 + cursor_delta.y as i16
     }
 
-    pub fn get_new_start(&self, cursor_delta: Vector) -> f32 {
-        self.start + cursor_delta.x
+    pub fn get_new_start(&self, delta_time: Tick) -> Tick {
+        self.start + delta_time
     }
 
     // pub fn reposition(&mut self, cursor_delta: Vector, grid: &Grid) {
-    pub fn reposition(&mut self, delta_pitch: i8, delta_time: f32, scale: &Scale) -> i8 {
+    // A note out of the scale moves by scale degrees from the degree below it, and keeps
+    // its alteration
+    pub fn reposition(&mut self, delta_pitch: i8, delta_time: Tick, scale: &Scale) -> i8 {
         let chromatic_starting_pitch = self.pitch.get();
 
-        let scale_starting_pitch =
-            scale.from_chromatic_index_to_scale_index(chromatic_starting_pitch) as i8;
+        let (scale_starting_pitch, alteration) = scale.floor_scale_index(chromatic_starting_pitch);
 
-        let new_pitch_index = (scale_starting_pitch + delta_pitch) as usize;
+        let new_pitch_index = (scale_starting_pitch as i16 + delta_pitch as i16)
+            .clamp(0, scale.midi_size() as i16 - 1) as usize;
 
         // // if the new_pitch_index is out of bounds, cancel the move
         // if new_pitch_index >= grid.scale.midi_size() {
@@ -1054,7 +1153,7 @@ impl MidiNote {
 
         // println!("new_pitch_index: {}", new_pitch_index);
 
-        let new_pitch = scale.midi_range[new_pitch_index] as i16;
+        let new_pitch = (scale.midi_range[new_pitch_index] as i16 + alteration as i16).clamp(0, 127);
 
         // let delta_time = cursor_delta.x;
         self.pitch = Pitch(new_pitch);
@@ -1065,7 +1164,7 @@ impl MidiNote {
         return new_pitch as i8 - chromatic_starting_pitch as i8;
     }
 
-    pub fn resize_single_and_get_percent(&mut self, delta_time: f32, resize_end: NoteEdge, note_index: NoteIndex) -> ResizedEdgePercent {
+    pub fn resize_single_and_get_percent(&mut self, delta_time: Tick, resize_end: NoteEdge, note_index: NoteIndex) -> ResizedEdgePercent {
         let before = self.clone();
         let original_note_length = self.end - self.start;
 
@@ -1080,28 +1179,28 @@ impl MidiNote {
         match resize_end {
             NoteEdge::Start => {
                 new_start_time = self.start + delta_time;
-                new_legnth_to_old_length = (self.end - new_start_time) / original_note_length;
+                new_legnth_to_old_length = (self.end - new_start_time) as f32 / original_note_length as f32;
 
                 if new_start_time == self.end {
-                    new_start_time = self.end - NOTE_MIN_SIZE;
-                    new_legnth_to_old_length = NOTE_MIN_SIZE / original_note_length;
+                    new_start_time = self.end - NOTE_MIN_TICKS;
+                    new_legnth_to_old_length = NOTE_MIN_TICKS as f32 / original_note_length as f32;
                 } else if new_start_time > self.end {
                     new_end_time = new_start_time;
                     new_start_time = self.end;
-                    new_legnth_to_old_length = (new_start_time - new_end_time) / original_note_length;
+                    new_legnth_to_old_length = (new_start_time - new_end_time) as f32 / original_note_length as f32;
                 }
             }
             NoteEdge::End => {
                 new_end_time = self.end + delta_time;
-                new_legnth_to_old_length = (new_end_time - self.start) / original_note_length;
+                new_legnth_to_old_length = (new_end_time - self.start) as f32 / original_note_length as f32;
 
                 if new_end_time == self.start {
-                    new_end_time = self.start + NOTE_MIN_SIZE;
-                    new_legnth_to_old_length = NOTE_MIN_SIZE / original_note_length;
+                    new_end_time = self.start + NOTE_MIN_TICKS;
+                    new_legnth_to_old_length = NOTE_MIN_TICKS as f32 / original_note_length as f32;
                 } else if new_end_time < self.start {
                     new_start_time = new_end_time;
                     new_end_time = self.start;
-                    new_legnth_to_old_length = (new_start_time - new_end_time) / original_note_length;
+                    new_legnth_to_old_length = (new_start_time - new_end_time) as f32 / original_note_length as f32;
                 }
             }
             _ => {}
@@ -1112,7 +1211,7 @@ impl MidiNote {
 
         
 
-        *self = MidiNote::new(new_start_time, new_end_time, self.pitch);
+        *self = MidiNote::new(new_start_time, new_end_time, self.pitch).with_velocity(self.velocity);
 
         // println!("percent: {}", new_legnth_to_old_length);
 
@@ -1131,18 +1230,18 @@ impl MidiNote {
 
         match percent.edge {
             NoteEdge::Start => {
-                new_start_time = self.end - (original_note_length * percent.percent);
+                new_start_time = self.end - (original_note_length as f32 * percent.percent).round() as Tick;
                 if new_start_time == self.end {
-                    new_start_time = self.end - NOTE_MIN_SIZE;
+                    new_start_time = self.end - NOTE_MIN_TICKS;
                 } else if new_start_time > self.end {
                     new_end_time = new_start_time;
                     new_start_time = self.end;
                 }
             }
             NoteEdge::End => {
-                new_end_time = self.start + (original_note_length * percent.percent);
+                new_end_time = self.start + (original_note_length as f32 * percent.percent).round() as Tick;
                 if new_end_time == self.start {
-                    new_end_time = self.start + NOTE_MIN_SIZE;
+                    new_end_time = self.start + NOTE_MIN_TICKS;
                 } else if new_end_time < self.start {
                     new_start_time = new_end_time;
                     new_end_time = self.start;
@@ -1151,7 +1250,7 @@ impl MidiNote {
             _ => {}
         }
 
-        *self = MidiNote::new(new_start_time, new_end_time, self.pitch);
+        *self = MidiNote::new(new_start_time, new_end_time, self.pitch).with_velocity(self.velocity);
     }
 
 
@@ -1172,10 +1271,10 @@ impl MidiNote {
         //     _ => {}
         // }
 
-        let start_time = new_start_time.min(new_end_time).max(1.0);
+        let start_time = new_start_time.min(new_end_time).max(FIRST_TICK);
         let end_time = new_start_time.max(new_end_time);
 
-        *self = MidiNote::new(start_time, end_time, self.pitch);
+        *self = MidiNote::new(start_time, end_time, self.pitch).with_velocity(self.velocity);
 
         return ResizedEdges {
             start: self.start - before.start,
@@ -1230,6 +1329,14 @@ pub struct NoteIndex {
     pub time_index: usize,
 }
 
+// The new velocity of a note, which is either in the Selected MidiNotes or in the main MidiNotes
+#[derive(Clone, Copy, Debug)]
+pub struct VelocityChange {
+    pub note_index: NoteIndex,
+    pub is_selected: bool,
+    pub velocity: u8,
+}
+
 // An enum describing the transition between the main MidiNotes and the Selected MidiNotes
 #[derive(Clone, Debug)]
 pub enum ChangeSelection {
@@ -1251,7 +1358,7 @@ pub enum ChangeSelection {
     SelectMany { note_indices: Vec<NoteIndex> },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Pitch(pub i16);
 
 impl Pitch {
@@ -1412,8 +1519,8 @@ impl NoteEdge {
 
 #[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub struct ResizedEdges {
-    pub start: f32,
-    pub end: f32,
+    pub start: Tick,
+    pub end: Tick,
 }
 
 
@@ -1432,18 +1539,18 @@ impl Neg for ResizedEdges {
 }
 
 impl ResizedEdges {
-    pub const ZERO: Self = Self { start: 0.0, end: 0.0 };
-    pub fn new(start: f32, end: f32) -> Self {
+    pub const ZERO: Self = Self { start: 0, end: 0 };
+    pub fn new(start: Tick, end: Tick) -> Self {
         Self { start, end }
     }
-    pub fn from_end(end: f32) -> Self {
-        Self { start: 0.0, end }
+    pub fn from_end(end: Tick) -> Self {
+        Self { start: 0, end }
     }
 
-    pub fn from_start(start: f32) -> Self {
-        Self { start, end: 0.0 }
+    pub fn from_start(start: Tick) -> Self {
+        Self { start, end: 0 }
     }
-    pub fn splat(value: f32) -> Self {
+    pub fn splat(value: Tick) -> Self {
         Self { start: value, end: value }
     }
 }
@@ -1503,3 +1610,27 @@ impl ResizedEdgePercent {
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::scale::ScaleType;
+
+    #[test]
+    fn notes_below_the_scale_are_hit_on_row_minus_1() {
+        // C is below C#, the lowest pitch of D major, so it is drawn from row -0.5
+        let mut grid = Grid::default();
+        grid.scale = Scale::new(ScaleType::Major, 2);
+        let note = MidiNote::new(FIRST_TICK, FIRST_TICK + 2 * PPQ, Pitch::new(0));
+        let notes = MidiNotes::from(vec![note]);
+
+        let beat = tick::to_beats(FIRST_TICK + PPQ);
+        let over_note = notes.get_note_under_cursor(&grid, Point::new(beat, 0.2)).unwrap();
+        assert_eq!(over_note.note_index.pitch_index, 0);
+        assert_eq!(over_note.note_index.time_index, 0);
+        assert_eq!(over_note.note_edge, NoteEdge::None);
+
+        // below the lowest pitch, and in the upper half of the row of C#
+        assert!(notes.get_note_under_cursor(&grid, Point::new(beat, -0.2)).is_none());
+        assert!(notes.get_note_under_cursor(&grid, Point::new(beat, 1.7)).is_none());
+    }
+}
@@ -1,3 +1,7 @@
+pub mod chord;
+pub mod chord_stamp;
 pub mod midi_notes;
+pub mod quantize;
 pub mod scale;
+pub mod transpose;
 // pub mod undo;
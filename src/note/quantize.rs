@@ -0,0 +1,155 @@
+//! Quantize
+//!
+//! Moves note starts, and optionally note ends, towards the closest line of the grid.
+//! The grid lines are the multiples of the beat fraction, and swing delays every
+//! other line, counting from the first beat of the song so that it is never delayed.
+//! Positions are in ticks, and a partial strength rounds to the nearest tick.
+
+use crate::note::midi_notes::MidiNote;
+use crate::tick::{Tick, FIRST_TICK, NOTE_MIN_TICKS};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizeSettings {
+    // 1.0 snaps to the grid line, 0.5 moves halfway towards it
+    pub strength: f32,
+    // delay of every other grid line, as a fraction of half a period:
+    // 0.0 is straight, 1.0 moves the off-beat lines halfway to the next line
+    pub swing: f32,
+    // notes farther from a grid line than window * half a period are left alone
+    pub window: f32,
+    pub quantize_ends: bool,
+}
+
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        Self { strength: 1.0, swing: 0.0, window: 1.0, quantize_ends: false }
+    }
+}
+
+impl QuantizeSettings {
+    pub fn nearest_grid_line(&self, tick: Tick, period: Tick) -> Tick {
+        let period = period.max(1);
+        let swing_offset = (self.swing * period as f32 * 0.5).round() as Tick;
+        let index = (tick - FIRST_TICK).div_euclid(period);
+
+        (index - 1..=index + 1)
+            .map(|k| {
+                let line = FIRST_TICK + k * period;
+                if k.rem_euclid(2) == 1 {
+                    line + swing_offset
+                } else {
+                    line
+                }
+            })
+            .min_by_key(|line| (line - tick).abs())
+            .unwrap()
+    }
+
+    pub fn quantize_position(&self, tick: Tick, period: Tick) -> Tick {
+        let distance = self.nearest_grid_line(tick, period) - tick;
+
+        if distance.abs() as f32 > self.window * period as f32 * 0.5 {
+            tick
+        } else {
+            tick + (distance as f32 * self.strength).round() as Tick
+        }
+    }
+
+    pub fn quantize_note(&self, note: &MidiNote, period: Tick) -> MidiNote {
+        let mut quantized = note.clone();
+
+        quantized.start = self.quantize_position(note.start, period);
+
+        quantized.end = if self.quantize_ends {
+            self.quantize_position(note.end, period)
+        } else {
+            // the length is kept
+            note.end + quantized.start - note.start
+        };
+        quantized.end = quantized.end.max(quantized.start + NOTE_MIN_TICKS);
+
+        quantized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::midi_notes::Pitch;
+    use crate::tick::PPQ;
+
+    fn swung(swing: f32) -> QuantizeSettings {
+        QuantizeSettings { swing, ..QuantizeSettings::default() }
+    }
+
+    #[test]
+    fn swings_every_other_beat_from_the_first_beat() {
+        let settings = swung(0.5);
+
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + 100, PPQ), FIRST_TICK);
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + PPQ + 200, PPQ), FIRST_TICK + PPQ + 240);
+        assert_eq!(
+            settings.nearest_grid_line(FIRST_TICK + 2 * PPQ - 10, PPQ),
+            FIRST_TICK + 2 * PPQ
+        );
+        // before the first beat, the line one period earlier is swung
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK - PPQ + 200, PPQ), FIRST_TICK - PPQ + 240);
+    }
+
+    #[test]
+    fn swings_every_other_third_of_a_beat_from_the_first_beat() {
+        // the first beat is not an even multiple of a third of a beat
+        let (settings, period) = (swung(1.0), PPQ / 3);
+
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + 10, period), FIRST_TICK);
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + 450, period), FIRST_TICK + 480);
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + 650, period), FIRST_TICK + 640);
+        assert_eq!(settings.nearest_grid_line(FIRST_TICK + 1100, period), FIRST_TICK + 1120);
+    }
+
+    #[test]
+    fn strength_moves_part_of_the_way_to_the_grid_line() {
+        let half = QuantizeSettings { strength: 0.5, ..QuantizeSettings::default() };
+        assert_eq!(half.quantize_position(FIRST_TICK + 100, PPQ), FIRST_TICK + 50);
+        assert_eq!(half.quantize_position(FIRST_TICK + PPQ - 100, PPQ), FIRST_TICK + PPQ - 50);
+
+        // the partial move is rounded to the nearest tick
+        let quarter = QuantizeSettings { strength: 0.25, ..QuantizeSettings::default() };
+        assert_eq!(quarter.quantize_position(FIRST_TICK + 99, PPQ), FIRST_TICK + 74);
+
+        let none = QuantizeSettings { strength: 0.0, ..QuantizeSettings::default() };
+        assert_eq!(none.quantize_position(FIRST_TICK + 99, PPQ), FIRST_TICK + 99);
+    }
+
+    #[test]
+    fn positions_outside_the_window_are_left_alone() {
+        let settings = QuantizeSettings { window: 0.5, ..QuantizeSettings::default() };
+
+        // half a window is a quarter of a period
+        assert_eq!(settings.quantize_position(FIRST_TICK + 200, PPQ), FIRST_TICK);
+        assert_eq!(settings.quantize_position(FIRST_TICK + 240, PPQ), FIRST_TICK);
+        assert_eq!(settings.quantize_position(FIRST_TICK + 300, PPQ), FIRST_TICK + 300);
+        assert_eq!(settings.quantize_position(FIRST_TICK + PPQ - 300, PPQ), FIRST_TICK + PPQ - 300);
+
+        // the whole window reaches halfway to the next line
+        let settings = QuantizeSettings::default();
+        assert_eq!(settings.quantize_position(FIRST_TICK + 300, PPQ), FIRST_TICK);
+    }
+
+    #[test]
+    fn ends_are_quantized_or_keep_the_length() {
+        let note = MidiNote::new(FIRST_TICK + 100, FIRST_TICK + PPQ + 430, Pitch::new(60));
+
+        let quantized = QuantizeSettings::default().quantize_note(&note, PPQ);
+        assert_eq!((quantized.start, quantized.end), (FIRST_TICK, FIRST_TICK + PPQ + 330));
+
+        let settings = QuantizeSettings { quantize_ends: true, ..QuantizeSettings::default() };
+        let quantized = settings.quantize_note(&note, PPQ);
+        assert_eq!((quantized.start, quantized.end), (FIRST_TICK, FIRST_TICK + PPQ));
+
+        // a note whose start and end snap to the same line keeps the shortest length
+        let short = MidiNote::new(FIRST_TICK + 10, FIRST_TICK + 200, Pitch::new(60));
+        let quantized = settings.quantize_note(&short, PPQ);
+        assert_eq!((quantized.start, quantized.end), (FIRST_TICK, FIRST_TICK + NOTE_MIN_TICKS));
+    }
+}
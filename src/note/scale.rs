@@ -1,33 +1,51 @@
-pub static NOTE_LABELS: [&'static str; 12] =
-    ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
-
-#[allow(dead_code)]
-pub fn test() {
-    let scale = Scale::new(ScaleType::Major, 2);
-
-    println!(
-        "base_notes: {:?}",
-        scale.midi_range.iter().map(|&x| scale.int_to_label(x)).collect::<Vec<String>>()
-    );
+use serde::{Deserialize, Serialize};
 
-    let midi_range_labels =
-        scale.midi_range.iter().map(|&x| scale.int_to_label(x)).collect::<Vec<String>>();
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
 
-    println!("filtered midi notes: {:?}", midi_range_labels);
-    println!("cale.midi_range: {:?}", scale.midi_range);
-    println!("range: {:?}", scale.get_range(55, 12));
-}
+pub static NOTE_LABELS: [&'static str; 12] =
+    ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
 
-#[derive(Clone)]
+// the midi_range is not saved, it is rebuilt from the scale type and the root
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "SavedScale", into = "SavedScale")]
 pub struct Scale {
     pub scale_type: ScaleType,
     pub root: u8,
     pub midi_range: Vec<u8>,
 }
 
-use std::fmt::{Debug, Formatter, Result};
+#[derive(Serialize, Deserialize)]
+struct SavedScale {
+    scale_type: ScaleType,
+    root: u8,
+}
+
+// a custom scale without notes, which only a hand edited file can have, is read as the
+// chromatic scale
+impl From<SavedScale> for Scale {
+    fn from(saved: SavedScale) -> Self {
+        let scale_type = match saved.scale_type {
+            ScaleType::Custom(intervals) => {
+                ScaleType::custom(intervals).unwrap_or(ScaleType::Chromatic)
+            }
+            scale_type => scale_type,
+        };
+        Scale::new(scale_type, saved.root % 12)
+    }
+}
+
+impl From<Scale> for SavedScale {
+    fn from(scale: Scale) -> Self {
+        SavedScale { scale_type: scale.scale_type, root: scale.root }
+    }
+}
+
+use std::fmt::{self, Debug, Formatter};
 impl Debug for Scale {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("Scale")
             .field("scale_type", &self.scale_type)
             .field("root", &self.root)
@@ -35,17 +53,201 @@ impl Debug for Scale {
     }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Debug)]
+// the natural notes, by letter from C to B
+const LETTERS: [&'static str; 7] = ["C", "D", "E", "F", "G", "A", "B"];
+const NATURALS: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+// the major keys written with flats, from F to Gb
+const FLAT_MAJOR_KEYS: [u8; 6] = [5, 10, 3, 8, 1, 6];
+
+// Major and Minor are the ionian and aeolian modes, and Pentatonic is the major
+// pentatonic. The custom intervals start with 0 for the root
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ScaleType {
     Major,
     Minor,
     Pentatonic,
     Blues,
     Chromatic,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    HarmonicMinor,
+    MelodicMinor,
+    WholeTone,
+    // the whole-half diminished scale
+    Diminished,
+    MinorPentatonic,
+    HungarianMinor,
+    PhrygianDominant,
+    DoubleHarmonic,
+    Hirajoshi,
+    InSen,
     Custom(Vec<u8>),
 }
 
+impl ScaleType {
+    // the intervals are taken within the octave, sorted, and None when there are none
+    pub fn custom(intervals: Vec<u8>) -> Option<ScaleType> {
+        let mut intervals: Vec<u8> = intervals.iter().map(|interval| interval % 12).collect();
+        intervals.sort();
+        intervals.dedup();
+        (!intervals.is_empty()).then(|| ScaleType::Custom(intervals))
+    }
+
+    // every scale but the custom ones, in the order of the scale picker
+    pub const PRESETS: [ScaleType; 20] = [
+        ScaleType::Major,
+        ScaleType::Minor,
+        ScaleType::Dorian,
+        ScaleType::Phrygian,
+        ScaleType::Lydian,
+        ScaleType::Mixolydian,
+        ScaleType::Locrian,
+        ScaleType::HarmonicMinor,
+        ScaleType::MelodicMinor,
+        ScaleType::Pentatonic,
+        ScaleType::MinorPentatonic,
+        ScaleType::Blues,
+        ScaleType::WholeTone,
+        ScaleType::Diminished,
+        ScaleType::HungarianMinor,
+        ScaleType::PhrygianDominant,
+        ScaleType::DoubleHarmonic,
+        ScaleType::Hirajoshi,
+        ScaleType::InSen,
+        ScaleType::Chromatic,
+    ];
+
+    // in semitones above the root, sorted
+    pub fn intervals(&self) -> Vec<u8> {
+        match self {
+            ScaleType::Major => vec![0, 2, 4, 5, 7, 9, 11],
+            ScaleType::Minor => vec![0, 2, 3, 5, 7, 8, 10],
+            ScaleType::Pentatonic => vec![0, 2, 4, 7, 9],
+            ScaleType::Blues => vec![0, 3, 5, 6, 7, 10],
+            ScaleType::Chromatic => vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            ScaleType::Dorian => vec![0, 2, 3, 5, 7, 9, 10],
+            ScaleType::Phrygian => vec![0, 1, 3, 5, 7, 8, 10],
+            ScaleType::Lydian => vec![0, 2, 4, 6, 7, 9, 11],
+            ScaleType::Mixolydian => vec![0, 2, 4, 5, 7, 9, 10],
+            ScaleType::Locrian => vec![0, 1, 3, 5, 6, 8, 10],
+            ScaleType::HarmonicMinor => vec![0, 2, 3, 5, 7, 8, 11],
+            ScaleType::MelodicMinor => vec![0, 2, 3, 5, 7, 9, 11],
+            ScaleType::WholeTone => vec![0, 2, 4, 6, 8, 10],
+            ScaleType::Diminished => vec![0, 2, 3, 5, 6, 8, 9, 11],
+            ScaleType::MinorPentatonic => vec![0, 3, 5, 7, 10],
+            ScaleType::HungarianMinor => vec![0, 2, 3, 6, 7, 8, 11],
+            ScaleType::PhrygianDominant => vec![0, 1, 4, 5, 7, 8, 10],
+            ScaleType::DoubleHarmonic => vec![0, 1, 4, 5, 7, 8, 11],
+            ScaleType::Hirajoshi => vec![0, 2, 3, 7, 8],
+            ScaleType::InSen => vec![0, 1, 5, 7, 10],
+            ScaleType::Custom(intervals) => intervals.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScaleType::Major => "Major",
+            ScaleType::Minor => "Minor",
+            ScaleType::Pentatonic => "Major pentatonic",
+            ScaleType::Blues => "Blues",
+            ScaleType::Chromatic => "Chromatic",
+            ScaleType::Dorian => "Dorian",
+            ScaleType::Phrygian => "Phrygian",
+            ScaleType::Lydian => "Lydian",
+            ScaleType::Mixolydian => "Mixolydian",
+            ScaleType::Locrian => "Locrian",
+            ScaleType::HarmonicMinor => "Harmonic minor",
+            ScaleType::MelodicMinor => "Melodic minor",
+            ScaleType::WholeTone => "Whole tone",
+            ScaleType::Diminished => "Diminished",
+            ScaleType::MinorPentatonic => "Minor pentatonic",
+            ScaleType::HungarianMinor => "Hungarian minor",
+            ScaleType::PhrygianDominant => "Phrygian dominant",
+            ScaleType::DoubleHarmonic => "Double harmonic",
+            ScaleType::Hirajoshi => "Hirajoshi",
+            ScaleType::InSen => "In sen",
+            ScaleType::Custom(_) => "Custom",
+        }
+    }
+
+    // how far the root is above the root of the major key that the scale is written
+    // in, ex: 9 for the minor scales, since A minor is written like C major
+    fn major_key_offset(&self) -> u8 {
+        match self {
+            ScaleType::Dorian => 2,
+            ScaleType::Phrygian | ScaleType::PhrygianDominant | ScaleType::InSen => 4,
+            ScaleType::Lydian => 5,
+            ScaleType::Mixolydian => 7,
+            ScaleType::Locrian => 11,
+            ScaleType::Minor
+            | ScaleType::HarmonicMinor
+            | ScaleType::MelodicMinor
+            | ScaleType::MinorPentatonic
+            | ScaleType::Blues
+            | ScaleType::HungarianMinor
+            | ScaleType::Hirajoshi => 9,
+            // a minor third without a major third sounds minor
+            ScaleType::Custom(intervals) if intervals.contains(&3) && !intervals.contains(&4) => 9,
+            _ => 0,
+        }
+    }
+}
+
+// A scale saved by the user, which the scale picker offers after the presets
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomScale {
+    pub name: String,
+    pub intervals: Vec<u8>,
+}
+
+impl CustomScale {
+    // the pitch classes are taken above the root, and the root is always in the scale
+    pub fn new(name: String, root: u8, pitches: impl IntoIterator<Item = u8>) -> Self {
+        let mut intervals: Vec<u8> =
+            pitches.into_iter().map(|pitch| (pitch + 12 - root % 12) % 12).collect();
+        intervals.push(0);
+        intervals.sort();
+        intervals.dedup();
+        Self { name, intervals }
+    }
+
+    pub fn scale_type(&self) -> ScaleType {
+        ScaleType::Custom(self.intervals.clone())
+    }
+}
+
+// the custom scales are kept in a json file apart from the projects, so that every
+// project can use them. A missing file has no scales, and a scale without notes is skipped
+pub fn load_custom_scales(path: impl AsRef<Path>) -> io::Result<Vec<CustomScale>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let scales: Vec<CustomScale> =
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(scales
+        .into_iter()
+        .filter_map(|scale| match ScaleType::custom(scale.intervals) {
+            Some(ScaleType::Custom(intervals)) => Some(CustomScale { intervals, ..scale }),
+            _ => {
+                println!("Skipped the custom scale {}, which has no notes", scale.name);
+                None
+            }
+        })
+        .collect())
+}
+
+pub fn save_custom_scales(path: impl AsRef<Path>, scales: &[CustomScale]) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(scales)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, json)
+}
+
 impl Default for Scale {
     fn default() -> Self {
         Scale::new(ScaleType::Minor, 2)
@@ -58,49 +260,65 @@ impl Scale {
         Scale { scale_type, root, midi_range }
     }
 
-    pub fn from_chromatic_index_to_scale_index(&self, index: u8) -> u8 {
-        let octave = index / 12;
-        let note = index % 12;
-        // println!("index: {}, octave: {}, note: {}", index, octave, note);
-        // println!("octave: {}, note: {}", octave, note);
-        // get index of note in scale
-        let base_scale_index =
-            Self::get_base_notes(&self.scale_type, self.root).iter().position(|&x| x == note);
+    // the row of a pitch in the grid. A pitch out of the scale is halfway between the
+    // rows of the scale pitches around it, and half a row below the first row when it is
+    // below the lowest pitch of the scale
+    pub fn row(&self, pitch: u8) -> f32 {
+        match self.midi_range.binary_search(&pitch) {
+            Ok(index) => index as f32,
+            Err(index) => index as f32 - 0.5,
+        }
+    }
+
+    // the scale index at or below a pitch, and how many semitones the pitch is above
+    // it. A pitch below the lowest note of the scale is below its first index
+    pub fn floor_scale_index(&self, pitch: u8) -> (u8, i8) {
+        let index = match self.midi_range.binary_search(&pitch) {
+            Ok(index) => index,
+            Err(index) => index.saturating_sub(1),
+        };
+        (index as u8, pitch as i8 - self.midi_range[index] as i8)
+    }
 
-        if let Some(i) = base_scale_index {
-            i as u8 + octave * self.size() as u8
-        } else {
-            panic!("note not in scale");
+    // the scale index of the closest pitch in the scale, the lower one when the pitch
+    // is halfway between two
+    pub fn nearest_scale_index(&self, pitch: u8) -> u8 {
+        let (index, alteration) = self.floor_scale_index(pitch);
+        match self.midi_range.get(index as usize + 1) {
+            Some(above) if alteration > 0 && above - pitch < alteration as u8 => index + 1,
+            _ => index,
         }
     }
 
+    pub fn nearest_pitch(&self, pitch: u8) -> u8 {
+        self.midi_range[self.nearest_scale_index(pitch) as usize]
+    }
+
+    // the pitches out of the scale that are drawn between a row and the row above it.
+    // Row -1 holds the pitches below the lowest pitch of the scale
+    pub fn pitches_above_row(&self, row: isize) -> Range<usize> {
+        if row < -1 {
+            return 0..0;
+        }
+        let lowest = match usize::try_from(row) {
+            Ok(row) => self.midi_range.get(row).map_or(128, |below| *below as usize + 1),
+            Err(_) => 0,
+        };
+        let above = self.midi_range.get((row + 1) as usize).map_or(128, |above| *above as usize);
+        lowest..above.max(lowest)
+    }
+
     // pub fn find_index(&self, note: u8) -> Option<u8> {
     //     self.midi_range.iter().position(|&x| x == note).map(|x| x as u8)
     // }
 
     pub fn get_root_base_notes(&self) -> Vec<u8> {
-        match &self.scale_type {
-            ScaleType::Major => vec![0, 2, 4, 5, 7, 9, 11],
-            ScaleType::Minor => vec![0, 2, 3, 5, 7, 8, 10],
-            ScaleType::Pentatonic => vec![0, 2, 4, 7, 9],
-            ScaleType::Blues => vec![0, 3, 5, 6, 7, 10],
-            ScaleType::Chromatic => vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
-            ScaleType::Custom(scale) => scale.clone(),
-        }
+        self.scale_type.intervals()
     }
 
     pub fn get_base_notes(scale_type: &ScaleType, root: u8) -> Vec<u8> {
-        let mut notes = match scale_type {
-            ScaleType::Major => vec![0, 2, 4, 5, 7, 9, 11],
-            ScaleType::Minor => vec![0, 2, 3, 5, 7, 8, 10],
-            ScaleType::Pentatonic => vec![0, 2, 4, 7, 9],
-            ScaleType::Blues => vec![0, 3, 5, 6, 7, 10],
-            ScaleType::Chromatic => vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
-            ScaleType::Custom(scale) => scale.clone(),
-        }
-        .iter()
-        .map(|&x| (x + root) % 12)
-        .collect::<Vec<u8>>();
+        let mut notes =
+            scale_type.intervals().iter().map(|&x| (x + root) % 12).collect::<Vec<u8>>();
 
         notes.sort();
         notes
@@ -111,6 +329,63 @@ impl Scale {
         self.midi_range = Self::make_filtered_midi_range(&self.scale_type, self.root);
     }
 
+    pub fn set_root(&mut self, root: u8) {
+        self.root = root % 12;
+        self.midi_range = Self::make_filtered_midi_range(&self.scale_type, self.root);
+    }
+
+    // ex: "D Dorian"
+    pub fn name(&self) -> String {
+        format!("{} {}", self.root_label(), self.scale_type.name())
+    }
+
+    pub fn root_label(&self) -> String {
+        self.pitch_class_label(self.root)
+    }
+
+    // ex: "Bb" in F major, without an octave
+    pub fn pitch_class_label(&self, pitch_class: u8) -> String {
+        let (letter, accidental) = self.spell(pitch_class % 12);
+        format!("{}{}", LETTERS[letter], accidental_label(accidental))
+    }
+
+    fn uses_flats(&self) -> bool {
+        let major_key = (self.root + 12 - self.scale_type.major_key_offset()) % 12;
+        FLAT_MAJOR_KEYS.contains(&major_key)
+    }
+
+    // the letter and the accidental of a pitch class in the key of the scale. Each
+    // degree of a seven note scale gets its own letter, so that D harmonic minor has a
+    // Bb and a C#, and the other notes are written with the flats or the sharps of the key
+    fn spell(&self, pitch_class: u8) -> (usize, i8) {
+        let uses_flats = self.uses_flats();
+        let spell_chromatic =
+            |pitch_class: u8| match NATURALS.iter().position(|&x| x == pitch_class) {
+                Some(letter) => (letter, 0),
+                None if uses_flats => {
+                    (NATURALS.iter().position(|&x| x == pitch_class + 1).unwrap(), -1)
+                }
+                None => (NATURALS.iter().position(|&x| x == pitch_class - 1).unwrap(), 1),
+            };
+
+        let intervals = self.scale_type.intervals();
+        let degree = intervals.iter().position(|&x| (x + self.root) % 12 == pitch_class);
+        match degree {
+            Some(degree) if intervals.len() == 7 => {
+                let (root_letter, _) = spell_chromatic(self.root);
+                let letter = (root_letter + degree) % 7;
+                let accidental =
+                    (pitch_class as i8 - NATURALS[letter] as i8 + 18).rem_euclid(12) - 6;
+                if accidental.abs() <= 2 {
+                    (letter, accidental)
+                } else {
+                    spell_chromatic(pitch_class)
+                }
+            }
+            _ => spell_chromatic(pitch_class),
+        }
+    }
+
     // from C-2 tp C8
     fn make_filtered_midi_range(scale_type: &ScaleType, root: u8) -> Vec<u8> {
         let base_notes = Self::get_base_notes(scale_type, root);
@@ -123,8 +398,12 @@ impl Scale {
         filtered_midi_range
     }
 
+    // the octave is the one of the letter, so that the B below C3 is written Cb3 in
+    // Gb major
     pub fn int_to_label(&self, note: u8) -> String {
-        format!("{}{}", NOTE_LABELS[(note % 12) as usize], note as i16 / 12 - 2)
+        let (letter, accidental) = self.spell(note % 12);
+        let natural = note as i16 - accidental as i16;
+        format!("{}{}{}", LETTERS[letter], accidental_label(accidental), natural.div_euclid(12) - 2)
     }
 
     pub fn get_range(&self, start: u8, size: u8) -> Vec<u8> {
@@ -145,3 +424,168 @@ impl Scale {
         Self::get_base_notes(&self.scale_type, self.root).len() as u8
     }
 }
+
+fn accidental_label(accidental: i8) -> &'static str {
+    match accidental {
+        -2 => "bb",
+        -1 => "b",
+        1 => "#",
+        2 => "##",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_with_flats_are_spelled_with_flats() {
+        let f_major = Scale::new(ScaleType::Major, 5);
+        assert_eq!(f_major.pitch_class_label(10), "Bb");
+        assert_eq!(f_major.int_to_label(70), "Bb3");
+
+        // the B below C3 belongs to the octave of its letter
+        let g_flat_major = Scale::new(ScaleType::Major, 6);
+        assert_eq!(g_flat_major.root_label(), "Gb");
+        assert_eq!(g_flat_major.int_to_label(59), "Cb3");
+
+        // D minor is written like F major
+        let d_minor = Scale::new(ScaleType::Minor, 2);
+        assert_eq!(d_minor.pitch_class_label(10), "Bb");
+        assert_eq!(d_minor.pitch_class_label(1), "Db");
+    }
+
+    #[test]
+    fn keys_with_sharps_are_spelled_with_sharps() {
+        let e_major = Scale::new(ScaleType::Major, 4);
+        assert_eq!(e_major.int_to_label(68), "G#3");
+        assert_eq!(e_major.pitch_class_label(3), "D#");
+
+        let c_major = Scale::new(ScaleType::Major, 0);
+        assert_eq!(c_major.int_to_label(60), "C3");
+        assert_eq!(c_major.int_to_label(0), "C-2");
+        assert_eq!(c_major.pitch_class_label(6), "F#");
+    }
+
+    #[test]
+    fn every_degree_of_a_seven_note_scale_has_its_own_letter() {
+        // D harmonic minor has a Bb and a C#
+        let d_harmonic_minor = Scale::new(ScaleType::HarmonicMinor, 2);
+        assert_eq!(d_harmonic_minor.pitch_class_label(10), "Bb");
+        assert_eq!(d_harmonic_minor.pitch_class_label(1), "C#");
+
+        // the seventh degree of G# harmonic minor is F double sharp
+        let g_sharp_harmonic_minor = Scale::new(ScaleType::HarmonicMinor, 8);
+        assert_eq!(g_sharp_harmonic_minor.pitch_class_label(7), "F##");
+    }
+
+    #[test]
+    fn presets_start_on_the_root_and_fill_the_midi_range() {
+        for scale_type in ScaleType::PRESETS.iter() {
+            let intervals = scale_type.intervals();
+            assert_eq!(intervals[0], 0, "{}", scale_type.name());
+            assert!(intervals.windows(2).all(|pair| pair[0] < pair[1]), "{}", scale_type.name());
+            assert!(intervals.iter().all(|interval| *interval < 12), "{}", scale_type.name());
+
+            for root in 0..12 {
+                let scale = Scale::new(scale_type.clone(), root);
+                assert_eq!(scale.size() as usize, intervals.len());
+                assert!(scale.midi_range.contains(&root));
+                assert!(scale.midi_range[0] < 12);
+                assert!(scale.midi_range.windows(2).all(|pair| pair[1] - pair[0] <= 4));
+                assert!(*scale.midi_range.last().unwrap() > 115);
+            }
+        }
+
+        let names: Vec<&str> =
+            ScaleType::PRESETS.iter().map(|scale_type| scale_type.name()).collect();
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[i + 1..].contains(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn custom_scales_are_saved_and_loaded() {
+        let path = std::env::temp_dir()
+            .join(format!("midi_composer_custom_scales_{}.json", std::process::id()));
+        let scales = vec![
+            CustomScale::new("Tritones".to_string(), 2, [2, 8]),
+            CustomScale::new("Major triad".to_string(), 7, [7, 11, 14]),
+        ];
+        assert_eq!(scales[1].intervals, [0, 4, 7]);
+
+        save_custom_scales(&path, &scales).unwrap();
+        let loaded = load_custom_scales(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.unwrap(), scales);
+    }
+
+    #[test]
+    fn custom_scales_without_notes_are_skipped() {
+        let path = std::env::temp_dir()
+            .join(format!("midi_composer_empty_custom_scales_{}.json", std::process::id()));
+        let json =
+            r#"[{"name": "Empty", "intervals": []}, {"name": "Fifths", "intervals": [7, 0]}]"#;
+        std::fs::write(&path, json).unwrap();
+        let loaded = load_custom_scales(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].intervals, [0, 7]);
+
+        // nor does a project with an empty custom scale panic
+        let scale: Scale =
+            serde_json::from_str(r#"{"scale_type": {"Custom": []}, "root": 3}"#).unwrap();
+        assert_eq!(scale.scale_type, ScaleType::Chromatic);
+        assert_eq!(scale.floor_scale_index(60), (60, 0));
+    }
+
+    #[test]
+    fn a_missing_file_has_no_custom_scales() {
+        let path = std::env::temp_dir().join("midi_composer_missing_custom_scales.json");
+        assert!(load_custom_scales(path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn out_of_scale_pitches_move_to_the_nearest_degree() {
+        let c_major = Scale::new(ScaleType::Major, 0);
+        assert_eq!(c_major.nearest_pitch(60), 60);
+        // halfway between two degrees, the lower one is taken
+        assert_eq!(c_major.nearest_pitch(61), 60);
+        assert_eq!(c_major.nearest_pitch(66), 65);
+
+        let c_harmonic_minor = Scale::new(ScaleType::HarmonicMinor, 0);
+        // between Ab and B, A is closer to Ab and Bb to B
+        assert_eq!(c_harmonic_minor.nearest_pitch(69), 68);
+        assert_eq!(c_harmonic_minor.nearest_pitch(70), 71);
+
+        // below the lowest and above the highest pitch of the scale
+        let d_major = Scale::new(ScaleType::Major, 2);
+        assert_eq!(d_major.midi_range[0], 1);
+        assert_eq!(d_major.nearest_pitch(0), 1);
+        let e_major = Scale::new(ScaleType::Major, 4);
+        assert_eq!(e_major.midi_range.last(), Some(&126));
+        assert_eq!(e_major.nearest_pitch(127), 126);
+    }
+
+    #[test]
+    fn out_of_scale_pitches_below_the_scale_are_on_row_minus_1() {
+        let d_major = Scale::new(ScaleType::Major, 2);
+        assert_eq!(d_major.row(0), -0.5);
+        assert_eq!(d_major.floor_scale_index(0), (0, -1));
+        assert_eq!(d_major.pitches_above_row(-1), 0..1);
+        assert_eq!(d_major.pitches_above_row(-2), 0..0);
+        // D# is between D and E
+        assert_eq!(d_major.pitches_above_row(1), 3..4);
+        // the last row has nothing above it
+        let last_row = d_major.midi_size() as isize - 1;
+        assert_eq!(d_major.pitches_above_row(last_row), 128..128);
+        assert!(d_major.pitches_above_row(last_row + 1).is_empty());
+
+        let e_major = Scale::new(ScaleType::Major, 4);
+        assert_eq!(e_major.pitches_above_row(e_major.midi_size() as isize - 1), 127..128);
+    }
+}
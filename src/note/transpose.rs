@@ -0,0 +1,179 @@
+//! Transpose
+//!
+//! Moves notes by semitones, by octaves, or by degrees of a scale, so that C-E-G in C
+//! major becomes D-F-A one degree up. A note out of the scale moves by degrees from
+//! the degree below it and keeps its alteration. A note that would leave the midi range
+//! is brought back into it by octaves, and counted, so that the editor can tell how
+//! many notes did not move by the whole interval.
+//!
+//! Changing key maps every note from one scale to another degree by degree, so that
+//! C-E-G in C major becomes A-C-E in A minor, and C# becomes A#. The root moves by the
+//! smallest interval, up or down, so that the notes stay in the same register.
+
+use crate::note::midi_notes::{MidiNote, MidiNotes, Pitch};
+use crate::note::scale::Scale;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Semitones(i8),
+    Octaves(i8),
+    // degrees of the scale, which are the rows of the grid
+    Degrees(i8),
+}
+
+impl Interval {
+    // the transposed pitch, and whether it had to be brought back into the midi range
+    pub fn transpose(&self, pitch: u8, scale: &Scale) -> (u8, bool) {
+        let mut target = match *self {
+            Interval::Semitones(semitones) => pitch as i16 + semitones as i16,
+            Interval::Octaves(octaves) => pitch as i16 + 12 * octaves as i16,
+            Interval::Degrees(degrees) => {
+                let (index, alteration) = scale.floor_scale_index(pitch);
+                let mut index = index as i16 + degrees as i16;
+
+                // past the ends of the grid, the degree is found in the octave of the
+                // scale that is in the grid, then moved by octaves
+                let (size, degrees_per_octave) = (scale.midi_size() as i16, scale.size() as i16);
+                let mut octaves = 0;
+                while index >= size {
+                    index -= degrees_per_octave;
+                    octaves += 1;
+                }
+                while index < 0 {
+                    index += degrees_per_octave;
+                    octaves -= 1;
+                }
+                scale.midi_range[index as usize] as i16 + alteration as i16 + 12 * octaves
+            }
+        };
+
+        let is_clamped = !(0..128).contains(&target);
+        while target > 127 {
+            target -= 12;
+        }
+        while target < 0 {
+            target += 12;
+        }
+        (target as u8, is_clamped)
+    }
+}
+
+// the pitch from one scale to the other, and whether it had to be brought back into
+// the midi range
+pub fn change_key(pitch: u8, from: &Scale, to: &Scale) -> (u8, bool) {
+    let (from_intervals, to_intervals) = (from.get_root_base_notes(), to.get_root_base_notes());
+    let root_shift = (to.root as i16 - from.root as i16 + 6).rem_euclid(12) - 6;
+
+    // the chromatic scale has no degrees to follow, so the notes only move with the root
+    if from_intervals.len() == 12 || to_intervals.len() == 12 {
+        return Interval::Semitones(root_shift as i8).transpose(pitch, to);
+    }
+
+    let relative = pitch as i16 - from.root as i16;
+    let (octave, pitch_class) = (relative.div_euclid(12), relative.rem_euclid(12) as u8);
+
+    // the degree at or below the pitch, and the alteration of the pitch from it
+    let degree = from_intervals.iter().rposition(|&x| x <= pitch_class).unwrap_or(0);
+    let alteration = pitch_class as i16 - from_intervals[degree] as i16;
+
+    // scales with a different number of degrees are mapped proportionally
+    let to_degree = (degree * to_intervals.len() + from_intervals.len() / 2) / from_intervals.len();
+    let (octave, to_degree) = if to_degree >= to_intervals.len() {
+        (octave + 1, to_degree - to_intervals.len())
+    } else {
+        (octave, to_degree)
+    };
+
+    let mut target =
+        from.root as i16 + root_shift + 12 * octave + to_intervals[to_degree] as i16 + alteration;
+
+    let is_clamped = !(0..128).contains(&target);
+    while target > 127 {
+        target -= 12;
+    }
+    while target < 0 {
+        target += 12;
+    }
+    (target as u8, is_clamped)
+}
+
+// the transposed notes, and how many of them had to be brought back into the midi range
+pub fn transpose_notes(
+    notes: &MidiNotes,
+    interval: Interval,
+    scale: &Scale,
+) -> (Vec<MidiNote>, usize) {
+    let mut clamped = 0;
+    let transposed = notes
+        .notes
+        .iter()
+        .flatten()
+        .map(|note| {
+            let (pitch, is_clamped) = interval.transpose(note.get_pitch(), scale);
+            if is_clamped {
+                clamped += 1;
+            }
+            MidiNote { pitch: Pitch::new(pitch), ..note.clone() }
+        })
+        .collect();
+    (transposed, clamped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::scale::ScaleType;
+
+    fn c_major() -> Scale {
+        Scale::new(ScaleType::Major, 0)
+    }
+
+    fn transpose_all(pitches: &[u8], interval: Interval, scale: &Scale) -> Vec<u8> {
+        pitches.iter().map(|&pitch| interval.transpose(pitch, scale).0).collect()
+    }
+
+    #[test]
+    fn degrees_follow_the_scale() {
+        let scale = c_major();
+        // C-E-G becomes D-F-A one degree up, and B-D-F one degree down
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Degrees(1), &scale), [62, 65, 69]);
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Degrees(-1), &scale), [59, 62, 65]);
+        // seven degrees are an octave
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Degrees(7), &scale), [72, 76, 79]);
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Degrees(-7), &scale), [48, 52, 55]);
+    }
+
+    #[test]
+    fn semitones_and_octaves_ignore_the_scale() {
+        let scale = c_major();
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Semitones(1), &scale), [61, 65, 68]);
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Semitones(-13), &scale), [47, 51, 54]);
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Octaves(2), &scale), [84, 88, 91]);
+        assert_eq!(transpose_all(&[60, 64, 67], Interval::Octaves(-1), &scale), [48, 52, 55]);
+    }
+
+    #[test]
+    fn notes_out_of_the_scale_keep_their_alteration() {
+        let scale = c_major();
+        // C# moves from C and stays a semitone above the degree it lands on
+        assert_eq!(Interval::Degrees(1).transpose(61, &scale), (63, false));
+        assert_eq!(Interval::Degrees(-1).transpose(61, &scale), (60, false));
+        // F# moves from F to G#, and Eb from D to F#
+        assert_eq!(Interval::Degrees(1).transpose(66, &scale), (68, false));
+        assert_eq!(Interval::Degrees(2).transpose(63, &scale), (66, false));
+    }
+
+    #[test]
+    fn pitches_out_of_the_midi_range_are_brought_back_by_octaves() {
+        let scale = c_major();
+        assert_eq!(Interval::Semitones(5).transpose(125, &scale), (118, true));
+        assert_eq!(Interval::Octaves(-1).transpose(5, &scale), (5, true));
+        assert_eq!(Interval::Octaves(1).transpose(127, &scale), (127, true));
+        // G8 is the highest G, and C-1 the lowest C
+        assert_eq!(Interval::Degrees(1).transpose(127, &scale), (117, true));
+        assert_eq!(Interval::Degrees(-1).transpose(0, &scale), (11, true));
+        // the ends of the range themselves are kept
+        assert_eq!(Interval::Semitones(0).transpose(127, &scale), (127, false));
+        assert_eq!(Interval::Degrees(0).transpose(0, &scale), (0, false));
+    }
+}
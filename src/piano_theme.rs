@@ -1,6 +1,6 @@
 use iced::{
     application,
-    widget::{button, container, scrollable, text},
+    widget::{button, container, scrollable, text, text_input},
     Color,
 };
 
@@ -28,6 +28,7 @@ pub struct TrackTheme {
 
     pub grid_background: Color,
 
+    pub grid_bar_line: Color,
     pub grid_beat_line: Color,
     pub grid_subbeat_line: Color,
     pub grid_row_line: Color,
@@ -39,8 +40,14 @@ pub struct TrackTheme {
     pub grid_dark_column: Color,
 
     pub piano_background: Color,
+    pub piano_white_key: Color,
+    pub piano_black_key: Color,
+    // the keys in the scale of the track, and its root
+    pub piano_scale_key: Color,
+    pub piano_root_key: Color,
     pub background: Color,
     pub player_head: Color,
+    pub loop_region: Color,
 
     currant_line: Color,
 
@@ -53,6 +60,8 @@ pub struct TrackTheme {
     pub note: Color,
     pub selected_note: Color,
     pub note_contour: Color,
+    // the contour of the notes that are out of the scale of the track
+    pub out_of_key_note: Color,
 }
 
 impl TrackTheme {
@@ -101,6 +110,7 @@ impl TrackTheme {
 
             grid_background: transparent_dark,
 
+            grid_bar_line: Self::xa(black, 0.6),
             grid_beat_line: Self::x(transparent_dark2, 2.0),
             grid_subbeat_line: Self::x(transparent_dark2, 1.5),
             grid_row_line: Self::x(transparent_dark2, 1.1),
@@ -114,9 +124,15 @@ impl TrackTheme {
             note: green,
             selected_note: selected,
             note_contour: grey,
+            out_of_key_note: red,
 
             piano_background: color!(30, 30, 33),
+            piano_white_key: color!(200, 196, 184),
+            piano_black_key: color!(25, 28, 30),
+            piano_scale_key: Color::from_rgba8(105, 130, 105, 0.35),
+            piano_root_key: Color::from_rgba8(105, 130, 105, 0.8),
             player_head: color!(30, 180, 200),
+            loop_region: Color::from_rgba8(30, 180, 200, 0.15),
 
             background: color!(60, 60, 60),
             currant_line: color!(68, 71, 255),
@@ -294,6 +310,41 @@ impl text::StyleSheet for TrackTheme {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TextInput {
+    #[default]
+    Default,
+}
+
+impl text_input::StyleSheet for TrackTheme {
+    type Style = TextInput;
+
+    fn active(&self, _style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance {
+            background: self.piano_background.into(),
+            border_radius: 2.0,
+            border_width: 1.0,
+            border_color: self.track_contour,
+        }
+    }
+
+    fn focused(&self, style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance { border_color: self.player_head, ..self.active(style) }
+    }
+
+    fn placeholder_color(&self, _style: &Self::Style) -> Color {
+        Self::xa(self.text, 0.5)
+    }
+
+    fn value_color(&self, _style: &Self::Style) -> Color {
+        self.text
+    }
+
+    fn selection_color(&self, _style: &Self::Style) -> Color {
+        self.selected_note
+    }
+}
+
 /// The style of a scrollable.
 #[derive(Default)]
 pub enum Scrollable {
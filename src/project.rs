@@ -0,0 +1,432 @@
+//! Project files
+//!
+//! A project is saved as pretty-printed json so that it can be diffed and versioned.
+//! Every file carries a schema version. When a file from an older version is opened,
+//! the raw json goes through the migrations in MIGRATIONS, one version at a time,
+//! before being read into the current schema. Fields that were added without
+//! changing the meaning of the others only need a #[serde(default)].
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::fmt;
+use std::path::Path;
+
+use iced::Vector;
+
+use crate::audio::metronome::MetronomeSettings;
+use crate::grid::Grid;
+use crate::note::midi_notes::MidiNotes;
+use crate::note::scale::Scale;
+use crate::tick::{Tick, FIRST_TICK, PPQ};
+use crate::timeline::LoopRegion;
+use crate::track::{TimingInfo, Track, TrackMeta};
+use crate::util::TrackId;
+
+pub const PROJECT_VERSION: u32 = 3;
+
+// MIGRATIONS[i] converts a file of version i + 1 into a file of version i + 2
+const MIGRATIONS: [fn(&mut Value); 2] = [beats_to_ticks, tempo_and_meter_tracks];
+
+// version 1 stored the positions as float beats, and version 2 as integer ticks
+fn beats_to_ticks(project: &mut Value) {
+    fn convert(value: Option<&mut Value>) {
+        if let Some(value) = value {
+            if let Some(beats) = value.as_f64() {
+                *value = Value::from((beats * PPQ as f64).round() as Tick);
+            }
+        }
+    }
+
+    convert(project.get_mut("main_player_head"));
+
+    if let Some(tracks) = project.get_mut("tracks").and_then(Value::as_array_mut) {
+        for track in tracks {
+            convert(track.get_mut("player_head"));
+
+            for field in ["notes", "selected_notes"] {
+                if let Some(notes) = track.get_mut(field).and_then(Value::as_array_mut) {
+                    for note in notes {
+                        convert(note.get_mut("start"));
+                        convert(note.get_mut("end"));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// version 2 had a single tempo and time signature, and version 3 has a tempo track
+// and a meter track
+fn tempo_and_meter_tracks(project: &mut Value) {
+    let timing_info = match project.get_mut("timing_info").and_then(Value::as_object_mut) {
+        Some(timing_info) => timing_info,
+        None => return,
+    };
+
+    if let Some(bpm) = timing_info.remove("bpm").and_then(|bpm| bpm.as_f64()) {
+        timing_info.insert("tempo_track".to_string(), json!([{ "tick": FIRST_TICK, "bpm": bpm }]));
+    }
+
+    if let Some(time_signature) = timing_info.remove("time_signature") {
+        if let (Some(numerator), Some(denominator)) = (
+            time_signature.get(0).and_then(Value::as_u64),
+            time_signature.get(1).and_then(Value::as_u64),
+        ) {
+            timing_info.insert(
+                "meter_track".to_string(),
+                json!([{ "bar": 1, "numerator": numerator, "denominator": denominator }]),
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    MissingVersion,
+}
+
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectError::Io(e) => write!(f, "could not access project file: {}", e),
+            ProjectError::Json(e) => write!(f, "invalid project file: {}", e),
+            ProjectError::MissingVersion => write!(f, "the project file has no version"),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<std::io::Error> for ProjectError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProjectError {
+    fn from(e: serde_json::Error) -> Self {
+        ProjectError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub version: u32,
+    #[serde(default)]
+    pub timing_info: TimingInfo,
+    #[serde(default)]
+    pub main_player_head: Tick,
+    #[serde(default)]
+    pub loop_region: Option<LoopRegion>,
+    #[serde(default)]
+    pub is_looping: bool,
+    #[serde(default)]
+    pub metronome: MetronomeSettings,
+    #[serde(default)]
+    pub active_track: Option<TrackId>,
+    pub track_order: Vec<TrackId>,
+    pub tracks: Vec<TrackFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackFile {
+    pub track_id: TrackId,
+    #[serde(default)]
+    pub meta: TrackMeta,
+    #[serde(default)]
+    pub channel: u8,
+    #[serde(default)]
+    pub program: u8,
+    #[serde(default)]
+    pub is_muted: bool,
+    #[serde(default)]
+    pub is_soloed: bool,
+    #[serde(default)]
+    pub is_armed: bool,
+    #[serde(default)]
+    pub notes: MidiNotes,
+    #[serde(default)]
+    pub selected_notes: MidiNotes,
+    #[serde(default)]
+    pub grid: GridFile,
+    #[serde(default)]
+    pub player_head: Tick,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GridFile {
+    pub scale: Scale,
+    pub beat_fraction: f32,
+    pub max_beats: usize,
+    pub translation: (f32, f32),
+    pub scaling: (f32, f32),
+}
+
+impl Default for GridFile {
+    fn default() -> Self {
+        Self::from_grid(&Grid::default())
+    }
+}
+
+impl GridFile {
+    pub fn from_grid(grid: &Grid) -> Self {
+        Self {
+            scale: grid.scale.clone(),
+            beat_fraction: grid.beat_fraction,
+            max_beats: grid.max_beats,
+            translation: (grid.translation.x, grid.translation.y),
+            scaling: (grid.scaling.x, grid.scaling.y),
+        }
+    }
+
+    pub fn into_grid(self) -> Grid {
+        Grid {
+            scale: self.scale,
+            beat_fraction: self.beat_fraction,
+            max_beats: self.max_beats,
+            translation: Vector::new(self.translation.0, self.translation.1),
+            scaling: Vector::new(self.scaling.0, self.scaling.1),
+        }
+    }
+}
+
+impl TrackFile {
+    pub fn from_track(track: &Track) -> Self {
+        Self {
+            track_id: track.id(),
+            meta: track.meta.clone(),
+            channel: track.channel,
+            program: track.program,
+            is_muted: track.is_muted,
+            is_soloed: track.is_soloed,
+            is_armed: track.is_armed,
+            notes: track.midi_notes.clone(),
+            selected_notes: track.selected.notes.clone(),
+            grid: GridFile::from_grid(&track.grid),
+            player_head: track.player_head,
+        }
+    }
+
+    pub fn into_track(self, timing_info: TimingInfo) -> Track {
+        let mut track = Track::with_notes(self.track_id, self.notes);
+        track.selected.notes = self.selected_notes;
+        track.meta = self.meta;
+        track.channel = self.channel;
+        track.program = self.program;
+        track.is_muted = self.is_muted;
+        track.is_soloed = self.is_soloed;
+        track.is_armed = self.is_armed;
+        track.grid = self.grid.into_grid();
+        track.player_head = self.player_head;
+        track.timing_info = timing_info;
+        track
+    }
+}
+
+impl ProjectFile {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    pub fn to_json(&self) -> Result<String, ProjectError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ProjectError> {
+        let mut value: Value = serde_json::from_str(json)?;
+
+        let version =
+            value.get("version").and_then(Value::as_u64).ok_or(ProjectError::MissingVersion)?
+                as u32;
+
+        // files from a newer version are read as well as possible: unknown fields are ignored
+        if version > PROJECT_VERSION {
+            println!(
+                "Project file version {} is newer than the supported version {}",
+                version, PROJECT_VERSION
+            );
+        }
+
+        for migration in MIGRATIONS.iter().skip(version.saturating_sub(1) as usize) {
+            migration(&mut value);
+        }
+
+        if version < PROJECT_VERSION {
+            value["version"] = Value::from(PROJECT_VERSION);
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_VELOCITY;
+    use crate::note::midi_notes::{MidiNote, Pitch};
+    use crate::timeline::{MeterEvent, TempoEvent};
+
+    // (start, end, pitch, velocity) of the notes, ordered by pitch and then by time
+    fn notes(midi_notes: &MidiNotes) -> Vec<(Tick, Tick, i16, u8)> {
+        Vec::<MidiNote>::from(midi_notes.clone())
+            .iter()
+            .map(|note| (note.start, note.end, note.pitch.0, note.velocity))
+            .collect()
+    }
+
+    fn track_file(track_id: TrackId, notes: Vec<MidiNote>) -> TrackFile {
+        TrackFile {
+            track_id,
+            meta: TrackMeta::default(),
+            channel: 0,
+            program: 0,
+            is_muted: false,
+            is_soloed: false,
+            is_armed: false,
+            notes: MidiNotes::from(notes),
+            selected_notes: MidiNotes::new(),
+            grid: GridFile::default(),
+            player_head: FIRST_TICK,
+        }
+    }
+
+    #[test]
+    fn version_1_positions_are_converted_from_beats_to_ticks() {
+        let json = r#"{
+            "version": 1,
+            "timing_info": { "bpm": 90.0, "time_signature": [3, 4], "track_length": 4.0 },
+            "main_player_head": 2.5,
+            "track_order": [7],
+            "tracks": [{
+                "track_id": 7,
+                "player_head": 1.25,
+                "notes": [{ "start": 1.0, "end": 1.5, "pitch": 60, "velocity": 90 }],
+                "selected_notes": [{ "start": 2.0, "end": 3.0, "pitch": 64 }]
+            }]
+        }"#;
+
+        let project = ProjectFile::from_json(json).unwrap();
+
+        assert_eq!(project.version, PROJECT_VERSION);
+        assert_eq!(project.main_player_head, 5 * PPQ / 2);
+        let track = &project.tracks[0];
+        assert_eq!(track.player_head, 5 * PPQ / 4);
+        assert_eq!(notes(&track.notes), [(PPQ, 3 * PPQ / 2, 60, 90)]);
+        assert_eq!(notes(&track.selected_notes), [(2 * PPQ, 3 * PPQ, 64, DEFAULT_VELOCITY)]);
+        assert_eq!(project.timing_info.tempo_track, [TempoEvent { tick: FIRST_TICK, bpm: 90.0 }]);
+        assert_eq!(
+            project.timing_info.meter_track,
+            [MeterEvent { bar: 1, numerator: 3, denominator: 4 }]
+        );
+    }
+
+    #[test]
+    fn version_2_tempo_and_time_signature_become_tracks() {
+        let json = r#"{
+            "version": 2,
+            "timing_info": { "bpm": 140.0, "time_signature": [7, 8], "track_length": 4.0 },
+            "main_player_head": 1920,
+            "track_order": [0],
+            "tracks": [{
+                "track_id": 0,
+                "player_head": 1440,
+                "notes": [{ "start": 960, "end": 1200, "pitch": 62, "velocity": 40 }]
+            }]
+        }"#;
+
+        let project = ProjectFile::from_json(json).unwrap();
+
+        assert_eq!(project.version, PROJECT_VERSION);
+        // the ticks of version 2 are kept as they are
+        assert_eq!(project.main_player_head, 1920);
+        assert_eq!(project.tracks[0].player_head, 1440);
+        assert_eq!(notes(&project.tracks[0].notes), [(960, 1200, 62, 40)]);
+        assert_eq!(project.timing_info.tempo_track, [TempoEvent { tick: FIRST_TICK, bpm: 140.0 }]);
+        assert_eq!(
+            project.timing_info.meter_track,
+            [MeterEvent { bar: 1, numerator: 7, denominator: 8 }]
+        );
+    }
+
+    #[test]
+    fn a_file_without_a_version_is_rejected() {
+        let result = ProjectFile::from_json(r#"{ "track_order": [], "tracks": [] }"#);
+        assert!(matches!(result, Err(ProjectError::MissingVersion)));
+    }
+
+    #[test]
+    fn a_saved_project_is_loaded_back() {
+        let mut drums = track_file(
+            1,
+            vec![
+                MidiNote::new(FIRST_TICK, FIRST_TICK + PPQ, Pitch::new(36)).with_velocity(127),
+                MidiNote::new(FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ, Pitch::new(38))
+                    .with_velocity(12),
+            ],
+        );
+        drums.channel = 9;
+        drums.program = 3;
+        drums.is_muted = true;
+        let mut bass = track_file(2, vec![MidiNote::new(FIRST_TICK, 3 * PPQ, Pitch::new(40))]);
+        bass.channel = 4;
+        bass.is_soloed = true;
+        bass.is_armed = true;
+
+        let mut timing_info = TimingInfo::default();
+        timing_info.tempo_track.push(TempoEvent { tick: 5 * PPQ, bpm: 100.0 });
+        timing_info.meter_track.push(MeterEvent { bar: 3, numerator: 6, denominator: 8 });
+
+        let project = ProjectFile {
+            version: PROJECT_VERSION,
+            timing_info,
+            main_player_head: 2 * PPQ,
+            loop_region: LoopRegion::new(FIRST_TICK, 9 * PPQ),
+            is_looping: true,
+            metronome: MetronomeSettings::default(),
+            active_track: Some(2),
+            track_order: vec![2, 1],
+            tracks: vec![bass, drums],
+        };
+
+        let path = std::env::temp_dir()
+            .join(format!("midi_composer_project_round_trip_{}.json", std::process::id()));
+        project.save(&path).unwrap();
+        let loaded = ProjectFile::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.version, PROJECT_VERSION);
+        assert_eq!(loaded.timing_info, project.timing_info);
+        assert_eq!(loaded.main_player_head, 2 * PPQ);
+        assert_eq!(loaded.loop_region, LoopRegion::new(FIRST_TICK, 9 * PPQ));
+        assert!(loaded.is_looping);
+        assert_eq!(loaded.metronome, project.metronome);
+        assert_eq!(loaded.active_track, Some(2));
+        assert_eq!(loaded.track_order, [2, 1]);
+        assert_eq!(loaded.tracks.len(), 2);
+        for (loaded, saved) in loaded.tracks.iter().zip(project.tracks.iter()) {
+            assert_eq!(loaded.track_id, saved.track_id);
+            assert_eq!(loaded.channel, saved.channel);
+            assert_eq!(loaded.program, saved.program);
+            assert_eq!(
+                (loaded.is_muted, loaded.is_soloed, loaded.is_armed),
+                (saved.is_muted, saved.is_soloed, saved.is_armed)
+            );
+            assert_eq!(notes(&loaded.notes), notes(&saved.notes));
+        }
+        assert_eq!(
+            notes(&loaded.tracks[1].notes)[1],
+            (FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ, 38, 12)
+        );
+    }
+}
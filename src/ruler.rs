@@ -0,0 +1,213 @@
+//! Ruler
+//!
+//! Drawn above the tracks with the time axis of the active track. The bars and beats
+//! follow the meter track, and the tempo and meter changes are written under the bar
+//! where they happen. The position of the playhead is shown as bar.beat.tick.
+//! Clicking on the ruler moves the playhead, and dragging on it sets the loop region,
+//! snapped to the beat fraction of the grid.
+
+use iced::widget::canvas::event::{self, Event};
+use iced::widget::canvas::{self, Canvas, Cursor, Frame, Geometry, Path, Stroke, Text};
+use iced::{alignment, mouse, Color, Length, Point, Rectangle, Size};
+
+use crate::config::RULER_HEIGHT;
+use crate::grid::Grid;
+use crate::piano_theme::TrackTheme;
+use crate::tick::{self, Tick};
+use crate::timeline::{LoopRegion, TimingInfo};
+use crate::transport::{Transport, TransportMessage};
+
+pub type RulerElement<'a> = iced::Element<'a, TransportMessage, iced::Renderer<TrackTheme>>;
+
+pub struct Ruler<'a> {
+    grid: &'a Grid,
+    timing_info: &'a TimingInfo,
+    transport: &'a Transport,
+}
+
+// the ends of the loop region being dragged, snapped to the grid
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RulerState {
+    drag: Option<(Tick, Tick)>,
+}
+
+impl<'a> Ruler<'a> {
+    pub fn new(grid: &'a Grid, timing_info: &'a TimingInfo, transport: &'a Transport) -> Self {
+        Self { grid, timing_info, transport }
+    }
+
+    fn snapped_tick(&self, x: f32, width: f32) -> Tick {
+        tick::snap(tick::from_beats(self.grid.x_to_beat(x, width)), self.grid.beat_fraction_ticks())
+    }
+
+    fn draw_loop_region(&self, frame: &mut Frame, loop_region: LoopRegion, color: Color) {
+        let size = frame.size();
+        let start = self.grid.tick_to_x(loop_region.start, size.width);
+        let end = self.grid.tick_to_x(loop_region.end, size.width);
+        frame.fill_rectangle(Point::new(start, 0.0), Size::new(end - start, size.height), color);
+    }
+
+    pub fn view(self) -> RulerElement<'a> {
+        Canvas::new(self).width(Length::Fill).height(Length::Units(RULER_HEIGHT)).into()
+    }
+
+    fn draw_bars(&self, frame: &mut Frame, theme: &TrackTheme) {
+        let size = frame.size();
+        let first_tick = tick::from_beats(self.grid.x_to_beat(0.0, size.width));
+        let last_tick = tick::from_beats(self.grid.x_to_beat(size.width, size.width));
+
+        // starts one bar early, so that the beats of a bar that is partly visible are drawn
+        let bar_length = self.timing_info.meter_at(first_tick).bar_length();
+        for (bar, bar_tick) in self.timing_info.bar_lines(first_tick - bar_length, last_tick) {
+            let meter = self.timing_info.meter_at(bar_tick);
+
+            for beat in 0..meter.numerator as Tick {
+                let x = self.grid.tick_to_x(bar_tick + beat * meter.beat_length(), size.width);
+                let top = if beat == 0 { 0.0 } else { size.height * 0.7 };
+                frame.stroke(
+                    &Path::line(Point::new(x, top), Point::new(x, size.height)),
+                    Stroke::default().with_width(1.0).with_color(theme.text),
+                );
+            }
+
+            let x = self.grid.tick_to_x(bar_tick, size.width);
+            frame.fill_text(Text {
+                content: format!("{}", bar),
+                position: Point::new(x + 3.0, 2.0),
+                color: theme.text,
+                size: 14.0,
+                horizontal_alignment: alignment::Horizontal::Left,
+                vertical_alignment: alignment::Vertical::Top,
+                ..Text::default()
+            });
+        }
+
+        // the changes are written under the number of their bar
+        let change_text = |content: String, tick: Tick| Text {
+            content,
+            position: Point::new(self.grid.tick_to_x(tick, size.width) + 3.0, 16.0),
+            color: theme.player_head,
+            size: 12.0,
+            horizontal_alignment: alignment::Horizontal::Left,
+            vertical_alignment: alignment::Vertical::Top,
+            ..Text::default()
+        };
+
+        for meter in self.timing_info.meter_track.iter() {
+            let bar_tick = self.timing_info.bar_start(meter.bar);
+            if bar_tick >= first_tick && bar_tick <= last_tick {
+                let content = format!("{}/{}", meter.numerator, meter.denominator);
+                frame.fill_text(change_text(content, bar_tick));
+            }
+        }
+
+        for tempo in self.timing_info.tempo_track.iter() {
+            if tempo.tick >= first_tick && tempo.tick <= last_tick {
+                let mut text = change_text(format!("{:.0} bpm", tempo.bpm), tempo.tick);
+                text.position.x += 30.0;
+                frame.fill_text(text);
+            }
+        }
+    }
+}
+
+impl<'a> canvas::Program<TransportMessage, TrackTheme> for Ruler<'a> {
+    type State = RulerState;
+
+    fn update(
+        &self,
+        state: &mut RulerState,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<TransportMessage>) {
+        // the drag goes on when the cursor leaves the ruler
+        let x = match (cursor.position_in(&bounds), cursor.position(), state.drag) {
+            (Some(position), ..) => position.x,
+            (None, Some(position), Some(_)) => (position.x - bounds.x).clamp(0.0, bounds.width),
+            _ => return (event::Status::Ignored, None),
+        };
+        let snapped_tick = self.snapped_tick(x, bounds.width);
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                state.drag = Some((snapped_tick, snapped_tick));
+                (event::Status::Captured, None)
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => match state.drag {
+                Some((start, _)) => {
+                    state.drag = Some((start, snapped_tick));
+                    (event::Status::Captured, None)
+                }
+                None => (event::Status::Ignored, None),
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                match state.drag.take() {
+                    // a click, without a drag, moves the playhead where it is
+                    Some((start, end)) if start == end => {
+                        let beat = self.grid.x_to_beat(x, bounds.width);
+                        (
+                            event::Status::Captured,
+                            Some(TransportMessage::Seek(tick::from_beats(beat))),
+                        )
+                    }
+                    Some((start, end)) => (
+                        event::Status::Captured,
+                        Some(TransportMessage::SetLoop(LoopRegion::new(start, end))),
+                    ),
+                    None => (event::Status::Ignored, None),
+                }
+            }
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        state: &RulerState,
+        theme: &TrackTheme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let size = frame.size();
+
+        frame.fill_rectangle(Point::ORIGIN, size, theme.grid_background);
+
+        if let Some(loop_region) = state.drag.and_then(|(start, end)| LoopRegion::new(start, end)) {
+            self.draw_loop_region(&mut frame, loop_region, theme.loop_region);
+        } else if let Some(loop_region) = self.transport.loop_region {
+            let color = if self.transport.is_looping {
+                theme.loop_region
+            } else {
+                TrackTheme::xa(theme.loop_region, 0.4)
+            };
+            self.draw_loop_region(&mut frame, loop_region, color);
+        }
+
+        self.draw_bars(&mut frame, theme);
+
+        let x = self.grid.tick_to_x(self.transport.position, size.width);
+        frame.stroke(
+            &Path::line(Point::new(x, 0.0), Point::new(x, size.height)),
+            Stroke::default().with_width(1.0).with_color(theme.player_head),
+        );
+
+        frame.fill_text(Text {
+            content: format!("{}", self.timing_info.bar_beat_tick(self.transport.position)),
+            position: Point::new(size.width - 4.0, size.height - 2.0),
+            color: theme.player_head,
+            size: 14.0,
+            horizontal_alignment: alignment::Horizontal::Right,
+            vertical_alignment: alignment::Vertical::Bottom,
+            ..Text::default()
+        });
+
+        frame.stroke(
+            &Path::rectangle(Point::ORIGIN, size),
+            Stroke::default().with_width(1.0).with_color(theme.track_contour),
+        );
+
+        vec![frame.into_geometry()]
+    }
+}
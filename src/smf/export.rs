@@ -0,0 +1,317 @@
+//! Standard MIDI File export
+//!
+//! The tracks are written as a format-1 file: the first SMF track only holds the
+//! tempo and time signature changes, along with the loop region as a pair of cue
+//! markers, and every editor track gets its own SMF track.
+
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use std::path::Path;
+
+use crate::note::midi_notes::MidiNotes;
+use crate::tick::{Tick, FIRST_TICK, PPQ};
+use crate::timeline::LoopRegion;
+use crate::track::{TimingInfo, Track};
+
+pub const LOOP_START_CUE: &[u8] = b"Loop start";
+pub const LOOP_END_CUE: &[u8] = b"Loop end";
+
+// An absolute-time event, converted to a delta-time TrackEvent once sorted
+struct TimedEvent<'a> {
+    tick: u32,
+    kind: TrackEventKind<'a>,
+}
+
+// from the ticks of the editor to the ticks of the file, which start at the first beat
+pub fn to_file_ticks(tick: Tick, ppq: u16) -> u32 {
+    (((tick - FIRST_TICK).max(0) * ppq as Tick + PPQ / 2) / PPQ) as u32
+}
+
+pub fn export_file<'a>(
+    path: impl AsRef<Path>,
+    tracks: impl IntoIterator<Item = &'a Track>,
+    timing_info: &TimingInfo,
+    loop_region: Option<LoopRegion>,
+    ppq: u16,
+) -> std::io::Result<()> {
+    tracks_to_smf(tracks, timing_info, loop_region, ppq).save(path)
+}
+
+pub fn tracks_to_smf<'a>(
+    tracks: impl IntoIterator<Item = &'a Track>,
+    timing_info: &TimingInfo,
+    loop_region: Option<LoopRegion>,
+    ppq: u16,
+) -> Smf<'a> {
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(u15::new(ppq))));
+
+    smf.tracks.push(conductor_track(timing_info, loop_region, ppq));
+
+    for track in tracks {
+        let mut timed_events = vec![TimedEvent {
+            tick: 0,
+            kind: TrackEventKind::Meta(MetaMessage::TrackName(track.meta.name.as_bytes())),
+        }];
+
+        timed_events.push(TimedEvent {
+            tick: 0,
+            kind: TrackEventKind::Midi {
+                channel: u4::new(track.channel.min(15)),
+                message: MidiMessage::ProgramChange { program: u7::new(track.program.min(127)) },
+            },
+        });
+
+        // the notes are split between the selected and the non-selected notes
+        timed_events.extend(note_events(&track.midi_notes, track.channel, ppq));
+        timed_events.extend(note_events(&track.selected.notes, track.channel, ppq));
+
+        smf.tracks.push(to_track_events(timed_events));
+    }
+
+    smf
+}
+
+fn conductor_track<'a>(
+    timing_info: &TimingInfo,
+    loop_region: Option<LoopRegion>,
+    ppq: u16,
+) -> Vec<TrackEvent<'a>> {
+    let mut timed_events: Vec<TimedEvent<'a>> = timing_info
+        .tempo_track
+        .iter()
+        .map(|tempo| {
+            let micros_per_beat = (60_000_000.0 / tempo.bpm.max(1.0)).round() as u32;
+            TimedEvent {
+                tick: to_file_ticks(tempo.tick, ppq),
+                kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(micros_per_beat))),
+            }
+        })
+        .collect();
+
+    for meter in timing_info.meter_track.iter() {
+        // the denominator is written as a power of two
+        let denominator_power = (meter.denominator.max(1) as f32).log2().round() as u8;
+
+        timed_events.push(TimedEvent {
+            tick: to_file_ticks(timing_info.bar_start(meter.bar), ppq),
+            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(
+                meter.numerator,
+                denominator_power,
+                24,
+                8,
+            )),
+        });
+    }
+
+    if let Some(loop_region) = loop_region {
+        timed_events.push(TimedEvent {
+            tick: to_file_ticks(loop_region.start, ppq),
+            kind: TrackEventKind::Meta(MetaMessage::CuePoint(LOOP_START_CUE)),
+        });
+        timed_events.push(TimedEvent {
+            tick: to_file_ticks(loop_region.end, ppq),
+            kind: TrackEventKind::Meta(MetaMessage::CuePoint(LOOP_END_CUE)),
+        });
+    }
+
+    to_track_events(timed_events)
+}
+
+fn note_events<'a>(midi_notes: &MidiNotes, channel: u8, ppq: u16) -> Vec<TimedEvent<'a>> {
+    let channel = u4::new(channel.min(15));
+    let mut timed_events = Vec::with_capacity(midi_notes.number_of_notes * 2);
+
+    for note in midi_notes.notes.iter().flatten() {
+        let key = u7::new(note.pitch.get().min(127));
+        let start_tick = to_file_ticks(note.start, ppq);
+        let end_tick = to_file_ticks(note.end, ppq).max(start_tick + 1);
+
+        timed_events.push(TimedEvent {
+            tick: start_tick,
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel: u7::new(note.velocity.max(1)) },
+            },
+        });
+
+        timed_events.push(TimedEvent {
+            tick: end_tick,
+            kind: TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff { key, vel: u7::new(0) },
+            },
+        });
+    }
+
+    timed_events
+}
+
+fn to_track_events<'a>(mut timed_events: Vec<TimedEvent<'a>>) -> Vec<TrackEvent<'a>> {
+    // on the same tick, a NoteOff must come before a NoteOn, otherwise two consecutive
+    // notes of the same pitch would be read as a single long note
+    let order = |kind: &TrackEventKind| match kind {
+        TrackEventKind::Meta(_) => 0,
+        TrackEventKind::Midi { message: MidiMessage::ProgramChange { .. }, .. } => 1,
+        TrackEventKind::Midi { message: MidiMessage::NoteOff { .. }, .. } => 2,
+        _ => 3,
+    };
+    timed_events.sort_by_key(|event| (event.tick, order(&event.kind)));
+
+    let mut last_tick = 0;
+    let mut track_events: Vec<TrackEvent<'a>> = timed_events
+        .into_iter()
+        .map(|event| {
+            let delta = u28::new(event.tick - last_tick);
+            last_tick = event.tick;
+            TrackEvent { delta, kind: event.kind }
+        })
+        .collect();
+
+    track_events.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    track_events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::midi_notes::{MidiNote, Pitch};
+    use crate::smf::import::{import_file, ImportedSong};
+
+    type Note = (u8, Tick, Tick, u8);
+
+    fn note((pitch, start, end, velocity): Note) -> MidiNote {
+        MidiNote::new(start, end, Pitch::new(pitch)).with_velocity(velocity)
+    }
+
+    fn track(track_id: u32, name: &str, channel: u8, program: u8, notes: &[Note]) -> Track {
+        let mut track = Track::with_notes(
+            track_id,
+            MidiNotes::from(notes.iter().map(|n| note(*n)).collect::<Vec<_>>()),
+        );
+        track.meta.name = name.to_string();
+        track.channel = channel;
+        track.program = program;
+        track
+    }
+
+    fn notes_of(notes: &MidiNotes) -> Vec<Note> {
+        let mut notes: Vec<Note> = notes
+            .notes
+            .iter()
+            .flatten()
+            .map(|note| (note.get_pitch(), note.start, note.end, note.velocity))
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    // through a file, as the editor exports and imports it, named after the test
+    fn round_trip(
+        test_name: &str,
+        tracks: &[Track],
+        timing_info: &TimingInfo,
+        ppq: u16,
+    ) -> ImportedSong {
+        let path = std::env::temp_dir().join(format!(
+            "midi_composer_export_{}_{}.mid",
+            std::process::id(),
+            test_name
+        ));
+        export_file(&path, tracks, timing_info, None, ppq).unwrap();
+        let song = import_file(&path);
+        let _ = std::fs::remove_file(&path);
+        song.unwrap()
+    }
+
+    fn round_trip_keeps_the_song(test_name: &str, ppq: u16) {
+        let beat = PPQ;
+        let piano: [Note; 4] = [
+            (60, FIRST_TICK, FIRST_TICK + beat, 100),
+            // starts on the tick where the previous note of its pitch ends
+            (60, FIRST_TICK + beat, FIRST_TICK + 2 * beat, 64),
+            (64, FIRST_TICK + beat / 2, FIRST_TICK + 3 * beat, 1),
+            // a triplet
+            (67, FIRST_TICK + beat / 3, FIRST_TICK + 2 * beat / 3, 127),
+        ];
+        let bass: [Note; 2] = [
+            (36, FIRST_TICK, FIRST_TICK + 4 * beat, 90),
+            (43, FIRST_TICK + 8 * beat, FIRST_TICK + 9 * beat + beat / 4, 80),
+        ];
+        let tracks = [track(0, "Piano", 0, 0, &piano), track(1, "Bass", 9, 33, &bass)];
+
+        let mut timing_info = TimingInfo::default();
+        timing_info.set_meter(2, 3, 4);
+        timing_info.set_meter(4, 6, 8);
+        timing_info.set_tempo(3, 90.0);
+
+        let song = round_trip(test_name, &tracks, &timing_info, ppq);
+
+        // the tempo and meter track has no notes, so it gives no track
+        assert_eq!(song.tracks.len(), 2);
+        for (imported, (track, notes)) in
+            song.tracks.iter().zip(tracks.iter().zip([&piano[..], &bass[..]]))
+        {
+            assert_eq!(imported.name, track.meta.name);
+            assert_eq!(imported.channel, track.channel);
+            assert_eq!(imported.program, track.program);
+
+            let mut expected = notes.to_vec();
+            expected.sort();
+            assert_eq!(notes_of(&imported.notes), expected, "ppq {}", ppq);
+        }
+
+        let imported_timing = TimingInfo::from_tempo_map(&song.tempo_map);
+        assert_eq!(imported_timing.meter_track, timing_info.meter_track);
+        assert_eq!(imported_timing.tempo_track.len(), timing_info.tempo_track.len());
+        for (imported, tempo) in
+            imported_timing.tempo_track.iter().zip(timing_info.tempo_track.iter())
+        {
+            assert_eq!(imported.tick, tempo.tick);
+            // tempos are written in microseconds per beat
+            assert!((imported.bpm - tempo.bpm).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn round_trip_at_the_resolution_of_the_editor() {
+        round_trip_keeps_the_song("editor_resolution", PPQ as u16);
+    }
+
+    #[test]
+    fn round_trip_at_a_lower_resolution() {
+        round_trip_keeps_the_song("lower_resolution", 96);
+    }
+
+    #[test]
+    fn note_offs_come_before_note_ons_on_the_same_tick() {
+        let notes: [Note; 2] = [
+            (60, FIRST_TICK, FIRST_TICK + PPQ, 100),
+            (60, FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ, 100),
+        ];
+        let tracks = [track(0, "Piano", 0, 0, &notes)];
+        let smf = tracks_to_smf(&tracks, &TimingInfo::default(), None, 480);
+
+        let mut tick = 0;
+        let events: Vec<(u32, bool)> = smf.tracks[1]
+            .iter()
+            .filter_map(|event| {
+                tick += event.delta.as_int();
+                match event.kind {
+                    TrackEventKind::Midi { message: MidiMessage::NoteOn { .. }, .. } => {
+                        Some((tick, true))
+                    }
+                    TrackEventKind::Midi { message: MidiMessage::NoteOff { .. }, .. } => {
+                        Some((tick, false))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        assert_eq!(events, [(0, true), (480, false), (480, true), (960, false)]);
+    }
+}
@@ -0,0 +1,220 @@
+//! Standard MIDI File import
+//!
+//! Every SMF track is split by channel, so a format-0 file (one track holding all
+//! sixteen channels) gives one editor track per channel, and a format-1 file gives
+//! one editor track per SMF track.
+
+use midly::num::{u4, u7};
+use midly::{MetaMessage, MidiMessage, Smf, TrackEventKind};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::note::midi_notes::{MidiNote, MidiNotes, Pitch};
+use crate::note::scale::ScaleType;
+use crate::smf::tempo_map::TempoMap;
+use crate::tick::{self, NOTE_MIN_TICKS};
+use crate::track::Track;
+use crate::util::TrackId;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Parse(midly::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "could not read midi file: {}", e),
+            ImportError::Parse(e) => write!(f, "could not parse midi file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<midly::Error> for ImportError {
+    fn from(e: midly::Error) -> Self {
+        ImportError::Parse(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NotePress {
+    start_tick: u32,
+    vel: u7,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NoteOnHash {
+    channel: u4,
+    key: u7,
+}
+
+type NotePresses = HashMap<NoteOnHash, NotePress>;
+
+// The notes of a single channel of a single SMF track
+#[derive(Debug, Clone)]
+pub struct ImportedTrack {
+    pub name: String,
+    pub channel: u8,
+    pub program: u8,
+    pub notes: MidiNotes,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedSong {
+    pub tracks: Vec<ImportedTrack>,
+    pub tempo_map: TempoMap,
+}
+
+impl ImportedTrack {
+    pub fn into_track(self, track_id: TrackId) -> Track {
+        let last_tick = self.notes.notes.iter().flatten().map(|note| note.end).max().unwrap_or(0);
+        let last_beat = tick::to_beats(last_tick);
+
+        let mut track = Track::with_notes(track_id, self.notes);
+        track.channel = self.channel;
+        track.program = self.program;
+        track.meta.name = self.name;
+
+        // imported notes can be anywhere on the chromatic scale
+        track.grid.scale.set_scale_type(ScaleType::Chromatic);
+        track.grid.max_beats = track.grid.max_beats.max(last_beat.ceil() as usize + 1);
+
+        track
+    }
+}
+
+pub fn import_file(path: impl AsRef<Path>) -> Result<ImportedSong, ImportError> {
+    let data = std::fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+    import_smf(&smf)
+}
+
+pub fn import_smf(smf: &Smf) -> Result<ImportedSong, ImportError> {
+    let tempo_map = TempoMap::from_smf(smf);
+
+    // the ticks of the file are converted to the ticks of the editor through beats,
+    // since the file can have any resolution
+    let to_tick =
+        |file_tick: u32| tick::from_song_beats(tempo_map.ticks_to_beats(file_tick as f64));
+
+    let to_note = |press: NotePress, key: u7, end_tick: u32| {
+        let start = to_tick(press.start_tick);
+        let end = to_tick(end_tick).max(start + NOTE_MIN_TICKS);
+        MidiNote::new(start, end, Pitch::new(key.as_int())).with_velocity(press.vel.as_int())
+    };
+
+    let mut imported_tracks = Vec::new();
+
+    for (track_number, events) in smf.tracks.iter().enumerate() {
+        let mut track_name: Option<String> = None;
+        let mut notes_per_channel: Vec<Vec<MidiNote>> = vec![Vec::new(); 16];
+        // only the first program of each channel is kept
+        let mut program_per_channel: Vec<Option<u8>> = vec![None; 16];
+        let mut note_ons: NotePresses = HashMap::new();
+        let mut ticks_since_start: u32 = 0;
+
+        for event in events {
+            ticks_since_start += event.delta.as_int();
+
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                    // some sequencers pad the name with null bytes
+                    let name = String::from_utf8_lossy(name);
+                    track_name = Some(
+                        name.trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string(),
+                    );
+                }
+
+                TrackEventKind::Midi { channel, message } => match message {
+                    MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                        let note_on_hash = NoteOnHash { channel, key };
+
+                        // a key pressed again before being released ends the sounding note
+                        if let Some(note_press) = note_ons.remove(&note_on_hash) {
+                            notes_per_channel[channel.as_int() as usize].push(to_note(
+                                note_press,
+                                key,
+                                ticks_since_start,
+                            ));
+                        }
+
+                        note_ons
+                            .insert(note_on_hash, NotePress { start_tick: ticks_since_start, vel });
+                    }
+
+                    // a NoteOn with a velocity of 0 is a NoteOff
+                    MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                        if let Some(note_press) = note_ons.remove(&NoteOnHash { channel, key }) {
+                            notes_per_channel[channel.as_int() as usize].push(to_note(
+                                note_press,
+                                key,
+                                ticks_since_start,
+                            ));
+                        }
+                    }
+
+                    MidiMessage::ProgramChange { program } => {
+                        program_per_channel[channel.as_int() as usize]
+                            .get_or_insert(program.as_int());
+                    }
+
+                    _ => {}
+                },
+
+                _ => {}
+            }
+        }
+
+        // notes that are never released end with the track
+        for (NoteOnHash { channel, key }, note_press) in note_ons.drain() {
+            notes_per_channel[channel.as_int() as usize].push(to_note(
+                note_press,
+                key,
+                ticks_since_start,
+            ));
+        }
+
+        let used_channels =
+            notes_per_channel.iter().filter(|channel_notes| !channel_notes.is_empty()).count();
+
+        let name = track_name
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("Track {}", track_number + 1));
+
+        for (channel, mut channel_notes) in notes_per_channel.into_iter().enumerate() {
+            if channel_notes.is_empty() {
+                continue;
+            }
+
+            // MidiNotes::add resolves the overlaps between notes of the same pitch,
+            // giving priority to the note that is added last
+            channel_notes.sort_by(|a, b| a.start.cmp(&b.start));
+
+            let name = if used_channels > 1 {
+                format!("{} (ch {})", name, channel + 1)
+            } else {
+                name.clone()
+            };
+
+            imported_tracks.push(ImportedTrack {
+                name,
+                channel: channel as u8,
+                program: program_per_channel[channel].unwrap_or(0),
+                notes: MidiNotes::from(channel_notes),
+            });
+        }
+    }
+
+    Ok(ImportedSong { tracks: imported_tracks, tempo_map })
+}
@@ -0,0 +1,5 @@
+//! Standard MIDI Files
+//!
+pub mod export;
+pub mod import;
+pub mod tempo_map;
@@ -0,0 +1,364 @@
+//! Tempo map
+//!
+//! Collects the tempo and time signature changes of every track of a midi file and
+//! converts positions between ticks, beats and seconds. Beats here start at 0.0 on
+//! the first tick; the editor adds FIRST_BEAT on top of them.
+
+use midly::{MetaMessage, Smf, Timing, TrackEventKind};
+
+use crate::timeline::TimingInfo;
+
+// 120 bpm, the default tempo of a midi file without Tempo events
+pub const DEFAULT_MICROS_PER_BEAT: u32 = 500_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickTiming {
+    // the length of a tick depends on the tempo
+    Metrical { ticks_per_beat: f64 },
+    // the length of a tick is fixed, and the tempo only matters for beats
+    Timecode { ticks_per_second: f64 },
+}
+
+// A tempo change, along with the position where it happens in every unit so
+// that the conversions only need to walk a single segment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    pub tick: u32,
+    pub beat: f64,
+    pub second: f64,
+    pub micros_per_beat: u32,
+}
+
+impl TempoChange {
+    pub fn bpm(&self) -> f64 {
+        60_000_000.0 / self.micros_per_beat as f64
+    }
+
+    fn seconds_per_beat(&self) -> f64 {
+        self.micros_per_beat as f64 / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignatureChange {
+    pub tick: u32,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    pub timing: TickTiming,
+    // sorted by tick, the first change is always at tick 0
+    pub tempo_changes: Vec<TempoChange>,
+    pub time_signatures: Vec<TimeSignatureChange>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        TimingInfo::default().tempo_map()
+    }
+}
+
+impl TempoMap {
+    pub fn from_smf(smf: &Smf) -> Self {
+        let timing = match smf.header.timing {
+            Timing::Metrical(ticks_per_beat) => {
+                TickTiming::Metrical { ticks_per_beat: ticks_per_beat.as_int() as f64 }
+            }
+            Timing::Timecode(fps, subframes) => {
+                TickTiming::Timecode { ticks_per_second: fps.as_f32() as f64 * subframes as f64 }
+            }
+        };
+
+        // the tempo events are usually in the first track, but nothing prevents
+        // them from being in any other track
+        let mut tempos: Vec<(u32, u32)> = Vec::new();
+        let mut time_signatures: Vec<TimeSignatureChange> = Vec::new();
+
+        for events in smf.tracks.iter() {
+            let mut ticks_since_start: u32 = 0;
+
+            for event in events {
+                ticks_since_start += event.delta.as_int();
+
+                match event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                        tempos.push((ticks_since_start, tempo.as_int()));
+                    }
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(
+                        numerator,
+                        denominator_power,
+                        ..,
+                    )) => {
+                        time_signatures.push(TimeSignatureChange {
+                            tick: ticks_since_start,
+                            numerator,
+                            denominator: 1u8.checked_shl(denominator_power as u32).unwrap_or(4),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Self::new(timing, tempos, time_signatures)
+    }
+
+    // tempos are (tick, microseconds per beat)
+    pub fn new(
+        timing: TickTiming,
+        mut tempos: Vec<(u32, u32)>,
+        mut time_signatures: Vec<TimeSignatureChange>,
+    ) -> Self {
+        // stable sorts: when two changes happen on the same tick, the last one wins
+        tempos.sort_by_key(|(tick, _)| *tick);
+        tempos.dedup_by(|later, earlier| {
+            if later.0 == earlier.0 {
+                earlier.1 = later.1;
+                true
+            } else {
+                false
+            }
+        });
+        tempos.retain(|(_, micros_per_beat)| *micros_per_beat > 0);
+
+        if tempos.first().map_or(true, |(tick, _)| *tick > 0) {
+            tempos.insert(0, (0, DEFAULT_MICROS_PER_BEAT));
+        }
+
+        time_signatures.sort_by_key(|time_signature| time_signature.tick);
+        time_signatures.dedup_by(|later, earlier| {
+            if later.tick == earlier.tick {
+                *earlier = *later;
+                true
+            } else {
+                false
+            }
+        });
+
+        if time_signatures.first().map_or(true, |time_signature| time_signature.tick > 0) {
+            time_signatures
+                .insert(0, TimeSignatureChange { tick: 0, numerator: 4, denominator: 4 });
+        }
+
+        let mut tempo_map = Self { timing, tempo_changes: Vec::new(), time_signatures };
+
+        for (tick, micros_per_beat) in tempos {
+            let (beat, second) = match tempo_map.tempo_changes.last() {
+                None => (0.0, 0.0),
+                Some(previous) => {
+                    let delta_ticks = (tick - previous.tick) as f64;
+                    match timing {
+                        TickTiming::Metrical { ticks_per_beat } => {
+                            let delta_beats = delta_ticks / ticks_per_beat;
+                            (
+                                previous.beat + delta_beats,
+                                previous.second + delta_beats * previous.seconds_per_beat(),
+                            )
+                        }
+                        TickTiming::Timecode { ticks_per_second } => {
+                            let delta_seconds = delta_ticks / ticks_per_second;
+                            (
+                                previous.beat + delta_seconds / previous.seconds_per_beat(),
+                                previous.second + delta_seconds,
+                            )
+                        }
+                    }
+                }
+            };
+
+            tempo_map.tempo_changes.push(TempoChange { tick, beat, second, micros_per_beat });
+        }
+
+        tempo_map
+    }
+
+    // the tempo change that is in effect at a given position, found using
+    // a key that grows along with the tempo changes
+    fn tempo_change_at(&self, key: impl Fn(&TempoChange) -> f64, position: f64) -> &TempoChange {
+        let index = self.tempo_changes.partition_point(|change| key(change) <= position);
+        &self.tempo_changes[index.saturating_sub(1)]
+    }
+
+    pub fn tempo_at_beat(&self, beat: f64) -> &TempoChange {
+        self.tempo_change_at(|change| change.beat, beat)
+    }
+
+    pub fn time_signature_at_tick(&self, tick: u32) -> &TimeSignatureChange {
+        let index = self.time_signatures.partition_point(|change| change.tick <= tick);
+        &self.time_signatures[index.saturating_sub(1)]
+    }
+
+    pub fn initial_bpm(&self) -> f64 {
+        self.tempo_changes[0].bpm()
+    }
+
+    pub fn ticks_to_beats(&self, ticks: f64) -> f64 {
+        match self.timing {
+            TickTiming::Metrical { ticks_per_beat } => ticks / ticks_per_beat,
+            TickTiming::Timecode { ticks_per_second } => {
+                self.seconds_to_beats(ticks / ticks_per_second)
+            }
+        }
+    }
+
+    pub fn beats_to_ticks(&self, beats: f64) -> f64 {
+        match self.timing {
+            TickTiming::Metrical { ticks_per_beat } => beats * ticks_per_beat,
+            TickTiming::Timecode { ticks_per_second } => {
+                self.beats_to_seconds(beats) * ticks_per_second
+            }
+        }
+    }
+
+    pub fn ticks_to_seconds(&self, ticks: f64) -> f64 {
+        match self.timing {
+            TickTiming::Metrical { .. } => self.beats_to_seconds(self.ticks_to_beats(ticks)),
+            TickTiming::Timecode { ticks_per_second } => ticks / ticks_per_second,
+        }
+    }
+
+    pub fn seconds_to_ticks(&self, seconds: f64) -> f64 {
+        match self.timing {
+            TickTiming::Metrical { .. } => self.beats_to_ticks(self.seconds_to_beats(seconds)),
+            TickTiming::Timecode { ticks_per_second } => seconds * ticks_per_second,
+        }
+    }
+
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        let change = self.tempo_change_at(|change| change.beat, beats);
+        change.second + (beats - change.beat) * change.seconds_per_beat()
+    }
+
+    pub fn seconds_to_beats(&self, seconds: f64) -> f64 {
+        let change = self.tempo_change_at(|change| change.second, seconds);
+        change.beat + (seconds - change.second) / change.seconds_per_beat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u15, u24, u28};
+    use midly::{Format, Fps, Header, TrackEvent};
+
+    // a midi file whose tracks hold meta events at absolute ticks
+    fn smf(timing: Timing, tracks: Vec<Vec<(u32, MetaMessage<'static>)>>) -> Smf<'static> {
+        let mut smf = Smf::new(Header::new(Format::Parallel, timing));
+        for events in tracks {
+            let mut previous_tick = 0;
+            let mut track: Vec<TrackEvent> = events
+                .into_iter()
+                .map(|(tick, message)| {
+                    let delta = tick - previous_tick;
+                    previous_tick = tick;
+                    TrackEvent { delta: u28::new(delta), kind: TrackEventKind::Meta(message) }
+                })
+                .collect();
+            track.push(TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            });
+            smf.tracks.push(track);
+        }
+        smf
+    }
+
+    fn tempo(micros_per_beat: u32) -> MetaMessage<'static> {
+        MetaMessage::Tempo(u24::new(micros_per_beat))
+    }
+
+    fn time_signature(numerator: u8, denominator_power: u8) -> MetaMessage<'static> {
+        MetaMessage::TimeSignature(numerator, denominator_power, 24, 8)
+    }
+
+    #[test]
+    fn several_tempo_changes_are_walked_in_order() {
+        let smf = smf(
+            Timing::Metrical(u15::new(480)),
+            vec![vec![(0, tempo(500_000)), (960, tempo(1_000_000)), (1920, tempo(250_000))]],
+        );
+        let tempo_map = TempoMap::from_smf(&smf);
+
+        let positions: Vec<(u32, f64, f64)> = tempo_map
+            .tempo_changes
+            .iter()
+            .map(|change| (change.tick, change.beat, change.second))
+            .collect();
+        assert_eq!(positions, [(0, 0.0, 0.0), (960, 2.0, 1.0), (1920, 4.0, 3.0)]);
+
+        assert_eq!(tempo_map.initial_bpm(), 120.0);
+        assert_eq!(tempo_map.tempo_at_beat(3.0).bpm(), 60.0);
+        assert_eq!(tempo_map.tempo_at_beat(4.0).bpm(), 240.0);
+        assert_eq!(tempo_map.ticks_to_seconds(2400.0), 3.25);
+        assert_eq!(tempo_map.seconds_to_ticks(3.25), 2400.0);
+        assert_eq!(tempo_map.beats_to_seconds(3.0), 2.0);
+        assert_eq!(tempo_map.seconds_to_beats(2.0), 3.0);
+    }
+
+    #[test]
+    fn a_file_without_tempo_plays_at_120_bpm() {
+        let smf = smf(Timing::Metrical(u15::new(480)), vec![vec![]]);
+        let tempo_map = TempoMap::from_smf(&smf);
+
+        assert_eq!(
+            tempo_map.tempo_changes,
+            [TempoChange { tick: 0, beat: 0.0, second: 0.0, micros_per_beat: 500_000 }]
+        );
+        assert_eq!(tempo_map.initial_bpm(), 120.0);
+        assert_eq!(tempo_map.ticks_to_seconds(960.0), 1.0);
+        assert_eq!(
+            tempo_map.time_signatures,
+            [TimeSignatureChange { tick: 0, numerator: 4, denominator: 4 }]
+        );
+    }
+
+    #[test]
+    fn tempo_events_are_read_from_every_track() {
+        let smf = smf(
+            Timing::Metrical(u15::new(480)),
+            vec![
+                vec![(0, time_signature(3, 2))],
+                vec![(480, tempo(1_000_000)), (1440, time_signature(6, 3))],
+            ],
+        );
+        let tempo_map = TempoMap::from_smf(&smf);
+
+        // the default tempo runs until the first tempo event
+        assert_eq!(tempo_map.initial_bpm(), 120.0);
+        assert_eq!(
+            tempo_map.tempo_changes[1],
+            TempoChange { tick: 480, beat: 1.0, second: 0.5, micros_per_beat: 1_000_000 }
+        );
+        assert_eq!(tempo_map.ticks_to_seconds(960.0), 1.5);
+        assert_eq!(
+            tempo_map.time_signatures,
+            [
+                TimeSignatureChange { tick: 0, numerator: 3, denominator: 4 },
+                TimeSignatureChange { tick: 1440, numerator: 6, denominator: 8 },
+            ]
+        );
+    }
+
+    #[test]
+    fn timecode_ticks_have_a_fixed_length() {
+        // 25 frames of 40 ticks: 1000 ticks per second
+        let smf = smf(
+            Timing::Timecode(Fps::Fps25, 40),
+            vec![vec![(0, tempo(500_000)), (2000, tempo(1_000_000))]],
+        );
+        let tempo_map = TempoMap::from_smf(&smf);
+
+        assert_eq!(tempo_map.timing, TickTiming::Timecode { ticks_per_second: 1000.0 });
+        assert_eq!(
+            tempo_map.tempo_changes[1],
+            TempoChange { tick: 2000, beat: 4.0, second: 2.0, micros_per_beat: 1_000_000 }
+        );
+        // the seconds only depend on the ticks, and the tempo only changes the beats
+        assert_eq!(tempo_map.ticks_to_seconds(3000.0), 3.0);
+        assert_eq!(tempo_map.seconds_to_ticks(3.0), 3000.0);
+        assert_eq!(tempo_map.ticks_to_beats(3000.0), 5.0);
+        assert_eq!(tempo_map.beats_to_ticks(5.0), 3000.0);
+    }
+}
@@ -0,0 +1,47 @@
+//! Ticks
+//!
+//! Positions and lengths on the timeline are integer ticks, PPQ ticks per beat, so that
+//! dragging, resizing and undoing never accumulate rounding errors. The grid is still
+//! drawn in beats: a tick is converted to a float beat only to be drawn, and the cursor
+//! is converted back to the nearest tick. Tick 0 is beat 0.0, which means that the
+//! first beat of the grid (FIRST_BEAT) is at FIRST_TICK.
+
+use crate::config::{FIRST_BEAT, NOTE_MIN_SIZE};
+
+pub type Tick = i64;
+
+// pulses per quarter note, divisible by 2, 3, 5 and by every power of 2 up to 64
+pub const PPQ: Tick = 960;
+
+pub const FIRST_TICK: Tick = FIRST_BEAT as Tick * PPQ;
+
+// the shortest note, 1/64th of a beat
+pub const NOTE_MIN_TICKS: Tick = (NOTE_MIN_SIZE * PPQ as f32) as Tick;
+
+pub fn to_beats(ticks: Tick) -> f32 {
+    ticks as f32 / PPQ as f32
+}
+
+// rounded to the nearest tick
+pub fn from_beats(beats: f32) -> Tick {
+    (beats as f64 * PPQ as f64).round() as Tick
+}
+
+// the beats counted from the first beat of the grid, as used by the tempo map
+pub fn to_song_beats(ticks: Tick) -> f64 {
+    (ticks - FIRST_TICK) as f64 / PPQ as f64
+}
+
+pub fn from_song_beats(beats: f64) -> Tick {
+    FIRST_TICK + (beats * PPQ as f64).round() as Tick
+}
+
+// the closest multiple of period that is not after tick
+pub fn snap_down(tick: Tick, period: Tick) -> Tick {
+    tick.div_euclid(period.max(1)) * period.max(1)
+}
+
+// the closest multiple of period
+pub fn snap(tick: Tick, period: Tick) -> Tick {
+    snap_down(tick + period.max(1) / 2, period)
+}
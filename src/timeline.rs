@@ -0,0 +1,443 @@
+//! Tempo and meter tracks
+//!
+//! The tempo track and the meter track belong to the song rather than to a track. A
+//! tempo change can happen on any tick, while a meter change always happens at the
+//! start of a bar, so the meter track counts in bars. Both tracks always start with
+//! a change at the first beat (bar 1), which gives the initial tempo and meter, even
+//! when read from a project file. When the meter changes, the tempo changes keep their
+//! place in their bar, so that a tempo change at a bar line stays on it.
+//!
+//! Bars and beats follow the meter: in 6/8, a bar has six beats of an eighth note.
+//! The grid columns stay quarter notes, since ticks are counted per quarter note.
+
+use serde::{Deserialize, Serialize};
+
+use crate::smf::tempo_map::{TempoMap, TickTiming, TimeSignatureChange};
+use crate::tick::{self, Tick, FIRST_TICK, PPQ};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoEvent {
+    pub tick: Tick,
+    pub bpm: f32,
+}
+
+const INITIAL_TEMPO: TempoEvent = TempoEvent { tick: FIRST_TICK, bpm: 120.0 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeterEvent {
+    // counted from 1
+    pub bar: u32,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+const INITIAL_METER: MeterEvent = MeterEvent { bar: 1, numerator: 4, denominator: 4 };
+
+impl MeterEvent {
+    pub fn beat_length(&self) -> Tick {
+        4 * PPQ / self.denominator.max(1) as Tick
+    }
+
+    pub fn bar_length(&self) -> Tick {
+        self.numerator.max(1) as Tick * self.beat_length()
+    }
+}
+
+// The range of ticks that playback cycles over, from start to just before end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    pub start: Tick,
+    pub end: Tick,
+}
+
+impl LoopRegion {
+    // None when the region would be empty
+    pub fn new(from: Tick, to: Tick) -> Option<Self> {
+        let (start, end) = (from.min(to).max(FIRST_TICK), from.max(to));
+        if end > start {
+            Some(Self { start, end })
+        } else {
+            None
+        }
+    }
+
+    pub fn length(&self) -> Tick {
+        self.end - self.start
+    }
+}
+
+// A position in bars, beats and ticks, all counted from 1 except the ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarBeatTick {
+    pub bar: i64,
+    pub beat: i64,
+    pub tick: Tick,
+}
+
+impl std::fmt::Display for BarBeatTick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{:03}", self.bar, self.beat, self.tick)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedTimingInfo")]
+pub struct TimingInfo {
+    // sorted by tick, the first change is at FIRST_TICK
+    pub tempo_track: Vec<TempoEvent>,
+    // sorted by bar, the first change is at bar 1
+    pub meter_track: Vec<MeterEvent>,
+    pub track_length: f32,
+}
+
+impl Default for TimingInfo {
+    fn default() -> Self {
+        Self {
+            tempo_track: vec![INITIAL_TEMPO],
+            meter_track: vec![INITIAL_METER],
+            track_length: 4.0,
+        }
+    }
+}
+
+// The timing info as read from a project file, where the tracks may be unsorted or empty
+#[derive(Deserialize)]
+#[serde(default)]
+struct SavedTimingInfo {
+    tempo_track: Vec<TempoEvent>,
+    meter_track: Vec<MeterEvent>,
+    track_length: f32,
+}
+
+impl Default for SavedTimingInfo {
+    fn default() -> Self {
+        let TimingInfo { tempo_track, meter_track, track_length } = TimingInfo::default();
+        Self { tempo_track, meter_track, track_length }
+    }
+}
+
+impl From<SavedTimingInfo> for TimingInfo {
+    fn from(saved: SavedTimingInfo) -> Self {
+        let SavedTimingInfo { tempo_track, meter_track, track_length } = saved;
+        let mut timing_info = Self { tempo_track, meter_track, track_length };
+        timing_info.normalize();
+        timing_info
+    }
+}
+
+impl TimingInfo {
+    // the tempo and meter changes of a midi file, moved to the ticks of the editor.
+    // A time signature that does not fall on a bar line starts a new bar on the
+    // next bar line
+    pub fn from_tempo_map(tempo_map: &TempoMap) -> Self {
+        let mut timing_info = Self::default();
+
+        timing_info.meter_track.clear();
+        for time_signature in tempo_map.time_signatures.iter() {
+            let position =
+                tick::from_song_beats(tempo_map.ticks_to_beats(time_signature.tick as f64));
+            let bar = if timing_info.meter_track.is_empty() {
+                1
+            } else {
+                let bar_beat_tick = timing_info.bar_beat_tick(position);
+                let is_on_bar_line = bar_beat_tick.beat == 1 && bar_beat_tick.tick == 0;
+                bar_beat_tick.bar.max(1) as u32 + if is_on_bar_line { 0 } else { 1 }
+            };
+            timing_info.set_meter(bar, time_signature.numerator, time_signature.denominator);
+        }
+
+        // the tempo changes are set once the meter is known, as they are not moved
+        // along with their bars like when editing
+        timing_info.tempo_track = tempo_map
+            .tempo_changes
+            .iter()
+            .map(|change| TempoEvent {
+                tick: tick::from_song_beats(change.beat),
+                bpm: change.bpm() as f32,
+            })
+            .collect();
+
+        timing_info.normalize();
+        timing_info
+    }
+
+    // the tempo map used by playback, rendering and export, in the ticks of the editor
+    // counted from the first beat
+    pub fn tempo_map(&self) -> TempoMap {
+        let tempos = self
+            .tempo_track
+            .iter()
+            .map(|tempo| {
+                let tick = (tempo.tick - FIRST_TICK).max(0) as u32;
+                (tick, (60_000_000.0 / tempo.bpm.max(1.0) as f64).round() as u32)
+            })
+            .collect();
+
+        let time_signatures = self
+            .meter_track
+            .iter()
+            .map(|meter| TimeSignatureChange {
+                tick: (self.bar_start(meter.bar) - FIRST_TICK) as u32,
+                numerator: meter.numerator,
+                denominator: meter.denominator,
+            })
+            .collect();
+
+        TempoMap::new(TickTiming::Metrical { ticks_per_beat: PPQ as f64 }, tempos, time_signatures)
+    }
+
+    // keeps both tracks sorted, without duplicates, and starting at the first beat
+    fn normalize(&mut self) {
+        self.normalize_tempo_track();
+        self.normalize_meter_track();
+    }
+
+    fn normalize_tempo_track(&mut self) {
+        for tempo in self.tempo_track.iter_mut() {
+            tempo.tick = tempo.tick.max(FIRST_TICK);
+        }
+        self.tempo_track.sort_by_key(|tempo| tempo.tick);
+        self.tempo_track.dedup_by(|later, earlier| {
+            if later.tick == earlier.tick {
+                *earlier = *later;
+                true
+            } else {
+                false
+            }
+        });
+        if self.tempo_track.first().map_or(true, |tempo| tempo.tick > FIRST_TICK) {
+            self.tempo_track.insert(0, INITIAL_TEMPO);
+        }
+    }
+
+    fn normalize_meter_track(&mut self) {
+        self.meter_track.sort_by_key(|meter| meter.bar);
+        self.meter_track.dedup_by(|later, earlier| {
+            if later.bar == earlier.bar {
+                *earlier = *later;
+                true
+            } else {
+                false
+            }
+        });
+        match self.meter_track.first_mut() {
+            Some(first) => first.bar = 1,
+            None => self.meter_track.push(INITIAL_METER),
+        }
+    }
+
+    // changes the meter track, and moves each tempo change so that it keeps its bar and
+    // its distance from the start of the bar
+    fn change_meter_track(&mut self, change: impl FnOnce(&mut Vec<MeterEvent>)) {
+        let places: Vec<(u32, Tick)> = self
+            .tempo_track
+            .iter()
+            .map(|tempo| {
+                let bar = self.bar_beat_tick(tempo.tick).bar.max(1) as u32;
+                (bar, tempo.tick - self.bar_start(bar))
+            })
+            .collect();
+
+        change(&mut self.meter_track);
+        self.normalize_meter_track();
+
+        let ticks: Vec<Tick> =
+            places.iter().map(|(bar, offset)| self.bar_start(*bar) + offset).collect();
+        for (tempo, tick) in self.tempo_track.iter_mut().zip(ticks) {
+            tempo.tick = tick;
+        }
+        self.normalize_tempo_track();
+    }
+
+    // the first meter and tempo, which the tracks always have once normalized
+    fn initial_meter(&self) -> MeterEvent {
+        self.meter_track.first().copied().unwrap_or(INITIAL_METER)
+    }
+
+    fn initial_tempo(&self) -> TempoEvent {
+        self.tempo_track.first().copied().unwrap_or(INITIAL_TEMPO)
+    }
+
+    // the meter changes along with the tick where their first bar starts
+    fn meter_starts(&self) -> impl Iterator<Item = (Tick, &MeterEvent)> {
+        let mut start = FIRST_TICK;
+        let mut previous: Option<&MeterEvent> = None;
+        self.meter_track.iter().map(move |meter| {
+            if let Some(previous) = previous {
+                start += (meter.bar - previous.bar) as Tick * previous.bar_length();
+            }
+            previous = Some(meter);
+            (start, meter)
+        })
+    }
+
+    pub fn bar_start(&self, bar: u32) -> Tick {
+        let (start, meter) = self
+            .meter_starts()
+            .take_while(|(_, meter)| meter.bar <= bar.max(1))
+            .last()
+            .map_or((FIRST_TICK, self.initial_meter()), |(start, meter)| (start, *meter));
+        start + (bar.max(1) - meter.bar) as Tick * meter.bar_length()
+    }
+
+    pub fn meter_at(&self, tick: Tick) -> MeterEvent {
+        self.meter_starts()
+            .take_while(|(start, _)| *start <= tick)
+            .last()
+            .map_or(self.initial_meter(), |(_, meter)| *meter)
+    }
+
+    pub fn tempo_at(&self, tick: Tick) -> TempoEvent {
+        self.tempo_track
+            .iter()
+            .take_while(|tempo| tempo.tick <= tick)
+            .last()
+            .copied()
+            .unwrap_or(self.initial_tempo())
+    }
+
+    // positions before the first beat are counted in the first meter, in bars 0 and below
+    pub fn bar_beat_tick(&self, tick: Tick) -> BarBeatTick {
+        let (start, meter) = self
+            .meter_starts()
+            .take_while(|(start, _)| *start <= tick)
+            .last()
+            .map_or((FIRST_TICK, self.initial_meter()), |(start, meter)| (start, *meter));
+
+        let bars = (tick - start).div_euclid(meter.bar_length());
+        let in_bar = (tick - start).rem_euclid(meter.bar_length());
+
+        BarBeatTick {
+            bar: meter.bar as i64 + bars,
+            beat: in_bar / meter.beat_length() + 1,
+            tick: in_bar % meter.beat_length(),
+        }
+    }
+
+    // the bars that start between two ticks, as (bar, tick)
+    pub fn bar_lines(&self, from: Tick, to: Tick) -> Vec<(i64, Tick)> {
+        let first = self.bar_beat_tick(from);
+        let mut bar = if first.beat == 1 && first.tick == 0 { first.bar } else { first.bar + 1 };
+
+        let mut bar_lines = Vec::new();
+        loop {
+            let tick = if bar >= 1 {
+                self.bar_start(bar as u32)
+            } else {
+                FIRST_TICK + (bar - 1) * self.initial_meter().bar_length()
+            };
+            if tick > to {
+                break;
+            }
+            bar_lines.push((bar, tick));
+            bar += 1;
+        }
+        bar_lines
+    }
+
+    // the tempo from the start of a bar on, until the next tempo change
+    pub fn set_tempo(&mut self, bar: u32, bpm: f32) {
+        let tick = self.bar_start(bar);
+        self.tempo_track.push(TempoEvent { tick, bpm: bpm.clamp(20.0, 400.0) });
+        self.normalize();
+    }
+
+    pub fn set_meter(&mut self, bar: u32, numerator: u8, denominator: u8) {
+        let denominator = denominator.clamp(1, 32).next_power_of_two();
+        self.change_meter_track(|meter_track| {
+            meter_track.push(MeterEvent {
+                bar: bar.max(1),
+                numerator: numerator.clamp(1, 32),
+                denominator,
+            })
+        });
+    }
+
+    // removes the tempo and meter changes that happen at a bar, except the initial ones
+    pub fn remove_changes(&mut self, bar: u32) {
+        let tick = self.bar_start(bar);
+        self.tempo_track.retain(|tempo| tempo.tick == FIRST_TICK || tempo.tick != tick);
+        self.change_meter_track(|meter_track| {
+            meter_track.retain(|meter| meter.bar == 1 || meter.bar != bar)
+        });
+    }
+
+    // the whole bars that cover a range of ticks
+    pub fn bars_around(&self, from: Tick, to: Tick) -> Option<LoopRegion> {
+        let first = self.bar_beat_tick(from).bar.max(1) as u32;
+        let last = self.bar_beat_tick(to);
+        let is_on_bar_line = last.beat == 1 && last.tick == 0;
+        let after_last = last.bar.max(1) as u32 + if is_on_bar_line { 0 } else { 1 };
+        LoopRegion::new(self.bar_start(first), self.bar_start(after_last.max(first + 1)))
+    }
+
+    pub fn has_changes(&self, bar: u32) -> bool {
+        let tick = self.bar_start(bar);
+        bar > 1
+            && (self.tempo_track.iter().any(|tempo| tempo.tick == tick)
+                || self.meter_track.iter().any(|meter| meter.bar == bar))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_empty_tracks_as_the_initial_tempo_and_meter() {
+        let timing_info: TimingInfo =
+            serde_json::from_str(r#"{ "tempo_track": [], "meter_track": [] }"#).unwrap();
+
+        assert_eq!(timing_info.tempo_track, [INITIAL_TEMPO]);
+        assert_eq!(timing_info.meter_track, [INITIAL_METER]);
+    }
+
+    #[test]
+    fn reads_unsorted_tracks_sorted() {
+        let timing_info: TimingInfo = serde_json::from_str(
+            r#"{
+                "tempo_track": [{ "tick": 4800, "bpm": 90.0 }, { "tick": 960, "bpm": 100.0 }],
+                "meter_track": [
+                    { "bar": 3, "numerator": 6, "denominator": 8 },
+                    { "bar": 1, "numerator": 3, "denominator": 4 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let ticks: Vec<Tick> = timing_info.tempo_track.iter().map(|tempo| tempo.tick).collect();
+        let bars: Vec<u32> = timing_info.meter_track.iter().map(|meter| meter.bar).collect();
+        assert_eq!(ticks, [FIRST_TICK, 4800]);
+        assert_eq!(bars, [1, 3]);
+        assert_eq!(timing_info.track_length, TimingInfo::default().track_length);
+    }
+
+    #[test]
+    fn lookups_on_empty_tracks_use_the_initial_tempo_and_meter() {
+        let timing_info =
+            TimingInfo { tempo_track: Vec::new(), meter_track: Vec::new(), track_length: 4.0 };
+
+        assert_eq!(timing_info.bar_start(3), FIRST_TICK + 8 * PPQ);
+        assert_eq!(timing_info.meter_at(FIRST_TICK), INITIAL_METER);
+        assert_eq!(timing_info.tempo_at(FIRST_TICK), INITIAL_TEMPO);
+        assert_eq!(timing_info.bar_beat_tick(FIRST_TICK + 5 * PPQ).to_string(), "2.2.000");
+        assert_eq!(timing_info.bar_lines(0, FIRST_TICK + 4 * PPQ).len(), 2);
+    }
+
+    #[test]
+    fn tempo_changes_keep_their_bar_when_the_meter_changes() {
+        let mut timing_info = TimingInfo::default();
+        timing_info.set_tempo(3, 90.0);
+        timing_info.tempo_track.push(TempoEvent { tick: FIRST_TICK + 13 * PPQ, bpm: 60.0 });
+
+        // the fourth bar starts 2 beats earlier in 3/4
+        timing_info.set_meter(2, 3, 4);
+        assert_eq!(timing_info.tempo_track[1].tick, timing_info.bar_start(3));
+        assert_eq!(timing_info.tempo_track[2].tick, timing_info.bar_start(4) + PPQ);
+        assert_eq!(timing_info.tempo_at(timing_info.bar_start(3)).bpm, 90.0);
+
+        timing_info.remove_changes(2);
+        assert_eq!(timing_info.tempo_track[1].tick, FIRST_TICK + 8 * PPQ);
+        assert_eq!(timing_info.tempo_track[2].tick, FIRST_TICK + 13 * PPQ);
+    }
+}
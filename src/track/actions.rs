@@ -1,6 +1,7 @@
 use crate::note::midi_notes::{
-    ChangeSelection, MidiNote, MidiNotes, NoteEdge, NoteIndex, ResizedEdges,
+    ChangeSelection, MidiNote, MidiNotes, NoteEdge, NoteIndex, ResizedEdges, VelocityChange,
 };
+use crate::note::scale::Scale;
 // use crate::track::undoredo::{AddedNote, ResizedConflicts, TrackHistory};
 use crate::track::{AddMode, Track, TrackMessage};
 use crate::util::History;
@@ -117,16 +118,63 @@ pub enum TrackAction {
     DraggedNotes {
         // drag: crate::track::Drag,
         // scale: crate::note::scale::Scale,
+        // the selected notes before dragging
+        original_notes: MidiNotes,
         conflicts: ConflictHistory,
         message: TrackMessage,
     },
     ResizedNotes {
         // delta_time: f32,
         // resize_end: NoteEdge,
+        // the selected notes before resizing
+        original_notes: MidiNotes,
         resized_conflicts: Vec<ResizedConflicts>,
         conflicts: ConflictHistory,
         message: TrackMessage,
     },
+    ChangedVelocities {
+        previous: Vec<VelocityChange>,
+        message: TrackMessage,
+    },
+    Quantized {
+        // the notes before quantizing, which were either the selection or the whole track
+        original_notes: MidiNotes,
+        whole_track: bool,
+        conflicts: ConflictHistory,
+        message: TrackMessage,
+    },
+    Transposed {
+        // the notes before transposing, which were either the selection or the whole track
+        original_notes: MidiNotes,
+        whole_track: bool,
+        conflicts: ConflictHistory,
+        message: TrackMessage,
+    },
+    ChangedKey {
+        // all the notes of the track, and its scale, before changing key
+        original_notes: MidiNotes,
+        original_selected: MidiNotes,
+        original_scale: Scale,
+        message: TrackMessage,
+    },
+    ConformedToScale {
+        // the selected notes before moving them into the scale
+        original_notes: MidiNotes,
+        conflicts: ConflictHistory,
+        message: TrackMessage,
+    },
+    AddedChord {
+        // the selected notes before adding the chord to them
+        original_notes: MidiNotes,
+        conflicts: ConflictHistory,
+        message: TrackMessage,
+    },
+    RecordedTake {
+        // all the notes of the track before the take
+        original_notes: MidiNotes,
+        original_selected: MidiNotes,
+        message: TrackMessage,
+    },
     SelectionAction(SelectionAction),
 }
 
@@ -209,76 +257,103 @@ impl TrackAction {
 
                 track.notes_cache.clear();
                 track.selected_notes_cache.clear();
+                track.chord_lane_cache.clear();
             }
 
             TrackAction::AddManyNotes { added_notes, .. } => {
                 track.remove_notes_with_conflicts(added_notes);
                 track.notes_cache.clear();
                 track.selected_notes_cache.clear();
+                track.chord_lane_cache.clear();
             }
 
             TrackAction::RemoveNote { note_before, is_selected, .. } => {
                 if !is_selected {
                     track.midi_notes.add(note_before);
                     track.notes_cache.clear();
+                    track.chord_lane_cache.clear();
                 } else {
                     // println!("undo remove note (selected) ");
                     track.selected.notes.add(note_before);
                     track.selected_notes_cache.clear();
+                    track.chord_lane_cache.clear();
                 }
             }
 
             TrackAction::RemoveSelectedNotes { deleted_notes, .. } => {
                 track.selected.notes.add_midi_notes(deleted_notes);
                 track.selected_notes_cache.clear();
+                track.chord_lane_cache.clear();
             }
 
-            TrackAction::DraggedNotes {
-                message: TrackMessage::FinishDragging { drag, scale },
-                conflicts,
-                ..
-            } => {
-                let mut modified_notes: MidiNotes = track.selected.notes.clone();
+            TrackAction::DraggedNotes { original_notes, conflicts, .. } => {
+                // the notes are put back as they were, since a note out of the scale
+                // dragged onto another degree may not drag back to its pitch
+                track.selected.notes = original_notes.clone();
 
-                for v in modified_notes.notes.iter_mut() {
-                    for note in v.iter_mut() {
-                        note.reposition(-drag.delta_pitch, -drag.delta_times, &scale);
-                    }
-                }
+                Self::handle_conflicts(track, &conflicts);
 
+                track.selected_notes_cache.clear();
+                track.notes_cache.clear();
+                track.chord_lane_cache.clear();
+            }
+
+            TrackAction::ResizedNotes { original_notes, conflicts, .. } => {
+                // the notes are put back as they were, since resizing by the inverse
+                // percentage could round to a different tick
+                track.selected.notes = original_notes.clone();
+
+                // handle conflicts between selected notes and non-selected notes
                 Self::handle_conflicts(track, &conflicts);
 
-                track.selected.notes.clear();
-                track.selected.notes.add_midi_notes(&modified_notes);
                 track.selected_notes_cache.clear();
                 track.notes_cache.clear();
+                track.chord_lane_cache.clear();
             }
 
-            TrackAction::ResizedNotes {
-                message: TrackMessage::FinishResizingNotes { resize_percent },
-                resized_conflicts,
-                conflicts,
-            } => {
-                for notes_in_pitch in track.selected.notes.notes.iter_mut() {
-                    for note in notes_in_pitch.iter_mut() {
-                        note.resize_with_percent(-*resize_percent);
-                    }
+            TrackAction::ChangedVelocities { previous, .. } => {
+                track.set_velocities(previous);
+            }
+
+            TrackAction::Quantized { original_notes, whole_track, conflicts, .. }
+            | TrackAction::Transposed { original_notes, whole_track, conflicts, .. } => {
+                if *whole_track {
+                    track.midi_notes = original_notes.clone();
+                } else {
+                    track.selected.notes = original_notes.clone();
+                    Self::handle_conflicts(track, &conflicts);
                 }
 
-                // handle conflicts within the selected notes
-                for conflict in resized_conflicts.iter() {
-                    let note_index = conflict.note_index;
-                    let note = &mut track.selected.notes.notes[note_index.pitch_index]
-                        [note_index.time_index];
+                track.selected_notes_cache.clear();
+                track.notes_cache.clear();
+                track.chord_lane_cache.clear();
+            }
 
-                    note.resize(-conflict.delta_times);
-                }
+            TrackAction::ChangedKey {
+                original_notes, original_selected, original_scale, ..
+            } => {
+                track.midi_notes = original_notes.clone();
+                track.selected.notes = original_selected.clone();
+                track.set_scale(original_scale.scale_type.clone(), original_scale.root);
+            }
 
-                // handle conflicts between selected notes and non-selected notes
+            TrackAction::ConformedToScale { original_notes, conflicts, .. }
+            | TrackAction::AddedChord { original_notes, conflicts, .. } => {
+                track.selected.notes = original_notes.clone();
                 Self::handle_conflicts(track, &conflicts);
 
                 track.selected_notes_cache.clear();
                 track.notes_cache.clear();
+                track.chord_lane_cache.clear();
+            }
+
+            TrackAction::RecordedTake { original_notes, original_selected, .. } => {
+                track.midi_notes = original_notes.clone();
+                track.selected.notes = original_selected.clone();
+
+                track.selected_notes_cache.clear();
+                track.notes_cache.clear();
+                track.chord_lane_cache.clear();
             }
 
             TrackAction::SelectionAction(selection_action) => {
@@ -339,6 +414,7 @@ impl TrackAction {
         dummy_history.is_dummy = true;
         track.notes_cache.clear();
         track.selected_notes_cache.clear();
+        track.chord_lane_cache.clear();
         println!("redo self: {:?}", self);
         match self {
             TrackAction::AddNote { message, .. } => track.update(message, dummy_history),
@@ -347,6 +423,13 @@ impl TrackAction {
             TrackAction::RemoveSelectedNotes { message, .. } => {
                 track.update(message, dummy_history)
             }
+            TrackAction::ChangedVelocities { message, .. } => track.update(message, dummy_history),
+            TrackAction::Quantized { message, .. } => track.update(message, dummy_history),
+            TrackAction::Transposed { message, .. } => track.update(message, dummy_history),
+            TrackAction::ChangedKey { message, .. } => track.update(message, dummy_history),
+            TrackAction::ConformedToScale { message, .. } => track.update(message, dummy_history),
+            TrackAction::AddedChord { message, .. } => track.update(message, dummy_history),
+            TrackAction::RecordedTake { message, .. } => track.update(message, dummy_history),
             TrackAction::DraggedNotes { message, .. } => {
                 // track.update(message, dummy_history)
                 if let TrackMessage::FinishDragging { drag, scale } = message {
@@ -365,6 +448,7 @@ impl TrackAction {
 
                     track.selected_notes_cache.clear();
                     track.notes_cache.clear();
+                    track.chord_lane_cache.clear();
                 }
             }
             TrackAction::ResizedNotes { message, .. } => {
@@ -380,6 +464,7 @@ impl TrackAction {
 
                     track.selected_notes_cache.clear();
                     track.notes_cache.clear();
+                    track.chord_lane_cache.clear();
                 }
             }
 
@@ -416,3 +501,304 @@ impl TrackAction {
     }
 }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::midi_notes::Pitch;
+    use crate::note::quantize::QuantizeSettings;
+    use crate::note::scale::ScaleType;
+    use crate::tick::{Tick, FIRST_TICK, PPQ};
+    use iced::Vector;
+
+    const CYCLES: usize = 2000;
+
+    // the pitch, start and end of every note of the track, selected or not
+    fn positions(track: &Track) -> Vec<(u8, Tick, Tick)> {
+        let mut positions: Vec<(u8, Tick, Tick)> = track
+            .midi_notes
+            .notes
+            .iter()
+            .chain(track.selected.notes.notes.iter())
+            .flatten()
+            .map(|note| (note.get_pitch(), note.start, note.end))
+            .collect();
+        positions.sort();
+        positions
+    }
+
+    // notes at positions that are not multiples of any grid period
+    fn track_with_notes(history: &mut History) -> Track {
+        let mut track = Track::new(0);
+        let notes = vec![
+            MidiNote::new(FIRST_TICK + 7, FIRST_TICK + PPQ / 3 + 1, Pitch::new(60)),
+            MidiNote::new(FIRST_TICK + PPQ + 13, FIRST_TICK + 2 * PPQ - 5, Pitch::new(64)),
+            MidiNote::new(FIRST_TICK + 3 * PPQ, FIRST_TICK + 5 * PPQ + 1, Pitch::new(67)),
+        ];
+        track.update(&TrackMessage::AddManyNotes { notes: MidiNotes::from(notes) }, history);
+        track
+    }
+
+    #[test]
+    fn drag_undo_redo_cycles_do_not_drift() {
+        let mut history = History::default();
+        let mut track = track_with_notes(&mut history);
+        let original = positions(&track);
+        let mut dragged = None;
+
+        for _ in 0..CYCLES {
+            // a fraction of a beat that is not a whole number of ticks
+            let cursor_delta = Vector::new(0.3713, 1.0);
+            let original_notes = track.selected.notes.clone();
+            track.update(&TrackMessage::Dragged { cursor_delta, original_notes }, &mut history);
+            let drag = track.drag;
+            let scale = track.grid.scale.clone();
+            track.update(&TrackMessage::FinishDragging { drag, scale }, &mut history);
+
+            let after_drag = positions(&track);
+            assert_ne!(after_drag, original);
+            assert_eq!(*dragged.get_or_insert_with(|| after_drag.clone()), after_drag);
+
+            track.update(&TrackMessage::Undo, &mut history);
+            assert_eq!(positions(&track), original);
+            track.update(&TrackMessage::Redo, &mut history);
+            assert_eq!(positions(&track), after_drag);
+            track.update(&TrackMessage::Undo, &mut history);
+            assert_eq!(positions(&track), original);
+        }
+    }
+
+    #[test]
+    fn resize_undo_redo_cycles_do_not_drift() {
+        let mut history = History::default();
+        let mut track = track_with_notes(&mut history);
+        let original = positions(&track);
+        let mut resized = None;
+
+        for resize_end in [NoteEdge::End, NoteEdge::Start].iter().cycle().take(CYCLES) {
+            // the other notes are resized by a percentage that does not land on ticks
+            let on_index = NoteIndex { pitch_index: 64, time_index: 0 };
+            let original_notes = track.selected.notes.clone();
+            track.update(
+                &TrackMessage::ResizedNotes {
+                    original_notes,
+                    delta_time: PPQ / 7,
+                    resize_end: *resize_end,
+                    on_index,
+                },
+                &mut history,
+            );
+            let resize_percent = track.resize_percent;
+            track.update(&TrackMessage::FinishResizingNotes { resize_percent }, &mut history);
+
+            let after_resize = positions(&track);
+            assert_ne!(after_resize, original);
+            if *resize_end == NoteEdge::End {
+                assert_eq!(*resized.get_or_insert_with(|| after_resize.clone()), after_resize);
+            }
+
+            track.update(&TrackMessage::Undo, &mut history);
+            assert_eq!(positions(&track), original);
+            track.update(&TrackMessage::Redo, &mut history);
+            assert_eq!(positions(&track), after_resize);
+            track.update(&TrackMessage::Undo, &mut history);
+            assert_eq!(positions(&track), original);
+        }
+    }
+
+    // no two notes of the same pitch sound at once
+    fn assert_no_overlaps(track: &Track) {
+        let notes = positions(track);
+        for pair in notes.windows(2) {
+            let ((pitch, _, end), (next_pitch, next_start, _)) = (pair[0], pair[1]);
+            assert!(pitch != next_pitch || end <= next_start, "overlapping notes: {:?}", pair);
+        }
+    }
+
+    #[test]
+    fn chords_cut_the_notes_they_overlap() {
+        let mut history = History::default();
+        let mut track = track_with_notes(&mut history);
+        let original = positions(&track);
+
+        let chord: Vec<MidiNote> = [60, 64, 67]
+            .iter()
+            .map(|pitch| {
+                MidiNote::new(FIRST_TICK + 4 * PPQ, FIRST_TICK + 5 * PPQ, Pitch::new(*pitch))
+            })
+            .collect();
+        track.update(&TrackMessage::AddChord { notes: chord }, &mut history);
+        let with_chord = positions(&track);
+        assert_no_overlaps(&track);
+        assert_eq!(track.selected.notes.notes.iter().flatten().count(), 3);
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(positions(&track), original);
+        track.update(&TrackMessage::Redo, &mut history);
+        assert_eq!(positions(&track), with_chord);
+        assert_no_overlaps(&track);
+    }
+
+    // a track in C major, with notes that are not selected and notes that are, given
+    // as (pitch, start, end) in beats
+    fn track_in_c_major(
+        notes: &[(u8, Tick, Tick)],
+        selected: &[(u8, Tick, Tick)],
+        history: &mut History,
+    ) -> Track {
+        let to_notes = |notes: &[(u8, Tick, Tick)]| {
+            let notes: Vec<MidiNote> = notes
+                .iter()
+                .map(|(pitch, start, end)| {
+                    MidiNote::new(
+                        FIRST_TICK + start * PPQ,
+                        FIRST_TICK + end * PPQ,
+                        Pitch::new(*pitch),
+                    )
+                })
+                .collect();
+            MidiNotes::from(notes)
+        };
+
+        let mut track = Track::with_notes(0, to_notes(notes));
+        track.set_scale(ScaleType::Major, 0);
+        track.update(&TrackMessage::AddManyNotes { notes: to_notes(selected) }, history);
+        track
+    }
+
+    fn pitches(track: &Track) -> Vec<u8> {
+        positions(track).iter().map(|(pitch, _, _)| *pitch).collect()
+    }
+
+    #[test]
+    fn changing_key_from_major_to_minor_keeps_the_degrees() {
+        let mut history = History::default();
+        let mut track = track_in_c_major(&[(60, 0, 1), (64, 1, 2)], &[(67, 2, 3)], &mut history);
+
+        // the root moves down to A, the closest A to C
+        let message = TrackMessage::ChangeKey { scale_type: ScaleType::Minor, root: 9 };
+        track.update(&message, &mut history);
+
+        assert_eq!(pitches(&track), [57, 60, 64]);
+        assert_eq!(track.selected.notes.notes[64].len(), 1);
+        assert_eq!(track.grid.scale.scale_type, ScaleType::Minor);
+        assert_eq!(track.grid.scale.root, 9);
+    }
+
+    #[test]
+    fn changing_key_to_fewer_degrees_resolves_the_notes_that_land_on_each_other() {
+        let mut history = History::default();
+        // D and E, then A and B, become the same degree of the major pentatonic
+        let mut track =
+            track_in_c_major(&[(62, 0, 2), (69, 0, 4), (71, 2, 3)], &[(64, 1, 3)], &mut history);
+
+        let message = TrackMessage::ChangeKey { scale_type: ScaleType::Pentatonic, root: 0 };
+        track.update(&message, &mut history);
+
+        assert_no_overlaps(&track);
+        let beat = |beats: Tick| FIRST_TICK + beats * PPQ;
+        assert_eq!(
+            positions(&track),
+            [
+                (62, beat(0), beat(1)),
+                (62, beat(1), beat(3)),
+                (69, beat(0), beat(2)),
+                (69, beat(2), beat(3))
+            ]
+        );
+        // the selected note is the one that is kept whole
+        assert_eq!(track.selected.notes.notes[62].len(), 1);
+    }
+
+    #[test]
+    fn changing_key_undoes_in_one_step() {
+        let mut history = History::default();
+        let mut track =
+            track_in_c_major(&[(62, 0, 2), (69, 0, 4), (71, 2, 3)], &[(64, 1, 3)], &mut history);
+        let original = positions(&track);
+
+        let message = TrackMessage::ChangeKey { scale_type: ScaleType::Pentatonic, root: 0 };
+        track.update(&message, &mut history);
+        let changed = positions(&track);
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(positions(&track), original);
+        assert_eq!(track.grid.scale.scale_type, ScaleType::Major);
+        assert_eq!(track.selected.notes.notes[64].len(), 1);
+
+        track.update(&TrackMessage::Redo, &mut history);
+        assert_eq!(positions(&track), changed);
+        assert_eq!(track.grid.scale.scale_type, ScaleType::Pentatonic);
+    }
+
+    #[test]
+    fn conforming_to_the_scale_snaps_to_the_nearest_degree() {
+        let mut history = History::default();
+        let mut track =
+            track_in_c_major(&[], &[(61, 0, 1), (66, 1, 2), (70, 2, 3), (64, 3, 4)], &mut history);
+
+        let scale = track.grid.scale.clone();
+        track.update(&TrackMessage::ConformToScale { scale }, &mut history);
+
+        // C# to C, F# to F and A# to A, while E is already in the scale
+        assert_eq!(pitches(&track), [60, 64, 65, 69]);
+        assert_eq!(track.selected.notes.notes.iter().flatten().count(), 4);
+    }
+
+    #[test]
+    fn conforming_to_the_scale_undoes_in_one_step() {
+        let mut history = History::default();
+        // the C# lands on the C that is not selected, which is cut around it
+        let mut track = track_in_c_major(&[(60, 0, 4)], &[(61, 1, 2)], &mut history);
+        let original = positions(&track);
+
+        let scale = track.grid.scale.clone();
+        track.update(&TrackMessage::ConformToScale { scale }, &mut history);
+        let conformed = positions(&track);
+        assert_no_overlaps(&track);
+        assert!(!pitches(&track).contains(&61));
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(positions(&track), original);
+        assert_eq!(track.selected.notes.notes[61].len(), 1);
+
+        track.update(&TrackMessage::Redo, &mut history);
+        assert_eq!(positions(&track), conformed);
+    }
+
+    #[test]
+    fn quantizing_the_whole_track_undoes_in_one_step() {
+        let mut history = History::default();
+        let notes = vec![
+            MidiNote::new(FIRST_TICK + 7, FIRST_TICK + PPQ / 3 + 1, Pitch::new(60)),
+            MidiNote::new(FIRST_TICK + PPQ + 13, FIRST_TICK + 2 * PPQ - 5, Pitch::new(64)),
+            MidiNote::new(FIRST_TICK + 3 * PPQ + 250, FIRST_TICK + 5 * PPQ, Pitch::new(67)),
+        ];
+        // no note is selected, so the whole track is quantized
+        let mut track = Track::with_notes(0, MidiNotes::from(notes));
+        let original = positions(&track);
+
+        let settings = QuantizeSettings::default();
+        track.update(&TrackMessage::Quantize { settings }, &mut history);
+        let quantized = positions(&track);
+
+        // the grid lines are half a beat apart, and the lengths are kept
+        let half_beat = PPQ / 2;
+        assert_eq!(
+            quantized,
+            [
+                (60, FIRST_TICK, FIRST_TICK + PPQ / 3 - 6),
+                (64, FIRST_TICK + PPQ, FIRST_TICK + 2 * PPQ - 18),
+                (67, FIRST_TICK + 3 * PPQ + half_beat, FIRST_TICK + 5 * PPQ + half_beat - 250)
+            ]
+        );
+        assert!(track.selected.notes.notes.iter().all(|notes| notes.is_empty()));
+
+        track.update(&TrackMessage::Undo, &mut history);
+        assert_eq!(positions(&track), original);
+        assert!(track.selected.notes.notes.iter().all(|notes| notes.is_empty()));
+
+        track.update(&TrackMessage::Redo, &mut history);
+        assert_eq!(positions(&track), quantized);
+    }
+}
@@ -0,0 +1,94 @@
+//! Chord lane
+//!
+//! Drawn above the piano roll with the same time axis. The notes of the track, selected
+//! or not, are named beat by beat, and each chord is a box that spans the beats where it
+//! sounds, with its name spelled in the key of the track.
+
+use iced::widget::canvas::event::{self, Event};
+use iced::widget::canvas::{self, Canvas, Cursor, Geometry, Path, Stroke, Text};
+use iced::{alignment, Length, Point, Rectangle, Size};
+
+use crate::config::CHORD_LANE_HEIGHT;
+use crate::note::chord;
+use crate::piano_theme::TrackTheme;
+use crate::tick::{self, PPQ};
+use crate::track::{Track, TrackElement, TrackMessage};
+
+pub struct ChordLane<'a> {
+    track: &'a Track,
+}
+
+impl<'a> ChordLane<'a> {
+    pub fn new(track: &'a Track) -> Self {
+        Self { track }
+    }
+
+    pub fn view(self) -> TrackElement<'a> {
+        Canvas::new(self).width(Length::Fill).height(Length::Units(CHORD_LANE_HEIGHT)).into()
+    }
+}
+
+impl<'a> canvas::Program<TrackMessage, TrackTheme> for ChordLane<'a> {
+    type State = ();
+
+    fn update(
+        &self,
+        _state: &mut (),
+        _event: Event,
+        _bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> (event::Status, Option<TrackMessage>) {
+        (event::Status::Ignored, None)
+    }
+
+    fn draw(
+        &self,
+        _state: &(),
+        theme: &TrackTheme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let chords = self.track.chord_lane_cache.draw(bounds.size(), |frame| {
+            let size = frame.size();
+            let grid = &self.track.grid;
+
+            frame.fill_rectangle(Point::ORIGIN, size, theme.grid_background);
+
+            // only the visible beats are analyzed
+            let start = tick::from_beats(grid.x_to_beat(0.0, size.width));
+            let end = tick::from_beats(grid.x_to_beat(size.width, size.width));
+            let notes =
+                self.track.midi_notes.notes.iter().chain(self.track.selected.notes.notes.iter());
+
+            for span in chord::analyze(notes.flatten(), start, end, PPQ) {
+                let x0 = grid.tick_to_x(span.start, size.width);
+                let x1 = grid.tick_to_x(span.end, size.width);
+                let rectangle =
+                    Path::rectangle(Point::new(x0, 2.0), Size::new(x1 - x0, size.height - 4.0));
+
+                frame.fill(&rectangle, theme.grid_piano_light_row);
+                frame.stroke(
+                    &rectangle,
+                    Stroke::default().with_width(1.0).with_color(theme.grid_bar_line),
+                );
+
+                frame.fill_text(Text {
+                    content: span.chord.name(&grid.scale),
+                    color: theme.text,
+                    size: 14.0,
+                    position: Point::new(x0.max(0.0) + 4.0, size.height / 2.0),
+                    horizontal_alignment: alignment::Horizontal::Left,
+                    vertical_alignment: alignment::Vertical::Center,
+                    ..Text::default()
+                });
+            }
+
+            frame.stroke(
+                &Path::rectangle(Point::ORIGIN, size),
+                Stroke::default().with_width(1.0).with_color(theme.track_contour),
+            );
+        });
+
+        vec![chords]
+    }
+}
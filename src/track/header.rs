@@ -0,0 +1,89 @@
+//! Track header
+//!
+//! The column of controls on the left of a track: the midi channel, the general midi
+//! program, the mute, solo and record-arm toggles, and the step input toggle. The name
+//! and the position of the track belong to the editor, which adds them on top.
+
+use iced::alignment;
+use iced::widget::{button, text, Column, Row};
+use iced::{Alignment, Length};
+
+use crate::config::{DRUM_CHANNEL, GENERAL_MIDI_PROGRAMS, TRACK_HEADER_WIDTH};
+use crate::piano_theme::Button;
+use crate::track::step_input::step_label;
+use crate::track::{Track, TrackElement, TrackMessage};
+use crate::widgets;
+
+pub struct TrackHeader<'a> {
+    track: &'a Track,
+}
+
+impl<'a> TrackHeader<'a> {
+    pub fn new(track: &'a Track) -> Self {
+        Self { track }
+    }
+
+    pub fn view(self) -> TrackElement<'a> {
+        let track = self.track;
+
+        // channels are shown from 1 to 16, like on most instruments
+        let channel_label = if track.channel == DRUM_CHANNEL {
+            format!("Ch {} (drums)", track.channel + 1)
+        } else {
+            format!("Ch {}", track.channel + 1)
+        };
+        let channel = stepper(
+            channel_label,
+            TrackMessage::SetChannel { channel: (track.channel + 15) % 16 },
+            TrackMessage::SetChannel { channel: (track.channel + 1) % 16 },
+        );
+
+        let program = stepper(
+            format!("{} {}", track.program + 1, GENERAL_MIDI_PROGRAMS[track.program as usize]),
+            TrackMessage::SetProgram { program: (track.program + 127) % 128 },
+            TrackMessage::SetProgram { program: (track.program + 1) % 128 },
+        );
+
+        let toggles = Row::with_children(vec![
+            toggle("M", track.is_muted, TrackMessage::ToggleMute),
+            toggle("S", track.is_soloed, TrackMessage::ToggleSolo),
+            toggle("R", track.is_armed, TrackMessage::ToggleArm),
+        ])
+        .spacing(5);
+
+        // K for the computer keyboard, with the step and the octave while it is on
+        let step_input_label = match &track.step_input {
+            Some(step_input) => format!(
+                "Step {}, octave {}",
+                step_label(track.grid.beat_fraction),
+                step_input.octave
+            ),
+            None => "Step input".to_string(),
+        };
+        let step_input = Row::with_children(vec![
+            toggle("K", track.step_input.is_some(), TrackMessage::ToggleStepInput),
+            text(step_input_label).size(14).width(Length::Fill).into(),
+        ])
+        .spacing(5)
+        .align_items(Alignment::Center);
+
+        Column::with_children(vec![channel, program, toggles.into(), step_input.into()])
+            .spacing(5)
+            .width(Length::Units(TRACK_HEADER_WIDTH))
+            .into()
+    }
+}
+
+fn stepper<'a>(label: String, previous: TrackMessage, next: TrackMessage) -> TrackElement<'a> {
+    widgets::stepper(text(label).size(14).width(Length::Fill), previous, next)
+}
+
+// a toggle that is on is drawn in black
+fn toggle<'a>(label: &'static str, is_on: bool, message: TrackMessage) -> TrackElement<'a> {
+    button(text(label).horizontal_alignment(alignment::Horizontal::Center))
+        .padding(5)
+        .width(Length::Units(35))
+        .style(if is_on { Button::Black } else { Button::Yellow })
+        .on_press(message)
+        .into()
+}
@@ -1,4 +1,9 @@
 pub mod actions;
+pub mod chord_lane;
+pub mod header;
+pub mod piano;
+pub mod step_input;
+pub mod velocity_lane;
 // pub mod undoredo;
 
 pub use iced_native;
@@ -13,27 +18,37 @@ use iced::{
 };
 
 use crate::grid::{Grid, GridInteraction};
+use crate::midi_input::RecordMode;
+use crate::note::chord_stamp::ChordStamp;
 use crate::note::midi_notes::{
     ChangeSelection, MidiNote, MidiNotes, NoteEdge, NoteIndex, NoteInteraction, OverNote, Pitch,
-    ResizedEdgePercent, ResizedEdges, Selected, WritingMode,
+    ResizedEdgePercent, ResizedEdges, Selected, VelocityChange, WritingMode,
 };
+use crate::note::quantize::QuantizeSettings;
 use crate::note::scale::{Scale, ScaleType};
+use crate::note::transpose::{change_key, transpose_notes, Interval};
 use crate::piano_theme::TrackTheme;
+use crate::tick::{self, Tick, PPQ};
+use crate::timeline::LoopRegion;
+pub use crate::timeline::TimingInfo;
 
-use crate::config::{MAX_SCALING, MIN_SCALING, RESIZE_LEN_RATIO_THRESHOLD};
+use crate::config::{INIT_GRID_SIZE, MAX_SCALING, MIN_SCALING, RESIZE_LEN_RATIO_THRESHOLD};
 use crate::track::actions::{SelectionAction, TrackAction, TrackHistory};
+use crate::track::step_input::{StepInput, StepKey};
 use crate::util::{History, TrackId};
 
+use serde::{Deserialize, Serialize};
+
 pub type TrackElement<'a> = iced::Element<'a, TrackMessage, iced::Renderer<TrackTheme>>;
 
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Drag {
     delta_pitch: i8,
-    delta_times: f32,
+    delta_times: Tick,
 }
 
 impl Drag {
-    const ZERO: Self = Drag { delta_pitch: 0, delta_times: 0.0 };
+    const ZERO: Self = Drag { delta_pitch: 0, delta_times: 0 };
 }
 
 pub struct Track {
@@ -43,21 +58,46 @@ pub struct Track {
     selection_square_cache: Cache,
     selected_notes_cache: Cache,
     player_head_cache: Cache,
+    chord_lane_cache: Cache,
     pub selected: Selected,
     pub midi_notes: MidiNotes,
     pub grid: Grid,
     pub meta: TrackMeta,
     pub channel: u8,
+    // general midi program (instrument) of the channel
+    pub program: u8,
     pub timing_info: TimingInfo,
     pub is_active: bool,
+    // a muted track is not heard, and when any track is soloed only the soloed ones are
+    pub is_muted: bool,
+    pub is_soloed: bool,
+    // recording goes to the armed tracks
+    pub is_armed: bool,
+    // the computer keyboard writes notes while the track is active
+    pub step_input: Option<StepInput>,
+    // clicks in writing mode write this chord instead of a single note
+    pub chord_stamp: Option<ChordStamp>,
+    // the scale that Toggle puts back after showing every pitch
+    toggled_scale_type: ScaleType,
+    // the pitches to be heard, which the editor takes after every update
+    pub audition: Vec<u8>,
     pub modifiers: keyboard::Modifiers,
     pub last_cursor_delta: Vector,
-    pub last_delta_time: f32,
+    pub last_delta_time: Tick,
 
     pub drag: Drag,
     pub resize_percent: ResizedEdgePercent,
-
-    pub player_head: f32,
+    // the selected notes as they were before the current resize, which undo puts back
+    pub resize_origin: MidiNotes,
+    // the same for the current drag
+    pub drag_origin: MidiNotes,
+
+    pub player_head: Tick,
+    // the keys of the notes under the playhead light up on the piano while playing
+    pub is_playing: bool,
+    // the loop region of the transport, drawn over the grid
+    pub loop_region: Option<LoopRegion>,
+    pub is_looping: bool,
     pub hovering_selected: bool,
 
     interaction: Interaction,
@@ -65,7 +105,8 @@ pub struct Track {
     track_history: TrackHistory,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TrackMeta {
     pub name: String,
     pub bpm: f32,
@@ -81,14 +122,18 @@ impl Track {
     pub fn new(track_id: TrackId) -> Self {
         let mut midi_notes = MidiNotes::new();
 
-        let note0 = MidiNote::new(1.0, 2.5, Pitch::new(53));
-        let note1 = MidiNote::new(2.0, 3.5, Pitch::new(55));
-        let note2 = MidiNote::new(3.0, 4.5, Pitch::new(53));
+        let note0 = MidiNote::new(PPQ, 5 * PPQ / 2, Pitch::new(53));
+        let note1 = MidiNote::new(2 * PPQ, 7 * PPQ / 2, Pitch::new(55));
+        let note2 = MidiNote::new(3 * PPQ, 9 * PPQ / 2, Pitch::new(53));
 
         midi_notes.add(&note0);
         midi_notes.add(&note1);
         midi_notes.add(&note2);
 
+        Self::with_notes(track_id, midi_notes)
+    }
+
+    pub fn with_notes(track_id: TrackId, midi_notes: MidiNotes) -> Self {
         Self {
             track_id,
             grid_cache: Cache::default(),
@@ -96,28 +141,176 @@ impl Track {
             selection_square_cache: Cache::default(),
             selected_notes_cache: Cache::default(),
             player_head_cache: Cache::default(),
+            chord_lane_cache: Cache::default(),
             selected: Selected::default(),
             midi_notes,
             grid: Grid::default(),
             channel: 0,
+            program: 0,
             meta: TrackMeta::default(),
             timing_info: TimingInfo::default(),
             is_active: false,
+            is_muted: false,
+            is_soloed: false,
+            is_armed: false,
+            step_input: None,
+            chord_stamp: None,
+            toggled_scale_type: ScaleType::Minor,
+            audition: Vec::new(),
             modifiers: keyboard::Modifiers::default(),
             last_cursor_delta: Vector::default(),
-            last_delta_time: 0.0,
+            last_delta_time: 0,
             drag: Drag::default(),
             resize_percent: ResizedEdgePercent::default(),
+            resize_origin: MidiNotes::default(),
+            drag_origin: MidiNotes::default(),
 
             track_history: TrackHistory::default(),
             interaction: Interaction::default(),
-            player_head: 3.0,
+            player_head: 3 * PPQ,
+            is_playing: false,
+            loop_region: None,
+            is_looping: false,
             hovering_selected: false,
         }
     }
 
+    pub fn id(&self) -> TrackId {
+        self.track_id
+    }
+
+    // returns the previous velocities, which undo the change
+    pub fn set_velocities(&mut self, changes: &Vec<VelocityChange>) -> Vec<VelocityChange> {
+        let mut previous = Vec::with_capacity(changes.len());
+
+        for change in changes.iter() {
+            let notes =
+                if change.is_selected { &mut self.selected.notes } else { &mut self.midi_notes };
+
+            let note = notes.get_mut(change.note_index);
+            previous.push(VelocityChange { velocity: note.velocity, ..*change });
+            note.velocity = change.velocity.clamp(1, 127);
+        }
+
+        self.notes_cache.clear();
+        self.selected_notes_cache.clear();
+
+        previous
+    }
+
+    pub fn set_loop_region(&mut self, loop_region: Option<LoopRegion>, is_looping: bool) {
+        self.loop_region = loop_region;
+        self.is_looping = is_looping;
+    }
+
+    // the bar lines of the grid follow the meter track
+    pub fn set_timing_info(&mut self, timing_info: TimingInfo) {
+        self.timing_info = timing_info;
+        self.grid_cache.clear();
+    }
+
+    // only the rows of the grid change, the notes keep their pitches
+    pub fn set_scale(&mut self, scale_type: ScaleType, root: u8) {
+        self.grid.scale.set_root(root);
+        self.grid.scale.set_scale_type(scale_type);
+        self.notes_cache.clear();
+        self.grid_cache.clear();
+        self.selected_notes_cache.clear();
+        self.chord_lane_cache.clear();
+    }
+
+    pub fn set_player_head(&mut self, player_head: Tick) {
+        self.player_head = player_head;
+        self.player_head_cache.clear();
+    }
+
+    fn step_key_pressed(&mut self, key_code: keyboard::KeyCode, history: &mut History) {
+        let step = self.grid.beat_fraction_ticks();
+        let step_input = match &mut self.step_input {
+            Some(step_input) => step_input,
+            None => return,
+        };
+
+        let advance = match StepKey::from_key_code(key_code) {
+            Some(StepKey::Note { row, degree }) => {
+                match step_input.pitch(&self.grid.scale, row, degree) {
+                    Some(pitch) => step_input.press_note(key_code, pitch, self.player_head, step),
+                    None => 0,
+                }
+            }
+            Some(StepKey::Rest) => step_input.rest(step),
+            Some(StepKey::Step(beat_fraction)) => {
+                self.grid.beat_fraction = beat_fraction;
+                self.grid_cache.clear();
+                0
+            }
+            Some(StepKey::Octave(delta)) => {
+                step_input.set_octave(step_input.octave + delta);
+                0
+            }
+            Some(StepKey::Exit) => {
+                self.update(&TrackMessage::ToggleStepInput, history);
+                0
+            }
+            None => 0,
+        };
+
+        if advance > 0 {
+            self.set_player_head(self.player_head + advance);
+        }
+    }
+
+    // the chord becomes the selection, so that it can be moved right away, and cuts
+    // the notes it overlaps like any added note
+    fn add_chord(&mut self, notes: Vec<MidiNote>, history: &mut History) {
+        if notes.is_empty() {
+            return;
+        }
+        self.update(
+            &TrackMessage::UpdateSelection { change_selection: ChangeSelection::DrainSelect },
+            history,
+        );
+
+        let original_notes = self.selected.notes.clone();
+        self.selected.notes.add_midi_notes(&MidiNotes::from(notes.clone()));
+        let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
+
+        self.notes_cache.clear();
+        self.selected_notes_cache.clear();
+        self.chord_lane_cache.clear();
+
+        if !history.is_dummy {
+            history.add_action_from_track(self.track_id);
+            self.track_history.add_track_action(TrackAction::AddedChord {
+                original_notes,
+                conflicts,
+                message: TrackMessage::AddChord { notes },
+            });
+        }
+    }
+
     pub fn view(&self) -> TrackElement {
-        Canvas::new(self).width(Length::Fill).height(Length::Fill).into()
+        // fixed height, so that many tracks can be stacked in a scrollable
+        Canvas::new(self)
+            .width(Length::Fill)
+            .height(Length::Units(INIT_GRID_SIZE.height as u16))
+            .into()
+    }
+
+    pub fn piano_view(&self) -> TrackElement {
+        piano::PianoKeyboard::new(self).view()
+    }
+
+    pub fn chord_lane_view(&self) -> TrackElement {
+        chord_lane::ChordLane::new(self).view()
+    }
+
+    pub fn velocity_lane_view(&self) -> TrackElement {
+        velocity_lane::VelocityLane::new(self).view()
+    }
+
+    pub fn header_view(&self) -> TrackElement {
+        header::TrackHeader::new(self).view()
     }
 
     pub fn draw_player_head(&self, bounds: Rectangle, grid: &Grid, theme: &TrackTheme) -> Geometry {
@@ -125,7 +318,7 @@ impl Track {
             grid.adjust_frame(frame, &bounds.size());
             let region = grid.visible_region(frame.size());
 
-            let player_head_x = self.player_head;
+            let player_head_x = tick::to_beats(self.player_head);
 
             let player_head_color = theme.player_head;
 
@@ -184,11 +377,13 @@ impl Track {
             let conflicts = self.midi_notes.resolve_conflicts_single(&note);
             self.selected_notes_cache.clear();
             self.notes_cache.clear();
+            self.chord_lane_cache.clear();
 
             let added_note = self.selected.notes.add(&note);
 
             // println!("added note: {:?}", added_note);
             if !history.is_dummy {
+                self.audition.push(note.pitch.get());
                 history.add_action_from_track(self.track_id);
                 self.track_history.add_track_action(TrackAction::AddNote {
                     added_note,
@@ -207,24 +402,136 @@ impl Track {
             }
             TrackMessage::Toggle => {
                 // println!("Toggled");
-                match self.grid.scale.scale_type {
-                    ScaleType::Chromatic => self.grid.scale.set_scale_type(ScaleType::Minor),
-                    ScaleType::Minor => self.grid.scale.set_scale_type(ScaleType::Chromatic),
-                    _ => {}
+                let scale_type = match self.grid.scale.scale_type {
+                    ScaleType::Chromatic => self.toggled_scale_type.clone(),
+                    ref scale_type => {
+                        self.toggled_scale_type = scale_type.clone();
+                        ScaleType::Chromatic
+                    }
+                };
+                self.set_scale(scale_type, self.grid.scale.root);
+            }
+            TrackMessage::SetScale { scale_type, root } => {
+                self.set_scale(scale_type, root);
+            }
+            // the scale changes, and every note follows it degree by degree
+            TrackMessage::ChangeKey { ref scale_type, root } => {
+                let original_notes = self.midi_notes.clone();
+                let original_selected = self.selected.notes.clone();
+                let original_scale = self.grid.scale.clone();
+                let to = Scale::new(scale_type.clone(), root);
+
+                let mut clamped = 0;
+                let mut remap = |notes: &MidiNotes| -> MidiNotes {
+                    let mut remapped: Vec<MidiNote> = notes
+                        .notes
+                        .iter()
+                        .flatten()
+                        .map(|note| {
+                            let (pitch, is_clamped) =
+                                change_key(note.get_pitch(), &original_scale, &to);
+                            if is_clamped {
+                                clamped += 1;
+                            }
+                            MidiNote { pitch: Pitch::new(pitch), ..note.clone() }
+                        })
+                        .collect();
+                    remapped.sort_by(|a, b| a.start.cmp(&b.start));
+                    MidiNotes::from(remapped)
+                };
+
+                // a scale with fewer degrees maps several degrees onto one, and the notes
+                // that land on each other are resolved like any other addition, giving
+                // priority to the note that starts last, then to the selected notes
+                self.midi_notes = remap(&original_notes);
+                self.selected.notes = remap(&original_selected);
+                self.midi_notes.resolve_conflicts(&self.selected.notes);
+                if clamped > 0 {
+                    println!(
+                        "{} notes would have left the midi range, and were moved back by octaves",
+                        clamped
+                    );
+                }
+
+                self.set_scale(scale_type.clone(), root);
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::ChangedKey {
+                        original_notes,
+                        original_selected,
+                        original_scale,
+                        message: message.clone(),
+                    });
                 }
-                self.notes_cache.clear();
-                self.grid_cache.clear();
-                self.selected_notes_cache.clear();
             }
             TrackMessage::ModifiersChanged(modifiers) => {
                 self.modifiers = modifiers;
             }
+            TrackMessage::SetChannel { channel } => {
+                self.channel = channel.min(15);
+            }
+            TrackMessage::SetProgram { program } => {
+                self.program = program.min(127);
+            }
+            TrackMessage::ToggleMute => {
+                self.is_muted = !self.is_muted;
+            }
+            TrackMessage::ToggleSolo => {
+                self.is_soloed = !self.is_soloed;
+            }
+            TrackMessage::ToggleArm => {
+                self.is_armed = !self.is_armed;
+            }
+            TrackMessage::Audition { pitch } => {
+                self.audition.push(pitch);
+            }
+            TrackMessage::SelectPitch { pitch } => {
+                self.update(
+                    &TrackMessage::UpdateSelection {
+                        change_selection: ChangeSelection::DrainSelect,
+                    },
+                    history,
+                );
+                let pitch_index = pitch as usize;
+                let note_indices: Vec<NoteIndex> = (0..self.midi_notes.notes[pitch_index].len())
+                    .map(|time_index| NoteIndex { pitch_index, time_index })
+                    .collect();
+                self.update(
+                    &TrackMessage::UpdateSelection {
+                        change_selection: ChangeSelection::SelectMany { note_indices },
+                    },
+                    history,
+                );
+            }
+            TrackMessage::ToggleStepInput => match self.step_input.take() {
+                Some(mut step_input) => self.add_chord(step_input.finish(), history),
+                None => self.step_input = Some(StepInput::default()),
+            },
+            TrackMessage::AddChord { notes } => {
+                // not heard again on redo
+                if !history.is_dummy {
+                    self.audition.extend(notes.iter().map(|note| note.get_pitch()));
+                }
+                self.add_chord(notes, history);
+            }
+            TrackMessage::StepKeyPressed(key_code) => {
+                self.step_key_pressed(key_code, history);
+            }
+            TrackMessage::StepKeyReleased(key_code) => {
+                let notes = match &mut self.step_input {
+                    Some(step_input) => step_input.release(key_code),
+                    None => Vec::new(),
+                };
+                self.add_chord(notes, history);
+            }
             TrackMessage::Translated { translation } => {
                 self.grid.translation = translation;
 
                 self.notes_cache.clear();
                 self.grid_cache.clear();
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
                 self.player_head_cache.clear();
             }
 
@@ -238,6 +545,7 @@ impl Track {
                 self.notes_cache.clear();
                 self.grid_cache.clear();
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
                 self.player_head_cache.clear();
             }
             // TrackMessage::LeftClick => {
@@ -387,24 +695,38 @@ impl Track {
 
             TrackMessage::Dragged { cursor_delta, original_notes } => {
                 self.last_cursor_delta = cursor_delta;
+                self.drag_origin = original_notes.clone();
 
                 let mut modified_notes: MidiNotes = original_notes.clone();
                 let (delta_time, delta_pitch) =
                     modified_notes.drag_all_notes(cursor_delta, &self.grid);
 
+                // the notes are heard again only when they change pitch
+                if delta_pitch != self.drag.delta_pitch {
+                    for (pitch, notes) in modified_notes.notes.iter().enumerate() {
+                        if !notes.is_empty() {
+                            self.audition.push(pitch as u8);
+                        }
+                    }
+                }
+
                 self.drag.delta_times = delta_time;
                 self.drag.delta_pitch = delta_pitch;
 
                 self.selected.notes.clear();
                 self.selected.notes.add_midi_notes(&modified_notes);
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
                 self.notes_cache.clear();
             }
 
             TrackMessage::FinishDragging { drag, scale } => {
                 println!("Finish Dragging");
+                // the next drag starts from the original pitch
+                self.drag = Drag::ZERO;
                 let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
                 self.notes_cache.clear();
 
                 if !history.is_dummy {
@@ -412,6 +734,7 @@ impl Track {
                     self.track_history.add_track_action(TrackAction::DraggedNotes {
                         // drag,
                         // scale,
+                        original_notes: std::mem::take(&mut self.drag_origin),
                         conflicts,
                         message: TrackMessage::FinishDragging { drag, scale },
                     });
@@ -421,6 +744,7 @@ impl Track {
             TrackMessage::ResizedNotes { mut original_notes, delta_time, resize_end, on_index } => {
                 // println!("original_notes: {:?}", original_notes);
                 self.last_delta_time = delta_time;
+                self.resize_origin = original_notes.clone();
 
                 // let delta_times = match resize_end {
                 //     NoteEdge::Start => ResizedEdges::new(delta_time, 0.0),
@@ -437,6 +761,7 @@ impl Track {
                 self.selected.notes = original_notes;
 
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
             }
 
             TrackMessage::FinishResizingNotes { resize_percent: note_used } => {
@@ -444,12 +769,14 @@ impl Track {
                 let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
                 self.selected_notes_cache.clear();
                 self.notes_cache.clear();
+                self.chord_lane_cache.clear();
 
                 if !history.is_dummy {
                     history.add_action_from_track(self.track_id);
                     self.track_history.add_track_action(TrackAction::ResizedNotes {
                         // delta_time,
                         // resize_end,
+                        original_notes: std::mem::take(&mut self.resize_origin),
                         resized_conflicts,
                         conflicts,
                         message: TrackMessage::FinishResizingNotes {
@@ -471,6 +798,7 @@ impl Track {
                 // println!("deleted notes: {}", deleted_notes.number_of_notes);
                 self.notes_cache.clear();
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
 
                 if !history.is_dummy {
                     history.add_action_from_track(self.track_id);
@@ -485,6 +813,182 @@ impl Track {
                 self.add_note(&m, history);
             }
 
+            TrackMessage::Quantize { settings } => {
+                let whole_track = self.selected.notes.is_empty();
+                if whole_track {
+                    self.midi_notes.drain(&mut self.selected.notes);
+                }
+
+                let original_notes = self.selected.notes.clone();
+                let period = self.grid.beat_fraction_ticks();
+
+                let mut quantized: Vec<MidiNote> = original_notes
+                    .notes
+                    .iter()
+                    .flatten()
+                    .map(|note| settings.quantize_note(note, period))
+                    .collect();
+                quantized.sort_by(|a, b| a.start.cmp(&b.start));
+
+                // notes of the same pitch that land on each other are resolved like
+                // any other addition, giving priority to the note that starts last
+                self.selected.notes = MidiNotes::from(quantized);
+                let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
+
+                if whole_track {
+                    self.selected.notes.drain(&mut self.midi_notes);
+                }
+
+                self.notes_cache.clear();
+                self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::Quantized {
+                        original_notes,
+                        whole_track,
+                        conflicts,
+                        message: message.clone(),
+                    });
+                }
+            }
+
+            // transposes the selected notes, or the whole track if no note is selected
+            TrackMessage::Transpose { interval } => {
+                let whole_track = self.selected.notes.is_empty();
+                if whole_track {
+                    self.midi_notes.drain(&mut self.selected.notes);
+                }
+
+                let original_notes = self.selected.notes.clone();
+                let (mut transposed, clamped) =
+                    transpose_notes(&original_notes, interval, &self.grid.scale);
+                if clamped > 0 {
+                    println!(
+                        "{} notes would have left the midi range, and were moved back by octaves",
+                        clamped
+                    );
+                }
+                transposed.sort_by(|a, b| a.start.cmp(&b.start));
+
+                self.selected.notes = MidiNotes::from(transposed);
+                let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
+
+                if whole_track {
+                    self.selected.notes.drain(&mut self.midi_notes);
+                }
+
+                self.notes_cache.clear();
+                self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::Transposed {
+                        original_notes,
+                        whole_track,
+                        conflicts,
+                        message: message.clone(),
+                    });
+                }
+            }
+
+            // the selected notes out of the scale move to the closest pitch of the scale
+            TrackMessage::ConformToScale { ref scale } => {
+                if self.selected.notes.is_empty() {
+                    return;
+                }
+
+                let original_notes = self.selected.notes.clone();
+                let mut conformed: Vec<MidiNote> = original_notes
+                    .notes
+                    .iter()
+                    .flatten()
+                    .map(|note| MidiNote {
+                        pitch: Pitch::new(scale.nearest_pitch(note.get_pitch())),
+                        ..note.clone()
+                    })
+                    .collect();
+                conformed.sort_by(|a, b| a.start.cmp(&b.start));
+
+                self.selected.notes = MidiNotes::from(conformed);
+                let conflicts = self.midi_notes.resolve_conflicts(&self.selected.notes);
+
+                self.notes_cache.clear();
+                self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::ConformedToScale {
+                        original_notes,
+                        conflicts,
+                        message: message.clone(),
+                    });
+                }
+            }
+
+            TrackMessage::RecordTake { ref notes, start, end, mode, quantize } => {
+                let original_notes = self.midi_notes.clone();
+                let original_selected = self.selected.notes.clone();
+
+                // the take is merged with all the notes of the track, and nothing stays selected
+                self.selected.notes.drain(&mut self.midi_notes);
+
+                if let RecordMode::Replace = mode {
+                    let replaced: Vec<NoteIndex> = self
+                        .midi_notes
+                        .get_all_note_indices()
+                        .into_iter()
+                        .filter(|note_index| {
+                            let note = self.midi_notes.get(*note_index);
+                            note.start >= start && note.start < end
+                        })
+                        .collect();
+                    self.midi_notes.remove_notes(&replaced);
+                }
+
+                let take = if quantize {
+                    let period = self.grid.beat_fraction_ticks();
+                    let quantized: Vec<MidiNote> = notes
+                        .notes
+                        .iter()
+                        .flatten()
+                        .map(|note| QuantizeSettings::default().quantize_note(note, period))
+                        .collect();
+                    MidiNotes::from(quantized)
+                } else {
+                    notes.clone()
+                };
+                self.midi_notes.add_midi_notes(&take);
+
+                self.notes_cache.clear();
+                self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::RecordedTake {
+                        original_notes,
+                        original_selected,
+                        message: message.clone(),
+                    });
+                }
+            }
+
+            TrackMessage::SetVelocities { ref changes } => {
+                let previous = self.set_velocities(changes);
+
+                if !history.is_dummy {
+                    history.add_action_from_track(self.track_id);
+                    self.track_history.add_track_action(TrackAction::ChangedVelocities {
+                        previous,
+                        message: message.clone(),
+                    });
+                }
+            }
+
             TrackMessage::DeleteOne { note_index_before, is_selected } => {
                 let note_before = if !is_selected {
                     self.update(
@@ -499,6 +1003,7 @@ impl Track {
                 };
                 self.notes_cache.clear();
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
 
                 if !history.is_dummy {
                     history.add_action_from_track(self.track_id);
@@ -514,6 +1019,7 @@ impl Track {
             TrackMessage::AddManyNotes { notes } => {
                 let added_notes = self.selected.notes.add_midi_notes(&notes);
                 self.selected_notes_cache.clear();
+                self.chord_lane_cache.clear();
                 if !history.is_dummy {
                     history.add_action_from_track(self.track_id);
                     self.track_history.add_track_action(TrackAction::AddManyNotes {
@@ -717,14 +1223,15 @@ impl Track {
                 cursor_delta_x = self.nearest_beat(cursor_delta_x);
             };
 
-            if cursor_delta_x == self.last_delta_time {
+            let delta_time = tick::from_beats(cursor_delta_x);
+            if delta_time == self.last_delta_time {
                 return (event::Status::Ignored, None);
             }
 
             return (
                 event::Status::Captured,
                 Some(TrackMessage::ResizedNotes {
-                    delta_time: cursor_delta_x,
+                    delta_time,
                     original_notes: original_notes.clone(),
                     resize_end: resize_end.clone(),
                     on_index: *on_index,
@@ -941,7 +1448,7 @@ impl Track {
                     // find the indices of the notes that have a large difference in length compared to the note
                     // being clicked on
                     let note = self.selected.notes.get(*on_index);
-                    let length = note.end - note.start;
+                    let length = note.length() as f32;
 
                     let mut note_indices = vec![];
 
@@ -950,7 +1457,7 @@ impl Track {
 
                     for (pitch_index, note_vec) in self.selected.notes.notes.iter().enumerate() {
                         for (time_index, note) in note_vec.iter().enumerate().rev() {
-                            let len_ration = (note.end - note.start) / length;
+                            let len_ration = note.length() as f32 / length;
                             if len_ration > RESIZE_LEN_RATIO_THRESHOLD
                                 || len_ration < inverse_len_ratio
                             {
@@ -1061,8 +1568,8 @@ impl Track {
 
             let pitch = Pitch(music_scale_cursor.y.floor() as i16);
 
-            let period = self.grid.beat_fraction;
-            let start = music_scale_cursor.x;
+            let period = self.grid.beat_fraction_ticks();
+            let start = tick::from_beats(music_scale_cursor.x);
             let end = start + period;
 
             let note = MidiNote::new(start, end, pitch);
@@ -1071,6 +1578,23 @@ impl Track {
                 event::Status::Captured,
                 Some(TrackMessage::AddNote { note, add_mode: AddMode::Custom }),
             );
+        } else if let Some(chord_stamp) = &self.chord_stamp {
+            // a chord is written once per click, so dragging does not write any more notes
+            self.interaction.note_interaction =
+                NoteInteraction::Writing { writing_mode: WritingMode::None };
+
+            let pitch = music_scale_cursor.y.floor().clamp(0.0, 127.0) as u8;
+
+            let period = self.grid.beat_fraction_ticks();
+            let start = tick::snap_down(tick::from_beats(music_scale_cursor.x), period);
+
+            let notes = chord_stamp
+                .pitches(pitch, &self.grid.scale)
+                .into_iter()
+                .map(|pitch| MidiNote::new(start, start + period, Pitch::new(pitch)))
+                .collect();
+
+            return (event::Status::Captured, Some(TrackMessage::AddChord { notes }));
         } else {
             // if alt is not pressed, add a note with length equal to the current beat fraction
             self.interaction.note_interaction =
@@ -1079,8 +1603,8 @@ impl Track {
 
             let pitch = Pitch(music_scale_cursor.y.floor() as i16);
 
-            let period = self.grid.beat_fraction;
-            let start = (music_scale_cursor.x / period).floor() * period;
+            let period = self.grid.beat_fraction_ticks();
+            let start = tick::snap_down(tick::from_beats(music_scale_cursor.x), period);
 
             let end = start + period;
 
@@ -1184,7 +1708,7 @@ impl Track {
                 //
                 NoteInteraction::EitherSelectingOrSettingPlayerHead { .. } => {
                     // println!("blah1");
-                    self.player_head = music_scale_cursor.x;
+                    self.player_head = tick::from_beats(music_scale_cursor.x);
                     println!("player head: {}", self.player_head);
                     self.interaction.note_interaction = NoteInteraction::None;
                     self.player_head_cache.clear();
@@ -1224,7 +1748,7 @@ impl Track {
                 } => {
                     self.interaction.note_interaction = NoteInteraction::None;
                     let delta_time = music_scale_cursor.x - init_cursor.x;
-                    let delta_times = ResizedEdges::from_end(delta_time);
+                    let delta_times = ResizedEdges::from_end(tick::from_beats(delta_time));
                     return Some((
                         event::Status::Captured,
                         Some(TrackMessage::FinishResizingNotes {
@@ -1239,7 +1763,7 @@ impl Track {
                 }
                 //
                 NoteInteraction::EitherSelectingOrSettingPlayerHead { .. } => {
-                    self.player_head = music_scale_cursor.x;
+                    self.player_head = tick::from_beats(music_scale_cursor.x);
                     self.interaction.note_interaction = NoteInteraction::None;
                 }
                 NoteInteraction::Selecting { initial_music_cursor, .. } => {
@@ -1277,7 +1801,7 @@ impl Track {
                 NoteInteraction::Resizing { .. } => {
                     self.interaction.note_interaction = NoteInteraction::None;
 
-                    if self.drag.delta_times == 0.0 {
+                    if self.drag.delta_times == 0 {
                         return Some((event::Status::Ignored, None));
                     }
 
@@ -1346,12 +1870,11 @@ impl Track {
                 // println!("new notes");
 
                 let pitch = Pitch(music_scale_cursor.y.floor() as i16);
-                let period = self.grid.beat_fraction;
-                let start = (music_scale_cursor.x / period).floor() * period;
+                let period = self.grid.beat_fraction_ticks();
+                let start = tick::snap_down(tick::from_beats(music_scale_cursor.x), period);
                 let end = start + period;
-                let tiny = 1e-7;
 
-                let note = MidiNote::new(start + tiny, end - tiny, pitch);
+                let note = MidiNote::new(start, end, pitch);
 
                 return Some((
                     event::Status::Captured,
@@ -1364,8 +1887,8 @@ impl Track {
             NoteInteraction::Writing { writing_mode: WritingMode::CustomWriting(init_cursor) } => {
                 let pitch = Pitch(music_scale_cursor.y.floor() as i16);
                 // let start = music_scale_cursor.x.floor();
-                let period = self.grid.beat_fraction;
-                let start = init_cursor.x;
+                let period = self.grid.beat_fraction_ticks();
+                let start = tick::from_beats(init_cursor.x);
                 // let note_length = music_scale_cursor.x - init_cursor.x;
                 let end = start + period;
 
@@ -1373,7 +1896,7 @@ impl Track {
                 let mut notes = MidiNotes::new();
                 let added_note = notes.add(&note);
 
-                let delta_time = music_scale_cursor.x - init_cursor.x;
+                let delta_time = tick::from_beats(music_scale_cursor.x - init_cursor.x);
 
                 return Some((
                     event::Status::Captured,
@@ -1589,6 +2112,33 @@ pub enum TrackMessage {
     AddManyNotes {
         notes: MidiNotes,
     },
+    // drains the selection, then adds the notes of a chord as a single undoable step
+    AddChord {
+        notes: Vec<MidiNote>,
+    },
+    SetVelocities {
+        changes: Vec<VelocityChange>,
+    },
+    // quantizes the selected notes, or the whole track if no note is selected
+    Quantize {
+        settings: QuantizeSettings,
+    },
+    // transposes the selected notes, or the whole track if no note is selected
+    Transpose {
+        interval: Interval,
+    },
+    // moves the selected notes that are out of a scale to its closest pitches
+    ConformToScale {
+        scale: Scale,
+    },
+    // a take recorded from midi input, covering the ticks from start to end
+    RecordTake {
+        notes: MidiNotes,
+        start: Tick,
+        end: Tick,
+        mode: RecordMode,
+        quantize: bool,
+    },
     CursorMoved,
     DeleteSelectedNotes,
     DeleteOne {
@@ -1608,7 +2158,7 @@ pub enum TrackMessage {
     },
 
     ResizedNotes {
-        delta_time: f32,
+        delta_time: Tick,
         original_notes: MidiNotes,
         resize_end: NoteEdge,
         on_index: NoteIndex,
@@ -1626,7 +2176,41 @@ pub enum TrackMessage {
     //     // keep_already_selected: bool,
     // },
     ModifiersChanged(Modifiers),
+    // switches between the chromatic scale and the scale of the track
     Toggle,
+    SetScale {
+        scale_type: ScaleType,
+        root: u8,
+    },
+    // sets the scale and moves the notes into it
+    ChangeKey {
+        scale_type: ScaleType,
+        root: u8,
+    },
+
+    // from the track header
+    SetChannel {
+        channel: u8,
+    },
+    SetProgram {
+        program: u8,
+    },
+    ToggleMute,
+    ToggleSolo,
+    ToggleArm,
+    ToggleStepInput,
+    // hears a pitch on the channel of the track, ex: from a key of the piano
+    Audition {
+        pitch: u8,
+    },
+    // selects every note of a pitch, ex: from a key of the piano
+    SelectPitch {
+        pitch: u8,
+    },
+
+    // from the computer keyboard, in step input
+    StepKeyPressed(keyboard::KeyCode),
+    StepKeyReleased(keyboard::KeyCode),
 
     Undo,
     Redo,
@@ -1735,6 +2319,27 @@ impl canvas::Program<TrackMessage, TrackTheme> for Track {
             }
         }
 
+        // step input takes the keys it uses before the shortcuts of the track and of the
+        // editor, which only get the keys that are not captured
+        if self.step_input.is_some() && self.is_active {
+            match event {
+                Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers })
+                    if !modifiers.command() && StepKey::from_key_code(key_code).is_some() =>
+                {
+                    return (event::Status::Captured, Some(TrackMessage::StepKeyPressed(key_code)));
+                }
+                Event::Keyboard(keyboard::Event::KeyReleased { key_code, .. })
+                    if StepKey::from_key_code(key_code).is_some() =>
+                {
+                    return (
+                        event::Status::Captured,
+                        Some(TrackMessage::StepKeyReleased(key_code)),
+                    );
+                }
+                _ => {}
+            }
+        }
+
         match event {
             Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
                 return (event::Status::Captured, Some(TrackMessage::ModifiersChanged(modifiers)));
@@ -1829,7 +2434,8 @@ impl canvas::Program<TrackMessage, TrackTheme> for Track {
         bounds: Rectangle,
         cursor: Cursor,
     ) -> Vec<Geometry> {
-        let background = self.grid.draw_background(bounds, &self.grid_cache, theme);
+        let background =
+            self.grid.draw_background(bounds, &self.grid_cache, &self.timing_info, theme);
         let text_overlay = self.grid.draw_text_and_hover_overlay(bounds, cursor, theme);
 
         let notes_overlay = self.midi_notes.draw_notes(
@@ -1857,8 +2463,12 @@ impl canvas::Program<TrackMessage, TrackTheme> for Track {
 
         let player_head = self.draw_player_head(bounds, &self.grid, &theme);
 
+        let loop_region =
+            self.grid.draw_loop_region(bounds, self.loop_region, self.is_looping, theme);
+
         vec![
             background,
+            loop_region,
             notes_overlay,
             selected_notes_elements,
             selecting_box,
@@ -1886,25 +2496,3 @@ impl canvas::Program<TrackMessage, TrackTheme> for Track {
         }
     }
 }
-
-#[derive(Debug, Copy, Clone)]
-pub struct TimingInfo {
-    #[allow(dead_code)]
-    pub bpm: f32,
-    _time_signature: (u8, u8),
-    _beat_per_measure: u8,
-    _beat_value: u8, // 4 for quarter note, 8 for eighth note, etc.
-    pub track_length: f32,
-}
-
-impl Default for TimingInfo {
-    fn default() -> Self {
-        Self {
-            bpm: 120.0,
-            _time_signature: (4, 4),
-            _beat_per_measure: 4,
-            _beat_value: 4,
-            track_length: 4.0,
-        }
-    }
-}
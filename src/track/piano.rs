@@ -0,0 +1,190 @@
+//! Piano keyboard
+//!
+//! Drawn on the left of the piano roll with the same pitch axis: there is one key per
+//! row of the grid, so the keys scroll and zoom with it and skip the pitches that the
+//! grid skips. The keys in the scale of the track are tinted, its root more strongly,
+//! and the keys of the notes under the playhead light up during playback.
+//!
+//! Clicking a key selects every note of its pitch, and shift-clicking hears it.
+
+use iced::widget::canvas::event::{self, Event};
+use iced::widget::canvas::{self, Canvas, Cursor, Frame, Geometry, Path, Stroke, Text};
+use iced::{alignment, mouse, Color, Length, Point, Rectangle, Size};
+
+use crate::config::{INIT_GRID_SIZE, PIANO_WIDTH};
+use crate::grid::IS_WHITE_KEY;
+use crate::note::scale::ScaleType;
+use crate::piano_theme::TrackTheme;
+use crate::track::{Track, TrackElement, TrackMessage};
+
+// the part of the width taken by a black key
+const BLACK_KEY_RATIO: f32 = 0.6;
+// labels are only drawn on keys at least this tall, in pixels
+const LABEL_MIN_HEIGHT: f32 = 9.0;
+
+pub struct PianoKeyboard<'a> {
+    track: &'a Track,
+}
+
+impl<'a> PianoKeyboard<'a> {
+    pub fn new(track: &'a Track) -> Self {
+        Self { track }
+    }
+
+    pub fn view(self) -> TrackElement<'a> {
+        Canvas::new(self)
+            .width(Length::Units(PIANO_WIDTH))
+            .height(Length::Units(INIT_GRID_SIZE.height as u16))
+            .into()
+    }
+
+    // the pitch of the key at a height in pixels, if there is one
+    fn pitch_at(&self, y: f32, height: f32) -> Option<u8> {
+        let row = self.track.grid.y_to_row(y, height).floor();
+        if row < 0.0 {
+            return None;
+        }
+        self.track.grid.scale.midi_range.get(row as usize).copied()
+    }
+
+    // the pitches of the notes under the playhead, while the transport plays
+    fn sounding_pitches(&self) -> [bool; 128] {
+        let mut sounding = [false; 128];
+        if !self.track.is_playing {
+            return sounding;
+        }
+
+        let player_head = self.track.player_head;
+        let notes =
+            self.track.midi_notes.notes.iter().chain(self.track.selected.notes.notes.iter());
+        for note in notes.flatten() {
+            if note.start <= player_head && player_head < note.end {
+                sounding[note.get_pitch() as usize] = true;
+            }
+        }
+        sounding
+    }
+}
+
+impl<'a> canvas::Program<TrackMessage, TrackTheme> for PianoKeyboard<'a> {
+    type State = ();
+
+    fn update(
+        &self,
+        _state: &mut (),
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<TrackMessage>) {
+        let cursor_position = match cursor.position_in(&bounds) {
+            Some(position) => position,
+            None => return (event::Status::Ignored, None),
+        };
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                match self.pitch_at(cursor_position.y, bounds.height) {
+                    Some(pitch) if self.track.modifiers.shift() => {
+                        (event::Status::Captured, Some(TrackMessage::Audition { pitch }))
+                    }
+                    Some(pitch) => {
+                        (event::Status::Captured, Some(TrackMessage::SelectPitch { pitch }))
+                    }
+                    None => (event::Status::Captured, None),
+                }
+            }
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &(),
+        theme: &TrackTheme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let size = frame.size();
+        let grid = &self.track.grid;
+        let scale = &grid.scale;
+
+        frame.fill_rectangle(Point::ORIGIN, size, theme.piano_background);
+
+        // every key is in the chromatic scale, so only its root stands out
+        let is_chromatic = matches!(scale.scale_type, ScaleType::Chromatic);
+        let base_notes = scale.get_root_base_notes();
+        let sounding = self.sounding_pitches();
+
+        // the rows from the bottom of the canvas to its top
+        let first_row = grid.y_to_row(size.height, size.height).floor().max(0.0) as usize;
+        let last_row = grid.y_to_row(0.0, size.height).ceil().max(0.0) as usize;
+
+        for (row, pitch) in scale.midi_range.iter().enumerate().take(last_row + 1).skip(first_row) {
+            let top = grid.row_to_y(row as f32 + 1.0, size.height);
+            let bottom = grid.row_to_y(row as f32, size.height);
+            let key_height = bottom - top;
+            let pitch_class = (*pitch as i16 - scale.root as i16).rem_euclid(12) as u8;
+
+            frame.fill_rectangle(
+                Point::new(0.0, top),
+                Size::new(size.width, key_height),
+                theme.piano_white_key,
+            );
+            if !IS_WHITE_KEY[*pitch as usize % 12] {
+                frame.fill_rectangle(
+                    Point::new(0.0, top),
+                    Size::new(size.width * BLACK_KEY_RATIO, key_height),
+                    theme.piano_black_key,
+                );
+            }
+
+            let tint = if pitch_class == 0 {
+                Some(theme.piano_root_key)
+            } else if !is_chromatic && base_notes.contains(&pitch_class) {
+                Some(theme.piano_scale_key)
+            } else {
+                None
+            };
+            if let Some(tint) = tint {
+                frame.fill_rectangle(
+                    Point::new(size.width * BLACK_KEY_RATIO, top),
+                    Size::new(size.width * (1.0 - BLACK_KEY_RATIO), key_height),
+                    tint,
+                );
+            }
+
+            if sounding[*pitch as usize] {
+                frame.fill_rectangle(
+                    Point::new(0.0, top),
+                    Size::new(size.width, key_height),
+                    Color { a: 0.7, ..theme.player_head },
+                );
+            }
+
+            frame.stroke(
+                &Path::line(Point::new(0.0, bottom), Point::new(size.width, bottom)),
+                Stroke::default().with_width(1.0).with_color(theme.piano_background),
+            );
+
+            if pitch % 12 == 0 && key_height >= LABEL_MIN_HEIGHT {
+                frame.fill_text(Text {
+                    content: scale.int_to_label(*pitch),
+                    color: theme.piano_black_key,
+                    size: key_height.min(12.0),
+                    position: Point::new(size.width - 2.0, top + key_height / 2.0),
+                    horizontal_alignment: alignment::Horizontal::Right,
+                    vertical_alignment: alignment::Vertical::Center,
+                    ..Text::default()
+                });
+            }
+        }
+
+        frame.stroke(
+            &Path::rectangle(Point::ORIGIN, size),
+            Stroke::default().with_width(1.0).with_color(theme.track_contour),
+        );
+
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,271 @@
+//! Step input
+//!
+//! Writes notes from the computer keyboard at the playhead, which serves as the step
+//! cursor. Three rows of letters play the degrees of the scale of the track, each row
+//! starting on the root: the bottom row in the current octave, and the two rows above
+//! it one and two octaves higher. Every note lasts one step, the beat fraction of the
+//! grid, and the cursor moves one step forward.
+//!
+//! Keys pressed together make a chord. Holding its keys makes the chord a step longer
+//! each time the keyboard repeats the key, and the cursor follows. The chord is written
+//! when every key is released, as a single undoable addition.
+//!
+//! The number keys 1 to 8 set the step from a whole note to a 128th note, 0 enters a
+//! rest, - and = change the octave, and escape leaves step input.
+
+use iced::keyboard::KeyCode;
+
+use crate::note::midi_notes::{MidiNote, Pitch};
+use crate::note::scale::Scale;
+use crate::tick::Tick;
+
+const NOTE_ROWS: [[KeyCode; 10]; 3] = [
+    [
+        KeyCode::Z,
+        KeyCode::X,
+        KeyCode::C,
+        KeyCode::V,
+        KeyCode::B,
+        KeyCode::N,
+        KeyCode::M,
+        KeyCode::Comma,
+        KeyCode::Period,
+        KeyCode::Slash,
+    ],
+    [
+        KeyCode::A,
+        KeyCode::S,
+        KeyCode::D,
+        KeyCode::F,
+        KeyCode::G,
+        KeyCode::H,
+        KeyCode::J,
+        KeyCode::K,
+        KeyCode::L,
+        KeyCode::Semicolon,
+    ],
+    [
+        KeyCode::Q,
+        KeyCode::W,
+        KeyCode::E,
+        KeyCode::R,
+        KeyCode::T,
+        KeyCode::Y,
+        KeyCode::U,
+        KeyCode::I,
+        KeyCode::O,
+        KeyCode::P,
+    ],
+];
+
+// the beat fractions of the number keys 1 to 8
+const STEP_KEYS: [(KeyCode, f32); 8] = [
+    (KeyCode::Key1, 4.0),
+    (KeyCode::Key2, 2.0),
+    (KeyCode::Key3, 1.0),
+    (KeyCode::Key4, 1.0 / 2.0),
+    (KeyCode::Key5, 1.0 / 4.0),
+    (KeyCode::Key6, 1.0 / 8.0),
+    (KeyCode::Key7, 1.0 / 16.0),
+    (KeyCode::Key8, 1.0 / 32.0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepKey {
+    // the row counts octaves above the current octave
+    Note { row: u8, degree: u8 },
+    Rest,
+    // sets the beat fraction of the grid
+    Step(f32),
+    Octave(i8),
+    Exit,
+}
+
+impl StepKey {
+    pub fn from_key_code(key_code: KeyCode) -> Option<Self> {
+        for (row, keys) in NOTE_ROWS.iter().enumerate() {
+            if let Some(degree) = keys.iter().position(|key| *key == key_code) {
+                return Some(StepKey::Note { row: row as u8, degree: degree as u8 });
+            }
+        }
+
+        if let Some((_, beat_fraction)) = STEP_KEYS.iter().find(|(key, _)| *key == key_code) {
+            return Some(StepKey::Step(*beat_fraction));
+        }
+
+        match key_code {
+            KeyCode::Key0 => Some(StepKey::Rest),
+            KeyCode::Minus => Some(StepKey::Octave(-1)),
+            KeyCode::Equals => Some(StepKey::Octave(1)),
+            KeyCode::Escape => Some(StepKey::Exit),
+            _ => None,
+        }
+    }
+}
+
+// the step as a note value, ex: 1/8 for half a beat
+pub fn step_label(beat_fraction: f32) -> String {
+    if beat_fraction > 4.0 {
+        format!("{}/1", beat_fraction / 4.0)
+    } else {
+        format!("1/{}", 4.0 / beat_fraction)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Chord {
+    start: Tick,
+    length: Tick,
+    pitches: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StepInput {
+    // the octave of the bottom row, with the octave numbers of the note labels
+    pub octave: i8,
+    // the note keys of the chord being entered that are still held down
+    held: Vec<KeyCode>,
+    chord: Option<Chord>,
+}
+
+impl Default for StepInput {
+    fn default() -> Self {
+        Self { octave: 3, held: Vec::new(), chord: None }
+    }
+}
+
+impl StepInput {
+    pub fn set_octave(&mut self, octave: i8) {
+        self.octave = octave.clamp(-2, 8);
+    }
+
+    // None above the highest midi note
+    pub fn pitch(&self, scale: &Scale, row: u8, degree: u8) -> Option<u8> {
+        let base_notes = scale.get_root_base_notes();
+        let degree = degree as usize;
+        let octave = self.octave as i16 + row as i16 + (degree / base_notes.len()) as i16;
+        let pitch =
+            (octave + 2) * 12 + scale.root as i16 + base_notes[degree % base_notes.len()] as i16;
+
+        if (0..128).contains(&pitch) {
+            Some(pitch as u8)
+        } else {
+            None
+        }
+    }
+
+    // returns how far the cursor moves: one step for the first key of a chord and for
+    // a key that repeats while held down, and nothing for the other keys of the chord
+    pub fn press_note(&mut self, key_code: KeyCode, pitch: u8, cursor: Tick, step: Tick) -> Tick {
+        if self.held.contains(&key_code) {
+            return match &mut self.chord {
+                Some(chord) => {
+                    chord.length += step;
+                    step
+                }
+                None => 0,
+            };
+        }
+        self.held.push(key_code);
+
+        match &mut self.chord {
+            Some(chord) => {
+                if !chord.pitches.contains(&pitch) {
+                    chord.pitches.push(pitch);
+                }
+                0
+            }
+            None => {
+                self.chord = Some(Chord { start: cursor, length: step, pitches: vec![pitch] });
+                step
+            }
+        }
+    }
+
+    // returns how far the cursor moves, which is not at all while a chord is held
+    pub fn rest(&mut self, step: Tick) -> Tick {
+        if self.chord.is_some() {
+            0
+        } else {
+            step
+        }
+    }
+
+    // the notes of the chord, once all of its keys are released
+    pub fn release(&mut self, key_code: KeyCode) -> Vec<MidiNote> {
+        self.held.retain(|key| *key != key_code);
+        if self.held.is_empty() {
+            self.finish()
+        } else {
+            Vec::new()
+        }
+    }
+
+    // the notes of the chord being entered, even if its keys are still held
+    pub fn finish(&mut self) -> Vec<MidiNote> {
+        self.held.clear();
+        match self.chord.take() {
+            Some(chord) => chord
+                .pitches
+                .iter()
+                .map(|pitch| {
+                    MidiNote::new(chord.start, chord.start + chord.length, Pitch::new(*pitch))
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Tick = 480;
+
+    fn spans(notes: &[MidiNote]) -> Vec<(u8, Tick, Tick)> {
+        let mut spans: Vec<_> =
+            notes.iter().map(|note| (note.get_pitch(), note.start, note.end)).collect();
+        spans.sort();
+        spans
+    }
+
+    #[test]
+    fn keys_pressed_together_make_a_chord_of_one_step() {
+        let mut step_input = StepInput::default();
+
+        assert_eq!(step_input.press_note(KeyCode::Z, 48, 960, STEP), STEP);
+        assert_eq!(step_input.press_note(KeyCode::C, 52, 960, STEP), 0);
+        assert!(step_input.release(KeyCode::Z).is_empty());
+        let notes = step_input.release(KeyCode::C);
+
+        assert_eq!(spans(&notes), [(48, 960, 960 + STEP), (52, 960, 960 + STEP)]);
+    }
+
+    #[test]
+    fn holding_a_chord_makes_it_a_step_longer_per_repeat() {
+        let mut step_input = StepInput::default();
+
+        let mut advance = step_input.press_note(KeyCode::Z, 48, 960, STEP);
+        advance += step_input.press_note(KeyCode::C, 52, 960, STEP);
+        // the keyboard repeats the last key pressed
+        advance += step_input.press_note(KeyCode::C, 52, 960 + STEP, STEP);
+        advance += step_input.press_note(KeyCode::C, 52, 960 + 2 * STEP, STEP);
+        let notes = [step_input.release(KeyCode::C), step_input.release(KeyCode::Z)].concat();
+
+        assert_eq!(advance, 3 * STEP);
+        assert_eq!(spans(&notes), [(48, 960, 960 + 3 * STEP), (52, 960, 960 + 3 * STEP)]);
+    }
+
+    #[test]
+    fn rests_move_the_cursor_only_between_chords() {
+        let mut step_input = StepInput::default();
+
+        assert_eq!(step_input.rest(STEP), STEP);
+        step_input.press_note(KeyCode::Z, 48, 960, STEP);
+        assert_eq!(step_input.rest(STEP), 0);
+        let notes = step_input.release(KeyCode::Z);
+
+        assert_eq!(spans(&notes), [(48, 960, 960 + STEP)]);
+    }
+}
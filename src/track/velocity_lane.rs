@@ -0,0 +1,193 @@
+//! Velocity lane
+//!
+//! Drawn under the piano roll with the same time axis. Every note is a stem whose
+//! height is its velocity. Clicking sets the velocity of the notes under the cursor,
+//! and dragging draws a ramp over the notes that start between the two ends.
+
+use iced::widget::canvas::event::{self, Event};
+use iced::widget::canvas::{self, Canvas, Cursor, Frame, Geometry, Path, Stroke};
+use iced::{mouse, Length, Point, Rectangle, Size};
+
+use crate::config::{DEFAULT_VELOCITY, NOTE_MIN_SIZE, VELOCITY_LANE_HEIGHT};
+use crate::note::midi_notes::{velocity_opacity, MidiNotes, NoteIndex, VelocityChange};
+use crate::piano_theme::TrackTheme;
+use crate::tick;
+use crate::track::{Track, TrackElement, TrackMessage};
+
+pub struct VelocityLane<'a> {
+    track: &'a Track,
+}
+
+// the ends of the ramp being drawn, as (beat, velocity)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VelocityLaneState {
+    ramp: Option<(Point, Point)>,
+}
+
+impl<'a> VelocityLane<'a> {
+    pub fn new(track: &'a Track) -> Self {
+        Self { track }
+    }
+
+    pub fn view(self) -> TrackElement<'a> {
+        Canvas::new(self).width(Length::Fill).height(Length::Units(VELOCITY_LANE_HEIGHT)).into()
+    }
+
+    fn to_lane_axes(&self, position: Point, size: Size) -> Point {
+        let beat = self.track.grid.x_to_beat(position.x, size.width);
+        let velocity = (1.0 - position.y / size.height).clamp(0.0, 1.0) * 127.0;
+        Point::new(beat, velocity)
+    }
+
+    // the selected notes are the only ones affected, unless nothing is selected
+    fn ramp_changes(&self, from: Point, to: Point) -> Vec<VelocityChange> {
+        let is_selected = !self.track.selected.notes.is_empty();
+        let notes = if is_selected { &self.track.selected.notes } else { &self.track.midi_notes };
+
+        let is_click = (to.x - from.x).abs() < NOTE_MIN_SIZE;
+        let (left, right) = if from.x <= to.x { (from, to) } else { (to, from) };
+
+        let mut changes = Vec::new();
+        for (pitch_index, notes_in_pitch) in notes.notes.iter().enumerate() {
+            for (time_index, note) in notes_in_pitch.iter().enumerate() {
+                let (start, end) = (tick::to_beats(note.start), tick::to_beats(note.end));
+                let velocity = if is_click {
+                    if start > from.x || end <= from.x {
+                        continue;
+                    }
+                    from.y
+                } else {
+                    if start < left.x || start > right.x {
+                        continue;
+                    }
+                    let ratio = (start - left.x) / (right.x - left.x);
+                    left.y + ratio * (right.y - left.y)
+                };
+
+                changes.push(VelocityChange {
+                    note_index: NoteIndex { pitch_index, time_index },
+                    is_selected,
+                    velocity: velocity.round().clamp(1.0, 127.0) as u8,
+                });
+            }
+        }
+        changes
+    }
+
+    fn draw_stems(&self, frame: &mut Frame, notes: &MidiNotes, color: iced::Color) {
+        let size = frame.size();
+
+        for note in notes.notes.iter().flatten() {
+            let x = self.track.grid.tick_to_x(note.start, size.width);
+            if x < 0.0 || x > size.width {
+                continue;
+            }
+            let y = size.height * (1.0 - note.velocity as f32 / 127.0);
+
+            let mut stem_color = color;
+            stem_color.a *= velocity_opacity(note.velocity);
+
+            frame.stroke(
+                &Path::line(Point::new(x, size.height), Point::new(x, y)),
+                Stroke::default().with_width(2.0).with_color(stem_color),
+            );
+            frame.fill(&Path::circle(Point::new(x, y), 3.0), stem_color);
+        }
+    }
+}
+
+impl<'a> canvas::Program<TrackMessage, TrackTheme> for VelocityLane<'a> {
+    type State = VelocityLaneState;
+
+    fn update(
+        &self,
+        state: &mut VelocityLaneState,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<TrackMessage>) {
+        let cursor_position = match cursor.position_in(&bounds) {
+            Some(position) => position,
+            // the ramp is kept when the cursor leaves the lane, and clamped to it
+            None => match (cursor.position(), state.ramp) {
+                (Some(position), Some(_)) => Point::new(
+                    (position.x - bounds.x).clamp(0.0, bounds.width),
+                    (position.y - bounds.y).clamp(0.0, bounds.height),
+                ),
+                _ => return (event::Status::Ignored, None),
+            },
+        };
+        let lane_cursor = self.to_lane_axes(cursor_position, bounds.size());
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                state.ramp = Some((lane_cursor, lane_cursor));
+                (event::Status::Captured, None)
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => match state.ramp {
+                Some((start, _)) => {
+                    state.ramp = Some((start, lane_cursor));
+                    (event::Status::Captured, None)
+                }
+                None => (event::Status::Ignored, None),
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                match state.ramp.take() {
+                    Some((start, _)) => {
+                        let changes = self.ramp_changes(start, lane_cursor);
+                        if changes.is_empty() {
+                            (event::Status::Captured, None)
+                        } else {
+                            (event::Status::Captured, Some(TrackMessage::SetVelocities { changes }))
+                        }
+                    }
+                    None => (event::Status::Ignored, None),
+                }
+            }
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        state: &VelocityLaneState,
+        theme: &TrackTheme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let size = frame.size();
+
+        frame.fill_rectangle(Point::ORIGIN, size, theme.grid_background);
+
+        // a line at the default velocity
+        let default_y = size.height * (1.0 - DEFAULT_VELOCITY as f32 / 127.0);
+        frame.stroke(
+            &Path::line(Point::new(0.0, default_y), Point::new(size.width, default_y)),
+            Stroke::default().with_width(1.0).with_color(theme.grid_row_line),
+        );
+
+        self.draw_stems(&mut frame, &self.track.midi_notes, theme.note);
+        self.draw_stems(&mut frame, &self.track.selected.notes, theme.selected_note);
+
+        if let Some((start, end)) = state.ramp {
+            let to_lane_point = |point: Point| {
+                Point::new(
+                    self.track.grid.beat_to_x(point.x, size.width),
+                    size.height * (1.0 - point.y / 127.0),
+                )
+            };
+            frame.stroke(
+                &Path::line(to_lane_point(start), to_lane_point(end)),
+                Stroke::default().with_width(1.5).with_color(theme.player_head),
+            );
+        }
+
+        frame.stroke(
+            &Path::rectangle(Point::ORIGIN, size),
+            Stroke::default().with_width(1.0).with_color(theme.track_contour),
+        );
+
+        vec![frame.into_geometry()]
+    }
+}
@@ -0,0 +1,177 @@
+//! Transport
+//!
+//! Play, pause, stop and return-to-start. While playing, the editor subscribes to a
+//! timer and the position is recomputed on every tick from the time elapsed since
+//! playback started, so that a late frame never makes the playhead drift. When a loop
+//! region is on, the playhead cycles over it.
+
+use iced::time::{Duration, Instant};
+
+use crate::smf::tempo_map::TempoMap;
+use crate::tick::{self, Tick, FIRST_TICK};
+use crate::timeline::LoopRegion;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TransportMessage {
+    Play,
+    Pause,
+    TogglePlay,
+    Stop,
+    ReturnToStart,
+    Seek(Tick),
+    // None removes the loop region
+    SetLoop(Option<LoopRegion>),
+    ToggleLoop,
+    Tick(Instant),
+}
+
+#[derive(Debug, Clone)]
+pub struct Transport {
+    pub state: TransportState,
+    // in ticks, like the notes
+    pub position: Tick,
+    // where the playhead goes back to when stopping
+    pub start_position: Tick,
+    // the instant and the position at which playback last (re)started
+    anchor: Option<(Instant, Tick)>,
+    pub loop_region: Option<LoopRegion>,
+    // the loop region is kept when looping is turned off
+    pub is_looping: bool,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new(FIRST_TICK)
+    }
+}
+
+impl Transport {
+    pub fn new(position: Tick) -> Self {
+        Self {
+            state: TransportState::Stopped,
+            position,
+            start_position: position,
+            anchor: None,
+            loop_region: None,
+            is_looping: false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == TransportState::Playing
+    }
+
+    // the playhead only starts moving after the delay, which is the count-in
+    pub fn play_after(&mut self, now: Instant, delay: Duration) {
+        if self.is_playing() {
+            return;
+        }
+        if self.state == TransportState::Stopped {
+            self.start_position = self.position;
+        }
+        self.anchor = Some((now + delay, self.position));
+        self.state = TransportState::Playing;
+    }
+
+    pub fn pause(&mut self) {
+        if self.is_playing() {
+            self.anchor = None;
+            self.state = TransportState::Paused;
+        }
+    }
+
+    // stops and brings the playhead back to where playback started
+    pub fn stop(&mut self) {
+        self.anchor = None;
+        self.state = TransportState::Stopped;
+        self.position = self.start_position;
+    }
+
+    pub fn return_to_start(&mut self, now: Instant) {
+        self.seek(FIRST_TICK, now);
+        self.start_position = FIRST_TICK;
+    }
+
+    // moves the playhead without interrupting playback
+    pub fn seek(&mut self, position: Tick, now: Instant) {
+        self.position = position.max(FIRST_TICK);
+        if self.is_playing() {
+            self.anchor = Some((now, self.position));
+        } else {
+            self.start_position = self.position;
+        }
+    }
+
+    // the loop region, when looping is on
+    pub fn active_loop(&self) -> Option<LoopRegion> {
+        self.loop_region.filter(|_| self.is_looping)
+    }
+
+    pub fn set_loop_region(&mut self, loop_region: Option<LoopRegion>) {
+        self.loop_region = loop_region;
+        self.is_looping = loop_region.is_some();
+    }
+
+    pub fn toggle_loop(&mut self) {
+        self.is_looping = !self.is_looping && self.loop_region.is_some();
+    }
+
+    // the position of the playhead at an instant of the current playback, ex: to place
+    // a note played on a midi keyboard. Before the playhead starts moving, it is where
+    // playback starts. None when not playing
+    pub fn position_at(&self, instant: Instant, tempo_map: &TempoMap) -> Option<Tick> {
+        let (anchor_instant, anchor_position) = self.anchor?;
+        let to_seconds = |tick: Tick| tempo_map.beats_to_seconds(tick::to_song_beats(tick));
+
+        let elapsed_seconds = instant.saturating_duration_since(anchor_instant).as_secs_f64();
+        let mut seconds = to_seconds(anchor_position) + elapsed_seconds;
+
+        if let Some(loop_region) = self.active_loop() {
+            let (start_seconds, end_seconds) =
+                (to_seconds(loop_region.start), to_seconds(loop_region.end));
+
+            if anchor_position < loop_region.end && seconds >= end_seconds {
+                seconds = start_seconds + (seconds - end_seconds) % (end_seconds - start_seconds);
+            }
+        }
+
+        Some(tick::from_song_beats(tempo_map.seconds_to_beats(seconds)))
+    }
+
+    // returns the new position of the playhead
+    // the tempo changes are walked in seconds, from the anchor to now
+    pub fn advance(&mut self, now: Instant, tempo_map: &TempoMap) -> Tick {
+        if let Some((anchor_instant, anchor_position)) = self.anchor {
+            let to_seconds = |tick: Tick| tempo_map.beats_to_seconds(tick::to_song_beats(tick));
+
+            let elapsed_seconds = now.saturating_duration_since(anchor_instant).as_secs_f64();
+            let mut seconds = to_seconds(anchor_position) + elapsed_seconds;
+
+            // playback wraps at the end of the loop, unless it started after the loop.
+            // The time spent past the end is replayed from the start, and the playhead
+            // is anchored again at the start of the loop
+            if let Some(loop_region) = self.active_loop() {
+                let (start_seconds, end_seconds) =
+                    (to_seconds(loop_region.start), to_seconds(loop_region.end));
+
+                if anchor_position < loop_region.end && seconds >= end_seconds {
+                    let overshoot = (seconds - end_seconds) % (end_seconds - start_seconds);
+                    let anchor_instant =
+                        now.checked_sub(Duration::from_secs_f64(overshoot)).unwrap_or(now);
+                    self.anchor = Some((anchor_instant, loop_region.start));
+                    seconds = start_seconds + overshoot;
+                }
+            }
+
+            self.position = tick::from_song_beats(tempo_map.seconds_to_beats(seconds));
+        }
+        self.position
+    }
+}
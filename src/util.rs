@@ -1,4 +1,6 @@
 use crate::note::midi_notes::MidiNotes;
+use crate::tick::Tick;
+use crate::timeline::TimingInfo;
 
 // use std::collections::HashMap;
 // The only actions that matter are the ones that change the main MidiNotes
@@ -6,12 +8,27 @@ use crate::note::midi_notes::MidiNotes;
 
 pub type TrackId = u32;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct History {
     pub action_sequence: Vec<Action>,
     pub head_position: usize,
     pub current_size: usize,
     pub is_dummy: bool,
+    // the head position when the project was last saved or opened, or None once the
+    // actions that led there are overwritten
+    saved_position: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            action_sequence: Vec::new(),
+            head_position: 0,
+            current_size: 0,
+            is_dummy: false,
+            saved_position: Some(0),
+        }
+    }
 }
 
 impl History {
@@ -36,22 +53,74 @@ impl History {
         action
     }
 
-    pub fn add_action_from_track(&mut self, track_id: TrackId) {
+    pub fn add_action(&mut self, action: Action) {
+        if self.saved_position.map_or(false, |position| position > self.head_position) {
+            self.saved_position = None;
+        }
         self.action_sequence.truncate(self.head_position);
-        self.action_sequence.push(Action::FromTrackId(track_id));
+        self.action_sequence.push(action);
         self.head_position += 1;
     }
+
+    pub fn add_action_from_track(&mut self, track_id: TrackId) {
+        self.add_action(Action::FromTrackId(track_id));
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_position = Some(self.head_position);
+    }
+
+    // undoing or redoing back to where the project was saved leaves nothing unsaved
+    pub fn has_unsaved_actions(&self) -> bool {
+        self.saved_position != Some(self.head_position)
+    }
 }
 
+// The actions on the notes are kept in the history of each track, and the editor only
+// remembers which track to forward the undo to. The actions on the tracks themselves
+// are undone by the editor.
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum Action {
     FromTrackId(TrackId),
+    // a new or a duplicated track, inserted at index in the track order
+    AddTrack { track_id: TrackId, index: usize },
+    RemoveTrack { track_id: TrackId, index: usize },
+    RenameTrack { track_id: TrackId, previous_name: String, name: String },
+    MoveTrack { track_id: TrackId, from: usize, to: usize },
+    // a change of the tempo track or of the meter track
+    ChangeTiming { previous: TimingInfo, timing_info: TimingInfo },
     None,
 }
 
 #[allow(dead_code)]
 pub enum ClipBoard {
-    Notes { notes: MidiNotes, player_head: f32 },
+    Notes { notes: MidiNotes, player_head: Tick },
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_are_unsaved_until_undone_or_saved() {
+        let mut history = History::default();
+        assert!(!history.has_unsaved_actions());
+
+        history.add_action_from_track(0);
+        assert!(history.has_unsaved_actions());
+        history.undo();
+        assert!(!history.has_unsaved_actions());
+        history.redo();
+        history.mark_saved();
+        assert!(!history.has_unsaved_actions());
+
+        // the saved actions can not come back once they are overwritten
+        history.undo();
+        history.add_action_from_track(1);
+        history.undo();
+        assert_eq!(history.head_position, 0);
+        assert!(history.has_unsaved_actions());
+    }
+}
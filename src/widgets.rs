@@ -0,0 +1,36 @@
+//! Widgets
+//!
+//! The small controls that the rows of the editor and the track headers have in common,
+//! drawn with the theme of the editor whatever message they send.
+
+use iced::alignment;
+use iced::widget::{button, text, Row};
+use iced::{Alignment, Length};
+
+use crate::piano_theme::TrackTheme;
+
+pub type Element<'a, Message> = iced::Element<'a, Message, iced::Renderer<TrackTheme>>;
+
+// a narrow button with a centered label, such as "-" or ">>"
+pub fn step_button<'a, Message: Clone + 'a>(
+    label: &'static str,
+    message: Message,
+) -> Element<'a, Message> {
+    button(text(label).horizontal_alignment(alignment::Horizontal::Center))
+        .padding(5)
+        .width(Length::Units(30))
+        .on_press(message)
+        .into()
+}
+
+// a label between the button that steps its value down and the one that steps it up
+pub fn stepper<'a, Message: Clone + 'a>(
+    label: impl Into<Element<'a, Message>>,
+    decreased: Message,
+    increased: Message,
+) -> Element<'a, Message> {
+    Row::with_children(vec![step_button("-", decreased), label.into(), step_button("+", increased)])
+        .spacing(5)
+        .align_items(Alignment::Center)
+        .into()
+}